@@ -3,11 +3,17 @@ use std::path::Path;
 use std::sync::{Arc, Mutex};
 use crate::smp::Smp;
 use crate::timer::Timer;
-use crate::dsp::dsp::Dsp;
+use crate::dsp::dsp::{Dsp, NUM_VOICES};
 use crate::script700::runtime::Runtime;
 use spc_spcp::spc::{Spc, RAM_LEN, IPL_ROM_LEN};
 use crate::blargg_spc_filter::BlarggSpcFilter;
+use crate::save_state::{SaveState, StateReader, StateWriter};
 use crate::ResamplingMode;
+use crate::disassembler::{Instruction, disassemble};
+use crate::trace::TraceSink;
+use crate::debugger::{Debugger, BreakEvent, WatchKind};
+use crate::dsp::envelope::EnvelopePhase;
+use crate::register_log::{RegisterLog, RegisterLogRecorder, RegisterWrite};
 
 #[derive(Copy, Clone, Default, Debug)]
 pub struct ApuChannelState {
@@ -22,7 +28,19 @@ pub struct ApuChannelState {
     pub kon_frames: usize,
     pub sample_block_index: usize,
     pub echo_delay: Option<u8>,
-    pub pitch_modulation: bool
+    pub pitch_modulation: bool,
+    /// ADSR1 and ADSR2 register values.
+    pub adsr: (u8, u8),
+    pub gain: u8,
+    pub envelope_phase: EnvelopePhase,
+    /// The voice was keyed on since the last state was reported.
+    pub key_on: bool,
+    /// KOF released the voice since the last state was reported.
+    pub key_off: bool,
+    /// The voice's bit in ENDX.
+    pub endx: bool,
+    /// The BRR stream has jumped back to the sample's loop point since key-on.
+    pub in_loop: bool
 }
 
 #[derive(Copy, Clone, Default, Debug)]
@@ -81,6 +99,12 @@ pub trait ApuStateReceiver {
     }
 }
 
+const DEFAULT_TEST_REG: u8 = 0x0a;
+// SMP cycles per access for each TEST register wait state setting
+const WAIT_STATE_CYCLES: [i32; 4] = [1, 2, 5, 10];
+// The timers have their own, shorter, wait states at the two slowest settings
+const TIMER_WAIT_STATE_CYCLES: [i32; 4] = [1, 2, 4, 8];
+
 static DEFAULT_IPL_ROM: [u8; IPL_ROM_LEN] = [
     0xcd, 0xef, 0xbd, 0xe8, 0x00, 0xc6, 0x1d, 0xd0,
     0xfc, 0x8f, 0xaa, 0xf4, 0x8f, 0xbb, 0xf5, 0x78,
@@ -107,9 +131,12 @@ pub struct Apu {
 
     is_ipl_rom_enabled: bool,
     dsp_reg_address: u8,
+    test_reg: u8,
 
     output_filter: BlarggSpcFilter,
-    output_filter_enabled: bool
+    output_filter_enabled: bool,
+
+    pub(crate) debugger: Debugger
 }
 
 impl Apu {
@@ -129,9 +156,12 @@ impl Apu {
 
             is_ipl_rom_enabled: true,
             dsp_reg_address: 0,
+            test_reg: DEFAULT_TEST_REG,
 
             output_filter: BlarggSpcFilter::default(),
-            output_filter_enabled: true
+            output_filter_enabled: true,
+
+            debugger: Debugger::default()
         });
         let ret_ptr = &mut *ret as *mut _;
         ret.smp = Some(Box::new(Smp::new(ret_ptr)));
@@ -169,6 +199,9 @@ impl Apu {
         self.timer1.set_target(self.ram[0xfb]);
         self.timer2.set_target(self.ram[0xfc]);
 
+        // Dumps rarely hold a meaningful TEST value, so start from the power-on state
+        self.test_reg = DEFAULT_TEST_REG;
+
         let control_reg = self.ram[0xf1];
         self.set_control_reg(control_reg);
 
@@ -181,7 +214,54 @@ impl Apu {
 
         self.script700_runtime.as_mut().unwrap().reset();
         self.output_filter.clear();
-        self.dsp.as_mut().unwrap().output_buffer.clear();
+        let dsp = self.dsp.as_mut().unwrap();
+        dsp.output_buffer.clear();
+        for buffer in dsp.voice_output_buffers.iter_mut() {
+            buffer.clear();
+        }
+    }
+
+    /// Exports the current state as an untagged SPC snapshot. The SPC format cannot hold DSP
+    /// internals such as envelopes or BRR decoding positions, so voices that are sounding when
+    /// the snapshot is taken stay silent until they are keyed on again.
+    pub fn to_spc(&mut self) -> Spc {
+        let regs = self.dsp.as_mut().unwrap().get_registers();
+
+        let mut ram = [0u8; RAM_LEN];
+        ram.copy_from_slice(&self.ram[..]);
+
+        ram[0xf0] = self.test_reg;
+        ram[0xf1] = ((self.is_ipl_rom_enabled as u8) << 7)
+            | ((self.timer2.enabled() as u8) << 2)
+            | ((self.timer1.enabled() as u8) << 1)
+            | (self.timer0.enabled() as u8);
+        ram[0xf2] = self.dsp_reg_address;
+        ram[0xf3] = regs[self.dsp_reg_address as usize];
+        ram[0xfa] = self.timer0.target();
+        ram[0xfb] = self.timer1.target();
+        ram[0xfc] = self.timer2.target();
+        ram[0xfd] = self.timer0.counter();
+        ram[0xfe] = self.timer1.counter();
+        ram[0xff] = self.timer2.counter();
+
+        let mut ipl_rom = [0u8; IPL_ROM_LEN];
+        ipl_rom.copy_from_slice(&self.ipl_rom);
+
+        let smp = self.smp.as_ref().unwrap();
+        Spc {
+            version_minor: 30,
+            pc: smp.reg_pc,
+            a: smp.reg_a,
+            x: smp.reg_x,
+            y: smp.reg_y,
+            psw: smp.get_psw(),
+            sp: smp.reg_sp,
+            id666_tag: None,
+            extended_id666: None,
+            ram,
+            regs,
+            ipl_rom
+        }
     }
 
     pub fn from_spc(spc: &Spc) -> Box<Apu> {
@@ -198,24 +278,227 @@ impl Apu {
         self.script700_runtime.as_mut().unwrap().load_script(script_path)
     }
 
-    pub fn render(&mut self, left_buffer: &mut [i16], right_buffer: &mut [i16], num_samples: usize) {
+    /// Captures the complete emulator state. The Script700 program itself is not included, so
+    /// the same script must be loaded before the state is restored.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut writer = StateWriter::new();
+
+        writer.write_bytes(&self.ram[..]);
+        writer.write_bytes(&self.ipl_rom);
+        writer.write(&self.output_ports);
+
+        self.timer0.save_state(&mut writer);
+        self.timer1.save_state(&mut writer);
+        self.timer2.save_state(&mut writer);
+
+        writer.write(&self.is_ipl_rom_enabled);
+        writer.write(&self.dsp_reg_address);
+        writer.write(&self.test_reg);
+        self.output_filter.save_state(&mut writer);
+
+        self.smp.as_ref().unwrap().save_state(&mut writer);
+        self.dsp.as_ref().unwrap().save_state(&mut writer);
+        self.script700_runtime.as_ref().unwrap().save_state(&mut writer);
+
+        writer.into_inner()
+    }
+
+    /// Restores a state from `save_state`. Invalid data leaves the APU untouched.
+    pub fn load_state(&mut self, state: &[u8]) -> io::Result<()> {
+        // Loading writes straight into each component, so check the whole state against a
+        // scratch APU before touching this one
+        Apu::new().read_state(state)?;
+        self.read_state(state)
+    }
+
+    fn read_state(&mut self, state: &[u8]) -> io::Result<()> {
+        let mut reader = StateReader::new(state)?;
+
+        reader.read_bytes(&mut self.ram[..])?;
+        reader.read_bytes(&mut self.ipl_rom)?;
+        self.output_ports = reader.read()?;
+
+        self.timer0.load_state(&mut reader)?;
+        self.timer1.load_state(&mut reader)?;
+        self.timer2.load_state(&mut reader)?;
+
+        self.is_ipl_rom_enabled = reader.read()?;
+        self.dsp_reg_address = reader.read()?;
+        self.test_reg = reader.read()?;
+        self.output_filter.load_state(&mut reader)?;
+
+        self.smp.as_mut().unwrap().load_state(&mut reader)?;
+        self.dsp.as_mut().unwrap().load_state(&mut reader)?;
+        self.script700_runtime.as_mut().unwrap().load_state(&mut reader)?;
+
+        reader.finish()
+    }
+
+    /// Renders `num_samples` samples, or fewer if the debugger breaks first.
+    pub fn render(&mut self, left_buffer: &mut [i16], right_buffer: &mut [i16], num_samples: usize) -> Option<BreakEvent> {
         let smp = self.smp.as_mut().unwrap();
         let dsp = self.dsp.as_mut().unwrap();
+        let mut break_reason = None;
         while dsp.output_buffer.get_sample_count() < num_samples {
             smp.run(64);
+            if let Some(reason) = self.debugger.take_break() {
+                break_reason = Some(reason);
+                break;
+            }
         }
 
-        dsp.output_buffer.read(left_buffer, right_buffer, num_samples);
+        let samples_rendered = dsp.output_buffer.get_sample_count().min(num_samples);
+        dsp.output_buffer.read(left_buffer, right_buffer, samples_rendered);
+        self.filter_output(&mut left_buffer[..samples_rendered], &mut right_buffer[..samples_rendered]);
+
+        break_reason.map(|reason| BreakEvent {
+            reason,
+            pc: self.smp.as_ref().unwrap().reg_pc,
+            samples_rendered
+        })
+    }
+
+    pub(crate) fn filter_output(&mut self, left_buffer: &mut [i16], right_buffer: &mut [i16]) {
         if self.output_filter_enabled {
             self.output_filter.run(left_buffer, 0);
             self.output_filter.run(right_buffer, 1);
         }
     }
 
-    pub fn cpu_cycles_callback(&mut self, num_cycles: i32) {
-        self.timer0.cpu_cycles_callback(num_cycles);
-        self.timer1.cpu_cycles_callback(num_cycles);
-        self.timer2.cpu_cycles_callback(num_cycles);
+    /// Starts recording DSP register and I/O port writes, discarding any log in progress.
+    pub fn start_register_log(&mut self) {
+        let state = self.save_state();
+        self.dsp.as_mut().unwrap().register_log = Some(RegisterLogRecorder::new(state));
+    }
+
+    /// Stops recording and returns the captured log, if one was started.
+    pub fn stop_register_log(&mut self) -> Option<RegisterLog> {
+        self.dsp.as_mut().unwrap().register_log.take().map(|recorder| recorder.finish())
+    }
+
+    /// Executes a single SMP instruction. Returns `None` if the SMP is stopped.
+    pub fn step(&mut self) -> Option<BreakEvent> {
+        self.debugger.request_step();
+        while !self.smp.as_ref().unwrap().is_stopped() {
+            self.smp.as_mut().unwrap().run(64);
+            if let Some(reason) = self.debugger.take_break() {
+                return Some(BreakEvent {
+                    reason,
+                    pc: self.smp.as_ref().unwrap().reg_pc,
+                    samples_rendered: 0
+                });
+            }
+        }
+        None
+    }
+
+    pub fn debugger(&self) -> &Debugger {
+        &self.debugger
+    }
+
+    pub fn debugger_mut(&mut self) -> &mut Debugger {
+        &mut self.debugger
+    }
+
+    pub(crate) fn check_watchpoints(&mut self, address: u16, value: u8, kind: WatchKind) {
+        self.debugger.check_ram_access(address, value, kind);
+        if address == 0xf3 {
+            self.debugger.check_dsp_access(self.dsp_reg_address, value, kind);
+        }
+    }
+
+    /// Runs the emulator for `num_samples` samples and throws the output away.
+    pub fn skip(&mut self, num_samples: usize) {
+        let mut remaining = num_samples;
+        loop {
+            let dsp = self.dsp.as_mut().unwrap();
+            let skipped = dsp.output_buffer.get_sample_count().min(remaining);
+            dsp.output_buffer.skip(skipped);
+            for buffer in dsp.voice_output_buffers.iter_mut() {
+                buffer.skip(skipped);
+            }
+
+            remaining -= skipped;
+            if remaining == 0 {
+                break;
+            }
+
+            self.smp.as_mut().unwrap().run(64);
+            // Breaks are only reported by `render` and `step`
+            self.debugger.take_break();
+        }
+
+        self.output_filter.clear();
+    }
+
+    /// Like `skip`, but without notifying the state receiver.
+    pub fn fast_forward(&mut self, num_samples: usize) {
+        let smp_state_receiver = self.smp.as_mut().unwrap().state_receiver.take();
+        let dsp_state_receiver = self.dsp.as_mut().unwrap().state_receiver.take();
+        let script700_state_receiver = self.script700_runtime.as_mut().unwrap().state_receiver.take();
+
+        self.skip(num_samples);
+
+        self.smp.as_mut().unwrap().state_receiver = smp_state_receiver;
+        self.dsp.as_mut().unwrap().state_receiver = dsp_state_receiver;
+        self.script700_runtime.as_mut().unwrap().state_receiver = script700_state_receiver;
+    }
+
+    /// Silences voices in the mix. Bit N of each mask refers to voice N; when any voice is soloed,
+    /// only soloed voices are heard. Channel states are still reported for silenced voices.
+    pub fn set_voice_mask(&mut self, muted: u8, solo: u8) {
+        self.dsp.as_mut().unwrap().set_voice_mask(muted, solo);
+    }
+
+    pub fn voice_mask(&self) -> (u8, u8) {
+        self.dsp.as_ref().unwrap().voice_mask()
+    }
+
+    pub fn set_voice_muted(&mut self, voice: usize, muted: bool) {
+        assert!(voice < NUM_VOICES, "Invalid voice index {}", voice);
+        let (muted_mask, solo_mask) = self.voice_mask();
+        let muted_mask = if muted { muted_mask | (1 << voice) } else { muted_mask & !(1 << voice) };
+        self.set_voice_mask(muted_mask, solo_mask);
+    }
+
+    pub fn is_voice_muted(&self, voice: usize) -> bool {
+        assert!(voice < NUM_VOICES, "Invalid voice index {}", voice);
+        self.dsp.as_ref().unwrap().voices[voice].is_muted
+    }
+
+    pub fn set_voice_solo(&mut self, voice: usize, solo: bool) {
+        assert!(voice < NUM_VOICES, "Invalid voice index {}", voice);
+        let (muted_mask, solo_mask) = self.voice_mask();
+        let solo_mask = if solo { solo_mask | (1 << voice) } else { solo_mask & !(1 << voice) };
+        self.set_voice_mask(muted_mask, solo_mask);
+    }
+
+    pub fn is_voice_solo(&self, voice: usize) -> bool {
+        assert!(voice < NUM_VOICES, "Invalid voice index {}", voice);
+        self.dsp.as_ref().unwrap().voices[voice].is_solod
+    }
+
+    pub fn set_voice_output_enabled(&mut self, enabled: bool) {
+        self.dsp.as_mut().unwrap().set_voice_output_enabled(enabled);
+    }
+
+    pub fn read_voice_output(&mut self, voice: usize, left_buffer: &mut [i16], right_buffer: &mut [i16], num_samples: usize) {
+        let dsp = self.dsp.as_mut().unwrap();
+        assert!(voice < NUM_VOICES, "Invalid voice index {}", voice);
+        dsp.voice_output_buffers[voice].read(left_buffer, right_buffer, num_samples);
+    }
+
+    pub fn read_echo_output(&mut self, left_buffer: &mut [i16], right_buffer: &mut [i16], num_samples: usize) {
+        let dsp = self.dsp.as_mut().unwrap();
+        dsp.voice_output_buffers[NUM_VOICES].read(left_buffer, right_buffer, num_samples);
+    }
+
+    /// `timer_cycles` is `num_cycles` with the timers' own wait states in place of the SMP's.
+    pub fn cpu_cycles_callback(&mut self, num_cycles: i32, timer_cycles: i32) {
+        let timers_enabled = self.timers_enabled();
+        self.timer0.cpu_cycles_callback(timer_cycles, timers_enabled);
+        self.timer1.cpu_cycles_callback(timer_cycles, timers_enabled);
+        self.timer2.cpu_cycles_callback(timer_cycles, timers_enabled);
         self.dsp.as_mut().unwrap().cycles_callback(num_cycles);
         self.script700_runtime.as_mut().unwrap().cycles_callback(num_cycles);
     }
@@ -248,6 +531,23 @@ impl Apu {
         }
     }
 
+    /// Reads memory as the SMP would see it, without triggering any I/O side effects.
+    pub fn peek_u8(&self, address: u16) -> u8 {
+        if address >= 0xffc0 && self.is_ipl_rom_enabled {
+            self.ipl_rom[(address - 0xffc0) as usize]
+        } else {
+            self.ram[address as usize]
+        }
+    }
+
+    pub fn disassemble(&self, address: u16) -> Instruction {
+        disassemble(address, |address| self.peek_u8(address))
+    }
+
+    pub fn set_trace_sink(&mut self, trace_sink: Option<Box<dyn TraceSink>>) {
+        self.smp.as_mut().unwrap().trace_sink = trace_sink;
+    }
+
     pub fn write_u8(&mut self, address: u32, value: u8) {
         let address = address & 0xffff;
         if address >= 0x00f0 && address < 0x0100 {
@@ -258,10 +558,12 @@ impl Apu {
                 0xf3 => { self.dsp.as_mut().unwrap().set_register(self.dsp_reg_address, value); },
 
                 0xf4 ..= 0xf7 => {
+                    let port = (address - 0xf4) as u8;
+                    self.dsp.as_mut().unwrap().record_register_write(RegisterWrite::OutputPort { port, value });
                     self.output_ports[(address - 0xf4) as usize] = value;
                     self.script700_runtime.as_mut().unwrap().trigger_port_event(true, (address - 0xf4) as u8);
                 },
-                0xf8 ..= 0xf9 => { self.ram[address as usize] = value; },
+                0xf8 ..= 0xf9 if self.is_ram_writable() => { self.ram[address as usize] = value; },
 
                 0xfa => { self.timer0.set_target(value); },
                 0xfb => { self.timer1.set_target(value); },
@@ -269,7 +571,7 @@ impl Apu {
 
                 _ => () // Do nothing
             }
-        } else {
+        } else if self.is_ram_writable() {
             self.ram[address as usize] = value;
         }
     }
@@ -291,15 +593,51 @@ impl Apu {
     }
 
     fn set_test_reg(&mut self, value: u8) {
-        if value != 0x0A {
-            let pc = self.smp.as_ref().unwrap().reg_pc;
-            let ir = self.read_u8(pc as u32);
-            panic!("Test reg not implemented (pc=${:04x}, ir=${:02x}, echo_start=${:04x}, value=${:02x})", pc, ir, self.dsp.as_ref().unwrap().get_echo_start_address(), value);
+        let smp = self.smp.as_ref().unwrap();
+        // TEST can only be written while the P flag is clear
+        if (smp.get_psw() & 0x20) != 0 {
+            return;
         }
 
-        self.timer0.synchronize_stage1();
-        self.timer1.synchronize_stage1();
-        self.timer2.synchronize_stage1();
+        if (value & 0x04) != 0 {
+            println!("[APU] Ignoring unsupported TEST register bit 2 (pc=${:04x}, value=${:02x})", smp.reg_pc, value);
+        }
+        self.test_reg = value;
+
+        let timers_enabled = self.timers_enabled();
+        self.timer0.synchronize_stage1(timers_enabled);
+        self.timer1.synchronize_stage1(timers_enabled);
+        self.timer2.synchronize_stage1(timers_enabled);
+    }
+
+    pub fn test_reg(&self) -> u8 {
+        self.test_reg
+    }
+
+    // Bit 3 enables the timers, bit 0 halts them
+    fn timers_enabled(&self) -> bool {
+        (self.test_reg & 0x09) == 0x08
+    }
+
+    fn is_ram_writable(&self) -> bool {
+        (self.test_reg & 0x02) != 0
+    }
+
+    /// SMP and timer cycles taken by an SMP memory access. Bits 4-5 of TEST set the wait
+    /// states for RAM, bits 6-7 for I/O registers and the IPL ROM.
+    pub(crate) fn access_cycles(&self, address: u16) -> (i32, i32) {
+        let is_internal = (address & 0xfff0) == 0x00f0 || (address >= 0xffc0 && self.is_ipl_rom_enabled);
+        let wait_states = match is_internal {
+            true => self.test_reg >> 6,
+            false => (self.test_reg >> 4) & 0x03
+        };
+        (WAIT_STATE_CYCLES[wait_states as usize], TIMER_WAIT_STATE_CYCLES[wait_states as usize])
+    }
+
+    /// SMP and timer cycles taken by an internal cycle.
+    pub(crate) fn idle_cycles(&self) -> (i32, i32) {
+        let wait_states = self.test_reg >> 6;
+        (WAIT_STATE_CYCLES[wait_states as usize], TIMER_WAIT_STATE_CYCLES[wait_states as usize])
     }
 
     fn set_control_reg(&mut self, value: u8) {
@@ -339,6 +677,7 @@ impl Apu {
 
     pub fn write_to_input_port(&mut self, port: usize, value: u8) {
         debug_assert!(port < 4);
+        self.dsp.as_mut().unwrap().record_register_write(RegisterWrite::InputPort { port: port as u8, value });
         self.ram[0xf4 + port] = value;
     }
 }
@@ -1,5 +1,7 @@
 // Port of SPC_Filter.cpp from blargg's snes_spc
 use super::dsp::dsp_helpers;
+use std::io;
+use crate::save_state::{SaveState, StateReader, StateWriter};
 
 pub const GAIN_UNIT: i32 = 0x100;
 const GAIN_BITS: u8 = 8;
@@ -84,3 +86,18 @@ impl Default for BlarggSpcFilter {
         Self::new(GAIN_UNIT, BASS_NORM)
     }
 }
+
+impl SaveState for BlarggSpcFilter {
+    fn save_state(&self, writer: &mut StateWriter) {
+        for c in self.ch.iter() {
+            writer.write(&[c.p1, c.pp1, c.sum]);
+        }
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> io::Result<()> {
+        for c in self.ch.iter_mut() {
+            [c.p1, c.pp1, c.sum] = reader.read()?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,162 @@
+use std::collections::BTreeSet;
+use std::ops::RangeInclusive;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    ReadWrite
+}
+
+impl WatchKind {
+    fn matches(&self, kind: WatchKind) -> bool {
+        *self == WatchKind::ReadWrite || *self == kind
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BreakReason {
+    Breakpoint(u16),
+    Step,
+    RamRead { address: u16, value: u8 },
+    RamWrite { address: u16, value: u8 },
+    DspRead { register: u8, value: u8 },
+    DspWrite { register: u8, value: u8 }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct BreakEvent {
+    pub reason: BreakReason,
+    /// Address of the next instruction to be executed.
+    pub pc: u16,
+    /// Number of samples written to the output buffers before the break.
+    pub samples_rendered: usize
+}
+
+/// Execution breakpoints, memory/DSP watchpoints and single-stepping for the SMP.
+/// Breakpoints stop before the instruction executes, watchpoints and steps stop after it completes.
+#[derive(Default)]
+pub struct Debugger {
+    breakpoints: BTreeSet<u16>,
+    ram_watchpoints: Vec<(RangeInclusive<u16>, WatchKind)>,
+    dsp_watchpoints: Vec<(RangeInclusive<u8>, WatchKind)>,
+    step: bool,
+    active: bool,
+
+    resume_address: Option<u16>,
+    pending_break: Option<BreakReason>
+}
+
+impl Debugger {
+    pub fn add_breakpoint(&mut self, address: u16) {
+        self.breakpoints.insert(address);
+        self.update_active();
+    }
+
+    pub fn remove_breakpoint(&mut self, address: u16) {
+        self.breakpoints.remove(&address);
+        self.update_active();
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    pub fn add_ram_watchpoint(&mut self, range: RangeInclusive<u16>, kind: WatchKind) {
+        self.ram_watchpoints.push((range, kind));
+        self.update_active();
+    }
+
+    pub fn add_dsp_watchpoint(&mut self, range: RangeInclusive<u8>, kind: WatchKind) {
+        self.dsp_watchpoints.push((range, kind));
+        self.update_active();
+    }
+
+    pub fn clear(&mut self) {
+        self.breakpoints.clear();
+        self.ram_watchpoints.clear();
+        self.dsp_watchpoints.clear();
+        self.step = false;
+        self.resume_address = None;
+        self.pending_break = None;
+        self.update_active();
+    }
+
+    fn update_active(&mut self) {
+        self.active = self.step
+            || !self.breakpoints.is_empty()
+            || !self.ram_watchpoints.is_empty()
+            || !self.dsp_watchpoints.is_empty();
+    }
+
+    pub(crate) fn is_active(&self) -> bool {
+        self.active
+    }
+
+    pub(crate) fn request_step(&mut self) {
+        self.step = true;
+        self.update_active();
+    }
+
+    /// Returns true if execution should stop before the instruction at `pc`.
+    pub(crate) fn check_execute(&mut self, pc: u16) -> bool {
+        if self.pending_break.is_some() {
+            return true;
+        }
+
+        if self.breakpoints.contains(&pc) && self.resume_address != Some(pc) {
+            self.resume_address = Some(pc);
+            self.pending_break = Some(BreakReason::Breakpoint(pc));
+            return true;
+        }
+
+        self.resume_address = None;
+        false
+    }
+
+    /// Returns true if execution should stop after the instruction that just completed.
+    pub(crate) fn check_instruction_complete(&mut self) -> bool {
+        if self.step {
+            self.step = false;
+            self.update_active();
+            self.pending_break.get_or_insert(BreakReason::Step);
+        }
+        self.pending_break.is_some()
+    }
+
+    pub(crate) fn check_ram_access(&mut self, address: u16, value: u8, kind: WatchKind) {
+        if self.pending_break.is_some() {
+            return;
+        }
+
+        let hit = self.ram_watchpoints
+            .iter()
+            .any(|(range, watch_kind)| watch_kind.matches(kind) && range.contains(&address));
+        if hit {
+            self.pending_break = Some(match kind {
+                WatchKind::Write => BreakReason::RamWrite { address, value },
+                _ => BreakReason::RamRead { address, value }
+            });
+        }
+    }
+
+    pub(crate) fn check_dsp_access(&mut self, register: u8, value: u8, kind: WatchKind) {
+        if self.pending_break.is_some() {
+            return;
+        }
+
+        let hit = self.dsp_watchpoints
+            .iter()
+            .any(|(range, watch_kind)| watch_kind.matches(kind) && range.contains(&register));
+        if hit {
+            self.pending_break = Some(match kind {
+                WatchKind::Write => BreakReason::DspWrite { register, value },
+                _ => BreakReason::DspRead { register, value }
+            });
+        }
+    }
+
+    pub(crate) fn take_break(&mut self) -> Option<BreakReason> {
+        self.pending_break.take()
+    }
+}
@@ -0,0 +1,178 @@
+use std::fmt;
+
+// Operand placeholders:
+//   {d1}, {d2}: direct page address in operand byte 1/2
+//   {i1}: immediate in operand byte 1
+//   {r1}, {r2}: relative branch target in operand byte 1/2
+//   {a}: absolute address in operand bytes 1-2
+//   {m}: absolute address with bit index in operand bytes 1-2
+//   {u1}: upper page address ($ffxx) in operand byte 1
+static OPCODE_TABLE: [&str; 256] = [
+    // 0x00
+    "nop", "tcall 0", "set1 {d1}.0", "bbs {d1}.0, {r2}",
+    "or a, {d1}", "or a, {a}", "or a, (x)", "or a, [{d1}+x]",
+    "or a, {i1}", "or {d2}, {d1}", "or1 c, {m}", "asl {d1}",
+    "asl {a}", "push psw", "tset1 {a}", "brk",
+    // 0x10
+    "bpl {r1}", "tcall 1", "clr1 {d1}.0", "bbc {d1}.0, {r2}",
+    "or a, {d1}+x", "or a, {a}+x", "or a, {a}+y", "or a, [{d1}]+y",
+    "or {d2}, {i1}", "or (x), (y)", "decw {d1}", "asl {d1}+x",
+    "asl a", "dec x", "cmp x, {a}", "jmp [{a}+x]",
+    // 0x20
+    "clrp", "tcall 2", "set1 {d1}.1", "bbs {d1}.1, {r2}",
+    "and a, {d1}", "and a, {a}", "and a, (x)", "and a, [{d1}+x]",
+    "and a, {i1}", "and {d2}, {d1}", "or1 c, /{m}", "rol {d1}",
+    "rol {a}", "push a", "cbne {d1}, {r2}", "bra {r1}",
+    // 0x30
+    "bmi {r1}", "tcall 3", "clr1 {d1}.1", "bbc {d1}.1, {r2}",
+    "and a, {d1}+x", "and a, {a}+x", "and a, {a}+y", "and a, [{d1}]+y",
+    "and {d2}, {i1}", "and (x), (y)", "incw {d1}", "rol {d1}+x",
+    "rol a", "inc x", "cmp x, {d1}", "call {a}",
+    // 0x40
+    "setp", "tcall 4", "set1 {d1}.2", "bbs {d1}.2, {r2}",
+    "eor a, {d1}", "eor a, {a}", "eor a, (x)", "eor a, [{d1}+x]",
+    "eor a, {i1}", "eor {d2}, {d1}", "and1 c, {m}", "lsr {d1}",
+    "lsr {a}", "push x", "tclr1 {a}", "pcall {u1}",
+    // 0x50
+    "bvc {r1}", "tcall 5", "clr1 {d1}.2", "bbc {d1}.2, {r2}",
+    "eor a, {d1}+x", "eor a, {a}+x", "eor a, {a}+y", "eor a, [{d1}]+y",
+    "eor {d2}, {i1}", "eor (x), (y)", "cmpw ya, {d1}", "lsr {d1}+x",
+    "lsr a", "mov x, a", "cmp y, {a}", "jmp {a}",
+    // 0x60
+    "clrc", "tcall 6", "set1 {d1}.3", "bbs {d1}.3, {r2}",
+    "cmp a, {d1}", "cmp a, {a}", "cmp a, (x)", "cmp a, [{d1}+x]",
+    "cmp a, {i1}", "cmp {d2}, {d1}", "and1 c, /{m}", "ror {d1}",
+    "ror {a}", "push y", "dbnz {d1}, {r2}", "ret",
+    // 0x70
+    "bvs {r1}", "tcall 7", "clr1 {d1}.3", "bbc {d1}.3, {r2}",
+    "cmp a, {d1}+x", "cmp a, {a}+x", "cmp a, {a}+y", "cmp a, [{d1}]+y",
+    "cmp {d2}, {i1}", "cmp (x), (y)", "addw ya, {d1}", "ror {d1}+x",
+    "ror a", "mov a, x", "cmp y, {d1}", "reti",
+    // 0x80
+    "setc", "tcall 8", "set1 {d1}.4", "bbs {d1}.4, {r2}",
+    "adc a, {d1}", "adc a, {a}", "adc a, (x)", "adc a, [{d1}+x]",
+    "adc a, {i1}", "adc {d2}, {d1}", "eor1 c, {m}", "dec {d1}",
+    "dec {a}", "mov y, {i1}", "pop psw", "mov {d2}, {i1}",
+    // 0x90
+    "bcc {r1}", "tcall 9", "clr1 {d1}.4", "bbc {d1}.4, {r2}",
+    "adc a, {d1}+x", "adc a, {a}+x", "adc a, {a}+y", "adc a, [{d1}]+y",
+    "adc {d2}, {i1}", "adc (x), (y)", "subw ya, {d1}", "dec {d1}+x",
+    "dec a", "mov x, sp", "div ya, x", "xcn a",
+    // 0xa0
+    "ei", "tcall 10", "set1 {d1}.5", "bbs {d1}.5, {r2}",
+    "sbc a, {d1}", "sbc a, {a}", "sbc a, (x)", "sbc a, [{d1}+x]",
+    "sbc a, {i1}", "sbc {d2}, {d1}", "mov1 c, {m}", "inc {d1}",
+    "inc {a}", "cmp y, {i1}", "pop a", "mov (x)+, a",
+    // 0xb0
+    "bcs {r1}", "tcall 11", "clr1 {d1}.5", "bbc {d1}.5, {r2}",
+    "sbc a, {d1}+x", "sbc a, {a}+x", "sbc a, {a}+y", "sbc a, [{d1}]+y",
+    "sbc {d2}, {i1}", "sbc (x), (y)", "movw ya, {d1}", "inc {d1}+x",
+    "inc a", "mov sp, x", "das a", "mov a, (x)+",
+    // 0xc0
+    "di", "tcall 12", "set1 {d1}.6", "bbs {d1}.6, {r2}",
+    "mov {d1}, a", "mov {a}, a", "mov (x), a", "mov [{d1}+x], a",
+    "cmp x, {i1}", "mov {a}, x", "mov1 {m}, c", "mov {d1}, y",
+    "mov {a}, y", "mov x, {i1}", "pop x", "mul ya",
+    // 0xd0
+    "bne {r1}", "tcall 13", "clr1 {d1}.6", "bbc {d1}.6, {r2}",
+    "mov {d1}+x, a", "mov {a}+x, a", "mov {a}+y, a", "mov [{d1}]+y, a",
+    "mov {d1}, x", "mov {d1}+y, x", "movw {d1}, ya", "mov {d1}+x, y",
+    "dec y", "mov a, y", "cbne {d1}+x, {r2}", "daa a",
+    // 0xe0
+    "clrv", "tcall 14", "set1 {d1}.7", "bbs {d1}.7, {r2}",
+    "mov a, {d1}", "mov a, {a}", "mov a, (x)", "mov a, [{d1}+x]",
+    "mov a, {i1}", "mov x, {a}", "not1 {m}", "mov y, {d1}",
+    "mov y, {a}", "notc", "pop y", "sleep",
+    // 0xf0
+    "beq {r1}", "tcall 15", "clr1 {d1}.7", "bbc {d1}.7, {r2}",
+    "mov a, {d1}+x", "mov a, {a}+x", "mov a, {a}+y", "mov a, [{d1}]+y",
+    "mov x, {d1}", "mov x, {d1}+y", "mov {d2}, {d1}", "mov y, {d1}+x",
+    "inc y", "mov y, a", "dbnz y, {r1}", "stop"
+];
+
+/// A single decoded SPC700 instruction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instruction {
+    pub address: u16,
+    pub opcode: u8,
+    pub operands: [u8; 2],
+    pub length: u16,
+    pub text: String
+}
+
+impl Instruction {
+    pub fn bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![self.opcode];
+        bytes.extend_from_slice(&self.operands[..(self.length as usize - 1)]);
+        bytes
+    }
+
+    pub fn next_address(&self) -> u16 {
+        self.address.wrapping_add(self.length)
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+fn instruction_length(template: &str) -> u16 {
+    if template.contains("{a}") || template.contains("{m}") || template.contains("{d2}") || template.contains("{r2}") {
+        3
+    } else if template.contains("{d1}") || template.contains("{i1}") || template.contains("{r1}") || template.contains("{u1}") {
+        2
+    } else {
+        1
+    }
+}
+
+/// Decodes the instruction at `address`, reading memory through `read`.
+pub fn disassemble<F: FnMut(u16) -> u8>(address: u16, mut read: F) -> Instruction {
+    let opcode = read(address);
+    let template = OPCODE_TABLE[opcode as usize];
+    let length = instruction_length(template);
+
+    let mut operands = [0u8; 2];
+    for (i, operand) in operands.iter_mut().enumerate().take(length as usize - 1) {
+        *operand = read(address.wrapping_add(1 + i as u16));
+    }
+
+    let next_address = address.wrapping_add(length);
+    let branch_target = |offset: u8| next_address.wrapping_add(offset as i8 as u16);
+    let absolute = ((operands[1] as u16) << 8) | (operands[0] as u16);
+
+    let text = template
+        .replace("{d1}", &format!("${:02x}", operands[0]))
+        .replace("{d2}", &format!("${:02x}", operands[1]))
+        .replace("{i1}", &format!("#${:02x}", operands[0]))
+        .replace("{r1}", &format!("${:04x}", branch_target(operands[0])))
+        .replace("{r2}", &format!("${:04x}", branch_target(operands[1])))
+        .replace("{a}", &format!("!${:04x}", absolute))
+        .replace("{m}", &format!("${:04x}.{}", absolute & 0x1fff, absolute >> 13))
+        .replace("{u1}", &format!("$ff{:02x}", operands[0]));
+
+    Instruction {
+        address,
+        opcode,
+        operands,
+        length,
+        text
+    }
+}
+
+/// Disassembles a block of code starting at `address`.
+pub fn disassemble_range(data: &[u8], address: u16) -> Vec<Instruction> {
+    let mut instructions = Vec::new();
+    let mut offset = 0usize;
+    while offset < data.len() {
+        let instruction = disassemble(address.wrapping_add(offset as u16), |read_address| {
+            let index = read_address.wrapping_sub(address) as usize;
+            data.get(index).copied().unwrap_or(0)
+        });
+        offset += instruction.length as usize;
+        instructions.push(instruction);
+    }
+    instructions
+}
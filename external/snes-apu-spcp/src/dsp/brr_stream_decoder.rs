@@ -1,3 +1,5 @@
+use std::io;
+use crate::save_state::{SaveState, StateReader, StateWriter};
 use super::dsp_helpers;
 
 pub struct BrrStreamDecoder {
@@ -108,3 +110,26 @@ impl BrrStreamDecoder {
         self.sample_index >= 16
     }
 }
+
+impl SaveState for BrrStreamDecoder {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write(&self.is_end);
+        writer.write(&self.is_looping);
+        writer.write(&[self.filter, self.shift, self.first_byte]);
+        writer.write(&self.samples);
+        writer.write(&self.decode_pos);
+        writer.write(&self.sample_index);
+        writer.write(&[self.last_sample, self.last_last_sample]);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> io::Result<()> {
+        self.is_end = reader.read()?;
+        self.is_looping = reader.read()?;
+        [self.filter, self.shift, self.first_byte] = reader.read()?;
+        self.samples = reader.read()?;
+        self.decode_pos = reader.read()?;
+        self.sample_index = reader.read()?;
+        [self.last_sample, self.last_last_sample] = reader.read()?;
+        Ok(())
+    }
+}
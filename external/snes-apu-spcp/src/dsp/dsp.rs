@@ -1,5 +1,8 @@
+use std::io;
 use std::sync::{Arc, Mutex};
 use crate::apu::{Apu, ApuChannelState, ApuMasterState, ApuStateReceiver};
+use crate::save_state::{SaveState, StateReader, StateWriter};
+use crate::register_log::{RegisterLogRecorder, RegisterWrite};
 use super::voice::{Voice, ResamplingMode};
 use super::ring_buffer::RingBuffer;
 use super::stereo::Stereo;
@@ -8,7 +11,7 @@ use spc_spcp::spc::{Spc, REG_LEN};
 pub const SAMPLE_RATE: usize = 32000;
 pub const BUFFER_LEN: usize = SAMPLE_RATE * 2;
 
-const NUM_VOICES: usize = 8;
+pub const NUM_VOICES: usize = 8;
 
 const COUNTER_RANGE: i32 = 30720;
 static COUNTER_RATES: [i32; 32] = [
@@ -26,6 +29,7 @@ pub struct Dsp {
     pub voices: Vec<Box<Voice>>,
 
     pub output_buffer: RingBuffer<BUFFER_LEN>,
+    pub voice_output_buffers: Vec<RingBuffer<BUFFER_LEN>>,
 
     registers: [u8; 0x80],
 
@@ -55,6 +59,7 @@ pub struct Dsp {
 
     pub(super) master_reset: bool,
     pub(super) master_mute: bool,
+    pub(super) solo_active: bool,
     pub(super) master_output: Stereo<i32>,
     pub(super) echo_input: Stereo<i32>,
     pub(super) echo_output: Stereo<i32>,
@@ -68,7 +73,8 @@ pub struct Dsp {
 
     resampling_mode: ResamplingMode,
 
-    pub state_receiver: Option<Arc<Mutex<dyn ApuStateReceiver>>>
+    pub state_receiver: Option<Arc<Mutex<dyn ApuStateReceiver>>>,
+    pub(crate) register_log: Option<RegisterLogRecorder>
 }
 
 impl Dsp {
@@ -79,6 +85,7 @@ impl Dsp {
             voices: Vec::with_capacity(NUM_VOICES),
 
             output_buffer: RingBuffer::new(),
+            voice_output_buffers: Vec::new(),
 
             registers: [0u8; 0x80],
 
@@ -108,6 +115,7 @@ impl Dsp {
 
             master_reset: true,
             master_mute: true,
+            solo_active: false,
             master_output: Stereo::default(),
             echo_input: Stereo::default(),
             echo_output: Stereo::default(),
@@ -121,7 +129,8 @@ impl Dsp {
 
             resampling_mode: ResamplingMode::Accurate,
 
-            state_receiver: None
+            state_receiver: None,
+            register_log: None
         });
         let ret_ptr = &mut *ret as *mut _;
         for _ in 0..NUM_VOICES {
@@ -148,16 +157,42 @@ impl Dsp {
         }
     }
 
+    pub fn set_voice_mask(&mut self, muted: u8, solo: u8) {
+        for (i, voice) in self.voices.iter_mut().enumerate() {
+            voice.is_muted = (muted & (1 << i)) != 0;
+            voice.is_solod = (solo & (1 << i)) != 0;
+        }
+        self.solo_active = solo != 0;
+    }
+
+    pub fn voice_mask(&self) -> (u8, u8) {
+        self.voices.iter().enumerate().fold((0, 0), |(muted, solo), (i, voice)| {
+            (muted | ((voice.is_muted as u8) << i), solo | ((voice.is_solod as u8) << i))
+        })
+    }
+
+    pub fn set_voice_output_enabled(&mut self, enabled: bool) {
+        self.voice_output_buffers.clear();
+        if enabled {
+            // One buffer per voice, plus one for the echo return
+            self.voice_output_buffers.extend((0..=NUM_VOICES).map(|_| RingBuffer::new()));
+        }
+    }
+
     pub fn set_state(&mut self, spc: &Spc) {
+        self.set_registers(&spc.regs);
+    }
+
+    pub(crate) fn set_registers(&mut self, regs: &[u8; REG_LEN]) {
         for i in 0..REG_LEN {
             match i {
                 0x4c | 0x5c => (), // Don't key on/off anything yet
-                _ => { self.set_register(i as u8, spc.regs[i]); }
+                _ => { self.set_register(i as u8, regs[i]); }
             }
         }
 
-        self.set_kon(spc.regs[0x4c]);
-        self.set_kof(spc.regs[0x5c]);
+        self.set_kon(regs[0x4c]);
+        self.set_kof(regs[0x5c]);
 
         // Tick some latches now
         self.source_dir = self.l_source_dir;
@@ -168,9 +203,26 @@ impl Dsp {
         }
     }
 
+    pub fn get_registers(&mut self) -> [u8; REG_LEN] {
+        let mut result = [0u8; REG_LEN];
+        for (address, value) in result.iter_mut().enumerate() {
+            *value = match address {
+                // Voices that are already playing must not be keyed on again when loaded
+                0x4c => 0,
+                _ => self.get_register(address as u8)
+            };
+        }
+        result
+    }
+
     pub fn cycles_callback(&mut self, num_cycles: i32) {
         for _ in 0..num_cycles {
             self.cycle_count = (self.cycle_count + 1) % 32;
+            if self.cycle_count == 0 {
+                if let Some(register_log) = self.register_log.as_mut() {
+                    register_log.next_sample();
+                }
+            }
 
             match self.cycle_count {
                 0 => {
@@ -338,7 +390,7 @@ impl Dsp {
 
                 let state = ApuChannelState {
                     source: voice.source,
-                    muted: voice.is_muted,
+                    muted: voice.is_muted || (self.solo_active && !voice.is_solod),
                     envelope_level: voice.envelope.level,
                     volume: (*voice.volume.left() as i8, *voice.volume.right() as i8),
                     amplitude: (voice.amplitude.into_inner_left(), voice.amplitude.into_inner_right()),
@@ -348,7 +400,14 @@ impl Dsp {
                     kon_frames: voice.get_sample_frame(),
                     sample_block_index: voice.sample_block_index,
                     echo_delay: (self.echo_write_enabled && voice.echo_on).then_some(self.echo_delay),
-                    pitch_modulation: voice.pitch_mod
+                    pitch_modulation: voice.pitch_mod,
+                    adsr: (voice.envelope.l_adsr0, voice.envelope.adsr1),
+                    gain: voice.envelope.gain,
+                    envelope_phase: voice.envelope.phase(),
+                    key_on: voice.key_on_detected(),
+                    key_off: voice.key_off_detected(),
+                    endx: voice.get_endx_bit(),
+                    in_loop: voice.in_loop()
                 };
 
                 self.state_receiver
@@ -380,6 +439,13 @@ impl Dsp {
                 .lock()
                 .unwrap()
                 .receive_master(state);
+        } else {
+            // Key events are reported per sample, so drop them while nothing is listening
+            // instead of reporting them all once a receiver is attached
+            for voice in self.voices.iter_mut() {
+                voice.key_on_detected();
+                voice.key_off_detected();
+            }
         }
     }
 
@@ -391,7 +457,19 @@ impl Dsp {
         (self.echo_delay as i32) * 0x800
     }
 
+    pub(crate) fn cycle_count(&self) -> u8 {
+        self.cycle_count as u8
+    }
+
+    pub(crate) fn record_register_write(&mut self, write: RegisterWrite) {
+        let cycle = self.cycle_count();
+        if let Some(register_log) = self.register_log.as_mut() {
+            register_log.record(cycle, write);
+        }
+    }
+
     pub fn set_register(&mut self, address: u8, value: u8) {
+        self.record_register_write(RegisterWrite::Dsp { register: address, value });
         self.registers[address as usize] = value;
 
         let voice_index = address >> 4;
@@ -607,3 +685,88 @@ impl Dsp {
         result
     }
 }
+
+impl SaveState for Dsp {
+    fn save_state(&self, writer: &mut StateWriter) {
+        for voice in self.voices.iter() {
+            voice.save_state(writer);
+        }
+
+        self.output_buffer.save_state(writer);
+        writer.write(&self.voice_output_buffers.len());
+        for buffer in self.voice_output_buffers.iter() {
+            buffer.save_state(writer);
+        }
+
+        writer.write(&self.registers);
+
+        writer.write(&self.master_volume);
+        writer.write(&self.echo_volume);
+        writer.write(&[self.noise_clock, self.echo_feedback, self.source_dir, self.l_source_dir, self.echo_delay, self.kon_cache, self.kof_cache]);
+        writer.write(&[self.echo_write_enabled, self.l_echo_write_enabled]);
+        writer.write(&[self.echo_start_address, self.l_echo_start_address, self.echo_address]);
+
+        writer.write(&[self.counter, self.cycle_count, self.echo_pos, self.echo_length]);
+        writer.write(&self.fir);
+        writer.write(&self.echo_history);
+        writer.write(&self.echo_history_offset);
+
+        writer.write(&[self.master_reset, self.master_mute, self.every_other_sample]);
+        writer.write(&self.master_output);
+        writer.write(&self.echo_input);
+        writer.write(&self.echo_output);
+        writer.write(&self.noise);
+        writer.write(&[self.l_adsr0, self.l_envx, self.l_outx]);
+        writer.write(&[self.l_pitch, self.l_output]);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> io::Result<()> {
+        for voice in self.voices.iter_mut() {
+            voice.load_state(reader)?;
+        }
+
+        self.output_buffer.load_state(reader)?;
+
+        // Voice output is configuration rather than state, so keep the buffers in sync with
+        // the main output buffer even if the saved state was taken with it disabled.
+        let saved_buffer_count: usize = reader.read()?;
+        let mut saved_buffers = Vec::with_capacity(saved_buffer_count);
+        for _ in 0..saved_buffer_count {
+            let mut buffer = RingBuffer::<BUFFER_LEN>::new();
+            buffer.load_state(reader)?;
+            saved_buffers.push(buffer);
+        }
+        if saved_buffers.len() == self.voice_output_buffers.len() {
+            self.voice_output_buffers = saved_buffers;
+        } else {
+            for buffer in self.voice_output_buffers.iter_mut() {
+                buffer.clear();
+                for _ in 0..self.output_buffer.get_sample_count() {
+                    buffer.write_sample(0, 0);
+                }
+            }
+        }
+
+        self.registers = reader.read()?;
+
+        self.master_volume = reader.read()?;
+        self.echo_volume = reader.read()?;
+        [self.noise_clock, self.echo_feedback, self.source_dir, self.l_source_dir, self.echo_delay, self.kon_cache, self.kof_cache] = reader.read()?;
+        [self.echo_write_enabled, self.l_echo_write_enabled] = reader.read()?;
+        [self.echo_start_address, self.l_echo_start_address, self.echo_address] = reader.read()?;
+
+        [self.counter, self.cycle_count, self.echo_pos, self.echo_length] = reader.read()?;
+        self.fir = reader.read()?;
+        self.echo_history = reader.read()?;
+        self.echo_history_offset = reader.read()?;
+
+        [self.master_reset, self.master_mute, self.every_other_sample] = reader.read()?;
+        self.master_output = reader.read()?;
+        self.echo_input = reader.read()?;
+        self.echo_output = reader.read()?;
+        self.noise = reader.read()?;
+        [self.l_adsr0, self.l_envx, self.l_outx] = reader.read()?;
+        [self.l_pitch, self.l_output] = reader.read()?;
+        Ok(())
+    }
+}
@@ -1,4 +1,4 @@
-use crate::dsp::dsp::Dsp;
+use crate::dsp::dsp::{Dsp, NUM_VOICES};
 use crate::dsp::dsp_helpers;
 use crate::dsp::stereo::StereoChannel;
 
@@ -103,6 +103,35 @@ impl Dsp {
         } else {
             self.output_buffer.write_sample(0, 0);
         }
+
+        if !self.voice_output_buffers.is_empty() {
+            self.write_voice_outputs();
+        }
+    }
+
+    fn write_voice_outputs(&mut self) {
+        for channel in 0..NUM_VOICES {
+            let amplitude = self.voices[channel].amplitude;
+            let (l, r) = if self.master_mute {
+                (0, 0)
+            } else {
+                (
+                    dsp_helpers::clamp(dsp_helpers::multiply_volume(amplitude.into_inner_left(), *self.master_volume.left())),
+                    dsp_helpers::clamp(dsp_helpers::multiply_volume(amplitude.into_inner_right(), *self.master_volume.right()))
+                )
+            };
+            self.voice_output_buffers[channel].write_sample(l as i16, r as i16);
+        }
+
+        let (l, r) = if self.master_mute {
+            (0, 0)
+        } else {
+            (
+                dsp_helpers::clamp(dsp_helpers::multiply_volume(self.echo_input.into_inner_left(), *self.echo_volume.left())),
+                dsp_helpers::clamp(dsp_helpers::multiply_volume(self.echo_input.into_inner_right(), *self.echo_volume.right()))
+            )
+        };
+        self.voice_output_buffers[NUM_VOICES].write_sample(l as i16, r as i16);
     }
 
     pub(super) fn echo28(&mut self) {
@@ -1,3 +1,5 @@
+use std::io;
+use crate::save_state::{SaveState, StateReader, StateWriter};
 use super::dsp::Dsp;
 
 enum Mode {
@@ -7,6 +9,26 @@ enum Mode {
     Release
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GainMode {
+    Direct,
+    LinearDecrease,
+    ExponentialDecrease,
+    LinearIncrease,
+    BentIncrease
+}
+
+/// The envelope phase as seen by the DSP. Voices in GAIN mode stay in `Gain` until released.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum EnvelopePhase {
+    Attack,
+    Decay,
+    Sustain,
+    #[default]
+    Release,
+    Gain(GainMode)
+}
+
 pub struct Envelope {
     dsp: *mut Dsp,
 
@@ -49,6 +71,26 @@ impl Envelope {
         self.mode = Mode::Release;
     }
 
+    pub fn is_released(&self) -> bool {
+        matches!(self.mode, Mode::Release)
+    }
+
+    pub fn phase(&self) -> EnvelopePhase {
+        match self.mode {
+            Mode::Release => EnvelopePhase::Release,
+            _ if (self.l_adsr0 & 0x80) == 0 => EnvelopePhase::Gain(match self.gain >> 5 {
+                0..=3 => GainMode::Direct,
+                4 => GainMode::LinearDecrease,
+                5 => GainMode::ExponentialDecrease,
+                6 => GainMode::LinearIncrease,
+                _ => GainMode::BentIncrease
+            }),
+            Mode::Attack => EnvelopePhase::Attack,
+            Mode::Decay => EnvelopePhase::Decay,
+            Mode::Sustain => EnvelopePhase::Sustain
+        }
+    }
+
     pub fn reset_level(&mut self) {
         self.level = 0;
         self.hidden_level = 0;
@@ -132,3 +174,32 @@ impl Envelope {
         self.level = env;
     }
 }
+
+impl SaveState for Envelope {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write(&[self.l_adsr0, self.adsr1, self.gain]);
+        let mode: u8 = match self.mode {
+            Mode::Attack => 0,
+            Mode::Decay => 1,
+            Mode::Sustain => 2,
+            Mode::Release => 3
+        };
+        writer.write(&mode);
+        writer.write(&self.level);
+        writer.write(&self.hidden_level);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> io::Result<()> {
+        [self.l_adsr0, self.adsr1, self.gain] = reader.read()?;
+        self.mode = match reader.read::<u8>()? {
+            0 => Mode::Attack,
+            1 => Mode::Decay,
+            2 => Mode::Sustain,
+            3 => Mode::Release,
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid envelope mode in save state"))
+        };
+        self.level = reader.read()?;
+        self.hidden_level = reader.read()?;
+        Ok(())
+    }
+}
@@ -1,10 +1,10 @@
 pub(crate) mod dsp_helpers;
-mod envelope;
+pub mod envelope;
 mod interpolation_tables;
 pub mod voice;
 mod ring_buffer;
 pub mod dsp;
 mod brr_stream_decoder;
-mod stereo;
+pub(crate) mod stereo;
 mod echo;
 mod misc;
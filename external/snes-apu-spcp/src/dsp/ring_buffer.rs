@@ -1,3 +1,6 @@
+use std::io;
+use crate::save_state::{SaveState, StateReader, StateWriter};
+
 pub struct RingBuffer<const N: usize> {
     left_buffer: Box<[i16; N]>,
     right_buffer: Box<[i16; N]>,
@@ -43,6 +46,13 @@ impl<const N: usize> RingBuffer<N> {
         self.sample_count -= num_samples;
     }
 
+    pub fn skip(&mut self, num_samples: usize) {
+        debug_assert!(num_samples <= self.sample_count);
+
+        self.read_pos = (self.read_pos + num_samples) % N;
+        self.sample_count -= num_samples;
+    }
+
     pub fn get_sample_count(&self) -> usize {
         self.sample_count
     }
@@ -53,3 +63,23 @@ impl<const N: usize> RingBuffer<N> {
         self.sample_count = 0;
     }
 }
+
+impl<const N: usize> SaveState for RingBuffer<N> {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write(&self.sample_count);
+        for i in 0..self.sample_count {
+            let pos = (self.read_pos + i) % N;
+            writer.write(&[self.left_buffer[pos], self.right_buffer[pos]]);
+        }
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> io::Result<()> {
+        self.clear();
+        let sample_count: usize = reader.read()?;
+        for _ in 0..sample_count {
+            let [left, right] = reader.read()?;
+            self.write_sample(left, right);
+        }
+        Ok(())
+    }
+}
@@ -1,4 +1,6 @@
+use std::io;
 use crate::dsp::stereo::{Stereo, StereoChannel};
+use crate::save_state::{SaveState, StateReader, StateWriter};
 use super::brr_stream_decoder::BrrStreamDecoder;
 use super::dsp::Dsp;
 use super::super::apu::Apu;
@@ -50,6 +52,9 @@ pub struct Voice {
     endx_bit: bool,
     l_endx_bit: bool,
     looped: bool,
+    in_loop: bool,
+    kon_event: bool,
+    kof_event: bool,
     pub outx_value: u8,
     pub envx_value: u8,
     l_envx_value: u8,
@@ -103,6 +108,9 @@ impl Voice {
             endx_bit: false,
             l_endx_bit: false,
             looped: false,
+            in_loop: false,
+            kon_event: false,
+            kof_event: false,
             outx_value: 0,
             envx_value: 0,
             l_envx_value: 0,
@@ -138,7 +146,11 @@ impl Voice {
     }
 
     fn output(&mut self, channel: StereoChannel) {
-        let amp = dsp_helpers::multiply_volume(self.dsp().l_output, self.volume.into_inner(channel));
+        let amp = if self.is_muted || (self.dsp().solo_active && !self.is_solod) {
+            0
+        } else {
+            dsp_helpers::multiply_volume(self.dsp().l_output, self.volume.into_inner(channel))
+        };
 
         let master = dsp_helpers::clamp(dsp_helpers::cast_arb_int(self.dsp().master_output.into_inner(channel) + amp, 17));
         self.dsp().master_output.set(channel, master);
@@ -192,6 +204,7 @@ impl Voice {
                 self.resample_buffer_pos = 0;
                 self.sample_block_index = 0;
                 self.sample_frame = 0;
+                self.in_loop = false;
                 self.edge_hit = true;
                 self.brr_decoder.read_header(0);
                 self.brr_decoder.reset();
@@ -252,11 +265,13 @@ impl Voice {
 
         if self.dsp().every_other_sample {
             if self.kof {
+                self.kof_event |= !self.envelope.is_released();
                 self.envelope.key_off();
             }
             if self.kon {
                 self.envelope.key_on();
                 self.kon_delay = 5;
+                self.kon_event = true;
             }
         }
 
@@ -282,6 +297,7 @@ impl Voice {
                     self.sample_address = self.next_sample_address;
                     self.edge_hit = true;
                     self.looped = true;
+                    self.in_loop = self.brr_decoder.is_looping;
                 } else {
                     self.sample_address += 9;
                 }
@@ -383,6 +399,24 @@ impl Voice {
         result
     }
 
+    /// Returns true if the voice was keyed on since the last call.
+    pub fn key_on_detected(&mut self) -> bool {
+        let result = self.kon_event;
+        self.kon_event = false;
+        result
+    }
+
+    /// Returns true if KOF released the voice since the last call.
+    pub fn key_off_detected(&mut self) -> bool {
+        let result = self.kof_event;
+        self.kof_event = false;
+        result
+    }
+
+    pub fn in_loop(&self) -> bool {
+        self.in_loop
+    }
+
     pub fn get_sample_frame(&mut self) -> usize {
         let result = self.sample_frame;
         self.sample_frame += 1;
@@ -405,3 +439,56 @@ impl Voice {
         self.echo_on = self.l_echo_on;
     }
 }
+
+impl SaveState for Voice {
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.envelope.save_state(writer);
+
+        writer.write(&self.volume);
+        writer.write(&[self.pitch_low, self.pitch_high, self.source, self.l_source]);
+        writer.write(&[self.pitch_mod, self.l_pitch_mod, self.noise_on, self.l_noise_on, self.echo_on, self.l_echo_on]);
+
+        writer.write(&[self.sample_start_address, self.loop_start_address, self.next_sample_address]);
+        self.brr_decoder.save_state(writer);
+        writer.write(&[self.sample_address, self.sample_offset]);
+        writer.write(&self.sample_pos);
+        writer.write(&self.sample_block_index);
+
+        writer.write(&self.edge_hit);
+        writer.write(&self.sample_frame);
+        writer.write(&[self.endx_bit, self.l_endx_bit, self.looped, self.in_loop, self.kon_event, self.kof_event]);
+        writer.write(&[self.outx_value, self.envx_value, self.l_envx_value, self.kon_delay]);
+
+        writer.write(&self.resample_buffer);
+        writer.write(&self.resample_buffer_pos);
+
+        writer.write(&self.amplitude);
+        writer.write(&[self.kon, self.l_kon, self.kof, self.l_kof]);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> io::Result<()> {
+        self.envelope.load_state(reader)?;
+
+        self.volume = reader.read()?;
+        [self.pitch_low, self.pitch_high, self.source, self.l_source] = reader.read()?;
+        [self.pitch_mod, self.l_pitch_mod, self.noise_on, self.l_noise_on, self.echo_on, self.l_echo_on] = reader.read()?;
+
+        [self.sample_start_address, self.loop_start_address, self.next_sample_address] = reader.read()?;
+        self.brr_decoder.load_state(reader)?;
+        [self.sample_address, self.sample_offset] = reader.read()?;
+        self.sample_pos = reader.read()?;
+        self.sample_block_index = reader.read()?;
+
+        self.edge_hit = reader.read()?;
+        self.sample_frame = reader.read()?;
+        [self.endx_bit, self.l_endx_bit, self.looped, self.in_loop, self.kon_event, self.kof_event] = reader.read()?;
+        [self.outx_value, self.envx_value, self.l_envx_value, self.kon_delay] = reader.read()?;
+
+        self.resample_buffer = reader.read()?;
+        self.resample_buffer_pos = reader.read()?;
+
+        self.amplitude = reader.read()?;
+        [self.kon, self.l_kon, self.kof, self.l_kof] = reader.read()?;
+        Ok(())
+    }
+}
@@ -4,10 +4,21 @@ mod dsp;
 mod timer;
 mod script700;
 mod blargg_spc_filter;
+mod save_state;
+mod disassembler;
+mod trace;
+mod debugger;
+mod register_log;
 
 pub use apu::{Apu, ApuChannelState, ApuMasterState, ApuStateReceiver};
 pub use dsp::voice::ResamplingMode;
+pub use dsp::envelope::{EnvelopePhase, GainMode};
 pub use script700::search_for_script700_file;
+pub use save_state::SAVE_STATE_VERSION;
+pub use disassembler::{Instruction, disassemble, disassemble_range};
+pub use trace::{TraceEntry, TraceSink, FileTraceSink};
+pub use debugger::{Debugger, BreakEvent, BreakReason, WatchKind};
+pub use register_log::{RegisterLog, RegisterLogEntry, RegisterLogPlayer, RegisterWrite};
 
 pub fn version() -> &'static str {
     env!("CARGO_PKG_VERSION")
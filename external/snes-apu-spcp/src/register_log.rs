@@ -0,0 +1,292 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use crate::apu::{Apu, ApuStateReceiver};
+use crate::ResamplingMode;
+
+const REGISTER_LOG_MAGIC: &[u8; 8] = b"SPCPRLOG";
+const REGISTER_LOG_VERSION: u8 = 2;
+
+// DSP cycles per output sample
+const SAMPLE_CYCLES: u8 = 32;
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RegisterWrite {
+    Dsp { register: u8, value: u8 },
+    /// Written by the SMP to $F4-$F7.
+    OutputPort { port: u8, value: u8 },
+    /// Written by the host (e.g. Script700) for the SMP to read.
+    InputPort { port: u8, value: u8 }
+}
+
+impl RegisterWrite {
+    fn encode(&self) -> (u8, u8, u8) {
+        match *self {
+            RegisterWrite::Dsp { register, value } => (0, register, value),
+            RegisterWrite::OutputPort { port, value } => (1, port, value),
+            RegisterWrite::InputPort { port, value } => (2, port, value)
+        }
+    }
+
+    fn decode(kind: u8, address: u8, value: u8) -> io::Result<Self> {
+        match (kind, address) {
+            (0, register @ 0x00..=0x7f) => Ok(RegisterWrite::Dsp { register, value }),
+            (1, port @ 0..=3) => Ok(RegisterWrite::OutputPort { port, value }),
+            (2, port @ 0..=3) => Ok(RegisterWrite::InputPort { port, value }),
+            _ => Err(invalid_data(&format!("Invalid register log entry (kind={}, address=${:02x})", kind, address)))
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RegisterLogEntry {
+    /// DSP samples elapsed since the capture started.
+    pub sample: u64,
+    /// DSP cycle within the sample (0-31) at which the write happened.
+    pub cycle: u8,
+    pub write: RegisterWrite
+}
+
+/// A capture of every DSP register and I/O port write, along with the APU's save state at the
+/// start of the capture. RAM written by the SMP during the capture is not recorded, so logs of
+/// drivers that stream sample data will not replay faithfully.
+#[derive(Clone)]
+pub struct RegisterLog {
+    pub state: Vec<u8>,
+    pub entries: Vec<RegisterLogEntry>
+}
+
+impl RegisterLog {
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer)?;
+        writer.flush()
+    }
+
+    pub fn from_reader<R: Read>(mut reader: R) -> io::Result<Self> {
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != REGISTER_LOG_MAGIC {
+            return Err(invalid_data("Not a register log"));
+        }
+
+        let version = read_u8(&mut reader)?;
+        if version != REGISTER_LOG_VERSION {
+            return Err(invalid_data(&format!("Unsupported register log version {} (expected {})", version, REGISTER_LOG_VERSION)));
+        }
+
+        let state_len = read_u32(&mut reader)?;
+        let mut state = Vec::new();
+        reader.by_ref().take(state_len as u64).read_to_end(&mut state)?;
+        if state.len() != state_len as usize {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Register log is truncated"));
+        }
+
+        let entry_count = read_u32(&mut reader)?;
+
+        let mut entries = Vec::with_capacity(entry_count as usize);
+        let mut sample = 0u64;
+        for _ in 0..entry_count {
+            let mut entry = [0u8; 4];
+            reader.read_exact(&mut entry)?;
+            let [kind, address, value, cycle] = entry;
+            if cycle >= SAMPLE_CYCLES {
+                return Err(invalid_data(&format!("Invalid register log cycle {}", cycle)));
+            }
+
+            sample += read_varint(&mut reader)?;
+            entries.push(RegisterLogEntry {
+                sample,
+                cycle,
+                write: RegisterWrite::decode(kind, address, value)?
+            });
+        }
+
+        Ok(Self {
+            state,
+            entries
+        })
+    }
+
+    /// Writes the log in a compact binary format: the initial save state, followed by 4 bytes
+    /// per write and the number of samples since the previous write as a varint.
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(REGISTER_LOG_MAGIC)?;
+        writer.write_all(&[REGISTER_LOG_VERSION])?;
+        writer.write_all(&(self.state.len() as u32).to_le_bytes())?;
+        writer.write_all(&self.state)?;
+        writer.write_all(&(self.entries.len() as u32).to_le_bytes())?;
+
+        let mut sample = 0u64;
+        for entry in self.entries.iter() {
+            let (kind, address, value) = entry.write.encode();
+            writer.write_all(&[kind, address, value, entry.cycle])?;
+            write_varint(writer, entry.sample - sample)?;
+            sample = entry.sample;
+        }
+
+        Ok(())
+    }
+
+    /// Total length of the capture in DSP samples, up to the last write.
+    pub fn sample_count(&self) -> u64 {
+        self.entries.last().map_or(0, |entry| entry.sample + 1)
+    }
+}
+
+fn read_u8<R: Read>(reader: &mut R) -> io::Result<u8> {
+    let mut result = [0u8];
+    reader.read_exact(&mut result)?;
+    Ok(result[0])
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut result = [0u8; 4];
+    reader.read_exact(&mut result)?;
+    Ok(u32::from_le_bytes(result))
+}
+
+fn read_varint<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut result = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = read_u8(reader)?;
+        result |= ((byte & 0x7f) as u64) << shift;
+        if (byte & 0x80) == 0 {
+            return Ok(result);
+        }
+    }
+    Err(invalid_data("Register log varint is too long"))
+}
+
+fn write_varint<W: Write>(writer: &mut W, mut value: u64) -> io::Result<()> {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            return writer.write_all(&[byte]);
+        }
+        writer.write_all(&[byte | 0x80])?;
+    }
+}
+
+pub(crate) struct RegisterLogRecorder {
+    log: RegisterLog,
+    sample: u64
+}
+
+impl RegisterLogRecorder {
+    pub fn new(state: Vec<u8>) -> Self {
+        Self {
+            log: RegisterLog {
+                state,
+                entries: Vec::new()
+            },
+            sample: 0
+        }
+    }
+
+    pub fn next_sample(&mut self) {
+        self.sample += 1;
+    }
+
+    pub fn record(&mut self, cycle: u8, write: RegisterWrite) {
+        self.log.entries.push(RegisterLogEntry {
+            sample: self.sample,
+            cycle,
+            write
+        });
+    }
+
+    pub fn finish(self) -> RegisterLog {
+        self.log
+    }
+}
+
+/// Replays a register log through the DSP alone. The SMP is never run, so the output depends
+/// only on the logged writes and the initial state.
+pub struct RegisterLogPlayer {
+    apu: Box<Apu>,
+    entries: Vec<RegisterLogEntry>,
+    position: usize,
+    sample: u64
+}
+
+impl RegisterLogPlayer {
+    pub fn new(log: &RegisterLog) -> io::Result<Self> {
+        let mut apu = Apu::new();
+        apu.load_state(&log.state)?;
+
+        Ok(Self {
+            apu,
+            entries: log.entries.clone(),
+            position: 0,
+            sample: 0
+        })
+    }
+
+    pub fn set_resampling_mode(&mut self, resampling_mode: ResamplingMode) {
+        self.apu.set_resampling_mode(resampling_mode);
+    }
+
+    pub fn set_output_filter_enabled(&mut self, enabled: bool) {
+        self.apu.set_output_filter_enabled(enabled);
+    }
+
+    pub fn set_state_receiver(&mut self, state_receiver: Option<Arc<Mutex<dyn ApuStateReceiver>>>) {
+        self.apu.dsp.as_mut().unwrap().state_receiver = state_receiver;
+    }
+
+    /// Returns true once every logged write has been applied.
+    pub fn is_finished(&self) -> bool {
+        self.position >= self.entries.len()
+    }
+
+    /// The APU being driven, for inspecting RAM and port state during playback.
+    pub fn apu(&self) -> &Apu {
+        &self.apu
+    }
+
+    pub fn render(&mut self, left_buffer: &mut [i16], right_buffer: &mut [i16], num_samples: usize) {
+        while self.apu.dsp.as_ref().unwrap().output_buffer.get_sample_count() < num_samples {
+            self.run_sample();
+        }
+
+        self.apu.dsp.as_mut().unwrap().output_buffer.read(left_buffer, right_buffer, num_samples);
+        self.apu.filter_output(&mut left_buffer[..num_samples], &mut right_buffer[..num_samples]);
+    }
+
+    fn run_sample(&mut self) {
+        for _ in 0..SAMPLE_CYCLES {
+            self.apply_writes();
+            self.apu.dsp.as_mut().unwrap().cycles_callback(1);
+            if self.apu.dsp.as_ref().unwrap().cycle_count() == 0 {
+                self.sample += 1;
+            }
+        }
+    }
+
+    fn apply_writes(&mut self) {
+        let cycle = self.apu.dsp.as_ref().unwrap().cycle_count();
+        while let Some(entry) = self.entries.get(self.position) {
+            if (entry.sample, entry.cycle) > (self.sample, cycle) {
+                break;
+            }
+
+            match entry.write {
+                RegisterWrite::Dsp { register, value } => self.apu.dsp.as_mut().unwrap().set_register(register, value),
+                RegisterWrite::OutputPort { port, value } => self.apu.output_ports[port as usize] = value,
+                RegisterWrite::InputPort { port, value } => self.apu.write_to_input_port(port as usize, value)
+            }
+            self.position += 1;
+        }
+    }
+}
@@ -0,0 +1,208 @@
+use std::io;
+use crate::dsp::stereo::Stereo;
+
+const SAVE_STATE_MAGIC: &[u8; 8] = b"SPCPSAVE";
+pub const SAVE_STATE_VERSION: u32 = 4;
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+pub(crate) trait StateValue: Sized {
+    fn write_to(&self, writer: &mut StateWriter);
+    fn read_from(reader: &mut StateReader) -> io::Result<Self>;
+}
+
+pub(crate) trait SaveState {
+    fn save_state(&self, writer: &mut StateWriter);
+    fn load_state(&mut self, reader: &mut StateReader) -> io::Result<()>;
+}
+
+pub(crate) struct StateWriter(Vec<u8>);
+
+impl StateWriter {
+    pub fn new() -> Self {
+        let mut result = Self(Vec::new());
+        result.write_bytes(SAVE_STATE_MAGIC);
+        result.write(&SAVE_STATE_VERSION);
+        result
+    }
+
+    pub fn write<T: StateValue>(&mut self, value: &T) {
+        value.write_to(self);
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.0.extend_from_slice(bytes);
+    }
+
+    pub fn into_inner(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+pub(crate) struct StateReader<'a> {
+    data: &'a [u8],
+    position: usize
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> io::Result<Self> {
+        let mut result = Self {
+            data,
+            position: 0
+        };
+
+        let mut magic = [0u8; 8];
+        result.read_bytes(&mut magic)?;
+        if &magic != SAVE_STATE_MAGIC {
+            return Err(invalid_data("Not a save state"));
+        }
+
+        let version: u32 = result.read()?;
+        if version != SAVE_STATE_VERSION {
+            return Err(invalid_data(&format!("Unsupported save state version {} (expected {})", version, SAVE_STATE_VERSION)));
+        }
+
+        Ok(result)
+    }
+
+    pub fn read<T: StateValue>(&mut self) -> io::Result<T> {
+        T::read_from(self)
+    }
+
+    pub fn read_bytes(&mut self, bytes: &mut [u8]) -> io::Result<()> {
+        let end = self.position + bytes.len();
+        if end > self.data.len() {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Save state is truncated"));
+        }
+
+        bytes.copy_from_slice(&self.data[self.position..end]);
+        self.position = end;
+        Ok(())
+    }
+
+    pub fn finish(self) -> io::Result<()> {
+        if self.position != self.data.len() {
+            return Err(invalid_data("Save state has trailing data"));
+        }
+        Ok(())
+    }
+}
+
+macro_rules! int_state_value {
+    ($($t: ty),*) => {
+        $(
+            impl StateValue for $t {
+                fn write_to(&self, writer: &mut StateWriter) {
+                    writer.write_bytes(&self.to_le_bytes());
+                }
+
+                fn read_from(reader: &mut StateReader) -> io::Result<Self> {
+                    let mut bytes = [0u8; std::mem::size_of::<$t>()];
+                    reader.read_bytes(&mut bytes)?;
+                    Ok(<$t>::from_le_bytes(bytes))
+                }
+            }
+        )*
+    }
+}
+
+int_state_value!(u8, i8, u16, i16, u32, i32, u64);
+
+impl StateValue for usize {
+    fn write_to(&self, writer: &mut StateWriter) {
+        writer.write(&(*self as u64));
+    }
+
+    fn read_from(reader: &mut StateReader) -> io::Result<Self> {
+        let value: u64 = reader.read()?;
+        usize::try_from(value).map_err(|_| invalid_data("Save state value out of range"))
+    }
+}
+
+impl StateValue for bool {
+    fn write_to(&self, writer: &mut StateWriter) {
+        writer.write(&(*self as u8));
+    }
+
+    fn read_from(reader: &mut StateReader) -> io::Result<Self> {
+        match reader.read::<u8>()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(invalid_data("Invalid boolean in save state"))
+        }
+    }
+}
+
+impl<T: StateValue + Copy + Default, const N: usize> StateValue for [T; N] {
+    fn write_to(&self, writer: &mut StateWriter) {
+        for value in self.iter() {
+            writer.write(value);
+        }
+    }
+
+    fn read_from(reader: &mut StateReader) -> io::Result<Self> {
+        let mut result = [T::default(); N];
+        for value in result.iter_mut() {
+            *value = reader.read()?;
+        }
+        Ok(result)
+    }
+}
+
+impl<T: StateValue> StateValue for Vec<T> {
+    fn write_to(&self, writer: &mut StateWriter) {
+        writer.write(&self.len());
+        for value in self.iter() {
+            writer.write(value);
+        }
+    }
+
+    fn read_from(reader: &mut StateReader) -> io::Result<Self> {
+        let len: usize = reader.read()?;
+        let mut result = Vec::new();
+        for _ in 0..len {
+            result.push(reader.read()?);
+        }
+        Ok(result)
+    }
+}
+
+impl<T: StateValue> StateValue for Option<T> {
+    fn write_to(&self, writer: &mut StateWriter) {
+        writer.write(&self.is_some());
+        if let Some(value) = self {
+            writer.write(value);
+        }
+    }
+
+    fn read_from(reader: &mut StateReader) -> io::Result<Self> {
+        match reader.read::<bool>()? {
+            true => Ok(Some(reader.read()?)),
+            false => Ok(None)
+        }
+    }
+}
+
+impl<A: StateValue, B: StateValue> StateValue for (A, B) {
+    fn write_to(&self, writer: &mut StateWriter) {
+        writer.write(&self.0);
+        writer.write(&self.1);
+    }
+
+    fn read_from(reader: &mut StateReader) -> io::Result<Self> {
+        Ok((reader.read()?, reader.read()?))
+    }
+}
+
+impl<T: StateValue> StateValue for Stereo<T> {
+    fn write_to(&self, writer: &mut StateWriter) {
+        writer.write(self.left());
+        writer.write(self.right());
+    }
+
+    fn read_from(reader: &mut StateReader) -> io::Result<Self> {
+        Ok(Stereo::new(reader.read()?, reader.read()?))
+    }
+}
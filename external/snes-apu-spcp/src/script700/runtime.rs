@@ -5,6 +5,7 @@ use std::path::Path;
 use std::sync::{Arc, Mutex};
 use crate::apu::{Apu, ApuScript700State, ApuStateReceiver};
 use crate::script700::context::ImportContext;
+use crate::save_state::{SaveState, StateReader, StateWriter};
 use super::{lexer, parser::{self, script_area::{ScriptAst, Command, Condition, Operation, Parameter, ParameterValue}}, context::ScriptContext};
 
 pub struct Runtime {
@@ -419,3 +420,46 @@ impl Runtime {
         }
     }
 }
+
+impl SaveState for Runtime {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write(&self.script_pc);
+        writer.write(&self.wait_cycles);
+        writer.write(&self.wait_port_event);
+        writer.write(&self.wait_port_cycles);
+
+        writer.write(&[self.cmp1, self.cmp2]);
+        writer.write(&self.working_memory);
+
+        writer.write(&self.call_stack);
+        writer.write(&self.call_stack_enabled);
+
+        writer.write(&self.input_ports_buffer);
+        writer.write(&self.input_ports_unbuffered);
+
+        writer.write(&self.data_area);
+
+        writer.write(&self.state_update_cycle_count);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> io::Result<()> {
+        self.script_pc = reader.read()?;
+        self.wait_cycles = reader.read()?;
+        self.wait_port_event = reader.read()?;
+        self.wait_port_cycles = reader.read()?;
+
+        [self.cmp1, self.cmp2] = reader.read()?;
+        self.working_memory = reader.read()?;
+
+        self.call_stack = reader.read()?;
+        self.call_stack_enabled = reader.read()?;
+
+        self.input_ports_buffer = reader.read()?;
+        self.input_ports_unbuffered = reader.read()?;
+
+        self.data_area = reader.read()?;
+
+        self.state_update_cycle_count = reader.read()?;
+        Ok(())
+    }
+}
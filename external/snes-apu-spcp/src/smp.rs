@@ -1,5 +1,10 @@
 use std::sync::{Arc, Mutex};
 use super::apu::{Apu, ApuSmpState, ApuStateReceiver};
+use std::io;
+use crate::save_state::{SaveState, StateReader, StateWriter};
+use crate::disassembler::disassemble;
+use crate::trace::{TraceEntry, TraceSink};
+use crate::debugger::WatchKind;
 
 pub struct Smp {
     emulator: *mut Apu,
@@ -22,9 +27,12 @@ pub struct Smp {
     is_stopped: bool,
 
     cycle_count: i32,
+    total_cycle_count: u64,
 
     pub state_receiver: Option<Arc<Mutex<dyn ApuStateReceiver>>>,
-    state_update_cycle_count: i32
+    state_update_cycle_count: i32,
+
+    pub trace_sink: Option<Box<dyn TraceSink>>
 }
 
 impl Smp {
@@ -50,9 +58,12 @@ impl Smp {
             is_stopped: false,
 
             cycle_count: 0,
+            total_cycle_count: 0,
 
             state_receiver: None,
-            state_update_cycle_count: 0
+            state_update_cycle_count: 0,
+
+            trace_sink: None
         }
     }
 
@@ -98,9 +109,16 @@ impl Smp {
         (value & 0x80) != 0
     }
 
+    // Internal (idle) cycles are stretched by the TEST register's wait states
     fn cycles(&mut self, num_cycles: i32) {
-        self.emulator().cpu_cycles_callback(num_cycles);
+        let (cycles, timer_cycles) = self.emulator().idle_cycles();
+        self.tick(num_cycles * cycles, num_cycles * timer_cycles);
+    }
+
+    fn tick(&mut self, num_cycles: i32, timer_cycles: i32) {
+        self.emulator().cpu_cycles_callback(num_cycles, timer_cycles);
         self.cycle_count += num_cycles;
+        self.total_cycle_count += num_cycles as u64;
 
         self.state_update_cycle_count += num_cycles;
         if self.state_update_cycle_count >= 32 {
@@ -126,14 +144,41 @@ impl Smp {
         }
     }
 
+    fn trace_instruction(&mut self) {
+        let emulator = self.emulator();
+        let entry = TraceEntry {
+            instruction: disassemble(self.reg_pc, |address| emulator.peek_u8(address)),
+            reg_a: self.reg_a,
+            reg_x: self.reg_x,
+            reg_y: self.reg_y,
+            reg_sp: self.reg_sp,
+            reg_psw: self.get_psw(),
+            cycles: self.total_cycle_count
+        };
+        self.trace_sink.as_mut().unwrap().trace(&entry);
+    }
+
     fn read(&mut self, addr: u16) -> u8 {
-        self.cycles(1);
-        self.emulator().read_u8(addr as u32)
+        let (num_cycles, timer_cycles) = self.emulator().access_cycles(addr);
+        self.tick(num_cycles, timer_cycles);
+        let value = self.emulator().read_u8(addr as u32);
+        if self.emulator().debugger.is_active() {
+            self.emulator().check_watchpoints(addr, value, WatchKind::Read);
+        }
+        value
     }
 
     fn write(&mut self, addr: u16, value: u8) {
-        self.cycles(1);
+        let (num_cycles, timer_cycles) = self.emulator().access_cycles(addr);
+        self.tick(num_cycles, timer_cycles);
         self.emulator().write_u8(addr as u32, value);
+        if self.emulator().debugger.is_active() {
+            self.emulator().check_watchpoints(addr, value, WatchKind::Write);
+        }
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.is_stopped
     }
 
     fn read_pc(&mut self) -> u8 {
@@ -913,6 +958,12 @@ impl Smp {
         self.cycle_count = 0;
         while self.cycle_count < target_cycles {
             if !self.is_stopped {
+                if self.emulator().debugger.is_active() && self.emulator().debugger.check_execute(self.reg_pc) {
+                    break;
+                }
+                if self.trace_sink.is_some() {
+                    self.trace_instruction();
+                }
                 let opcode = self.read_pc();
                 match opcode {
                     0x00 => self.nop(),
@@ -1187,6 +1238,10 @@ impl Smp {
                     0xfe => self.bne_y_dec(),
                     0xff => self.sleep_stop()
                 }
+
+                if self.emulator().debugger.is_active() && self.emulator().debugger.check_instruction_complete() {
+                    break;
+                }
             } else {
                 self.cycles(2);
             }
@@ -1195,3 +1250,29 @@ impl Smp {
         self.cycle_count
     }
 }
+
+impl SaveState for Smp {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write(&self.reg_pc);
+        writer.write(&[self.reg_a, self.reg_x, self.reg_y, self.reg_sp, self.get_psw()]);
+        writer.write(&self.is_stopped);
+        writer.write(&self.cycle_count);
+        writer.write(&self.state_update_cycle_count);
+        writer.write(&self.total_cycle_count);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> io::Result<()> {
+        self.reg_pc = reader.read()?;
+        let [reg_a, reg_x, reg_y, reg_sp, reg_psw] = reader.read()?;
+        self.reg_a = reg_a;
+        self.reg_x = reg_x;
+        self.reg_y = reg_y;
+        self.reg_sp = reg_sp;
+        self.set_psw(reg_psw);
+        self.is_stopped = reader.read()?;
+        self.cycle_count = reader.read()?;
+        self.state_update_cycle_count = reader.read()?;
+        self.total_cycle_count = reader.read()?;
+        Ok(())
+    }
+}
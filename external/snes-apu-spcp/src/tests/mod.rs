@@ -156,3 +156,628 @@ fn script700_data_area_parser() {
         (34, 8)
     ]);
 }
+
+fn write_dsp_register(apu: &mut crate::Apu, address: u8, value: u8) {
+    apu.write_u8(0xf2, address);
+    apu.write_u8(0xf3, value);
+}
+
+fn apu_with_test_tone() -> Box<crate::Apu> {
+    let mut apu = crate::Apu::new();
+
+    // Source directory at $0200, one looping BRR block at $0300
+    apu.write_u8(0x0200, 0x00);
+    apu.write_u8(0x0201, 0x03);
+    apu.write_u8(0x0202, 0x00);
+    apu.write_u8(0x0203, 0x03);
+    apu.write_u8(0x0300, 0xb3);
+    for i in 0..8 {
+        apu.write_u8(0x0301 + i, if i % 2 == 0 { 0x77 } else { 0x99 });
+    }
+
+    write_dsp_register(&mut apu, 0x5d, 0x02);
+    write_dsp_register(&mut apu, 0x0c, 0x7f);
+    write_dsp_register(&mut apu, 0x1c, 0x7f);
+    write_dsp_register(&mut apu, 0x00, 0x7f);
+    write_dsp_register(&mut apu, 0x01, 0x7f);
+    write_dsp_register(&mut apu, 0x02, 0x00);
+    write_dsp_register(&mut apu, 0x03, 0x10);
+    write_dsp_register(&mut apu, 0x04, 0x00);
+    write_dsp_register(&mut apu, 0x05, 0x8f);
+    write_dsp_register(&mut apu, 0x06, 0xe0);
+    write_dsp_register(&mut apu, 0x6c, 0x20);
+    write_dsp_register(&mut apu, 0x4c, 0x01);
+
+    apu
+}
+
+fn render_samples(apu: &mut crate::Apu, num_samples: usize) -> (Vec<i16>, Vec<i16>) {
+    let mut left = vec![0i16; num_samples];
+    let mut right = vec![0i16; num_samples];
+    apu.render(&mut left, &mut right, num_samples);
+    (left, right)
+}
+
+#[test]
+fn save_state_round_trip() {
+    let mut apu = apu_with_test_tone();
+    render_samples(&mut apu, 1000);
+
+    let state = apu.save_state();
+    let first_pass = render_samples(&mut apu, 2000);
+    assert!(first_pass.0.iter().any(|&s| s != 0), "test tone should be audible");
+
+    apu.load_state(&state).unwrap();
+    let second_pass = render_samples(&mut apu, 2000);
+    assert_eq!(first_pass, second_pass);
+
+    let mut other_apu = crate::Apu::new();
+    other_apu.load_state(&state).unwrap();
+    let third_pass = render_samples(&mut other_apu, 2000);
+    assert_eq!(first_pass, third_pass);
+
+    // Trace cycle counts continue from the loaded state
+    apu.load_state(&state).unwrap();
+    let mut fresh_apu = crate::Apu::new();
+    fresh_apu.load_state(&state).unwrap();
+    let cycles = first_traced_cycles(&mut apu);
+    assert!(cycles > 0);
+    assert_eq!(cycles, first_traced_cycles(&mut fresh_apu));
+    assert!(apu.save_state() == fresh_apu.save_state());
+}
+
+fn first_traced_cycles(apu: &mut crate::Apu) -> u64 {
+    use std::sync::{Arc, Mutex};
+    use crate::TraceEntry;
+
+    let cycles = Arc::new(Mutex::new(None));
+    {
+        let cycles = cycles.clone();
+        apu.set_trace_sink(Some(Box::new(move |entry: &TraceEntry| {
+            cycles.lock().unwrap().get_or_insert(entry.cycles);
+        })));
+    }
+    render_samples(apu, 16);
+    apu.set_trace_sink(None);
+
+    let cycles = cycles.lock().unwrap().unwrap();
+    cycles
+}
+
+#[test]
+fn save_state_rejects_invalid_data() {
+    let mut apu = apu_with_test_tone();
+    render_samples(&mut apu, 1000);
+    let mut state = apu.save_state();
+
+    // A failed load must leave the current state alone, even when most of the data was valid
+    let mut other_apu = crate::Apu::new();
+    let other_state = other_apu.save_state();
+    assert!(other_apu.load_state(&state[..state.len() - 1]).is_err());
+    assert!(other_apu.load_state(b"not a save state").is_err());
+    assert!(other_apu.save_state() == other_state);
+
+    state[8] = state[8].wrapping_add(1);
+    assert!(apu.load_state(&state).is_err());
+}
+
+#[test]
+fn fast_forward_matches_render() {
+    let mut rendered_apu = apu_with_test_tone();
+    rendered_apu.set_output_filter_enabled(false);
+    render_samples(&mut rendered_apu, 5000);
+    let rendered = render_samples(&mut rendered_apu, 1000);
+
+    let mut fast_forwarded_apu = apu_with_test_tone();
+    fast_forwarded_apu.set_output_filter_enabled(false);
+    fast_forwarded_apu.fast_forward(5000);
+    let fast_forwarded = render_samples(&mut fast_forwarded_apu, 1000);
+
+    assert_eq!(rendered, fast_forwarded);
+}
+
+#[test]
+fn muted_voice_is_silent() {
+    let mut apu = apu_with_test_tone();
+    apu.set_output_filter_enabled(false);
+    apu.set_voice_muted(0, true);
+    assert!(apu.is_voice_muted(0));
+
+    let (left, right) = render_samples(&mut apu, 2000);
+    assert!(left.iter().chain(right.iter()).all(|&s| s == 0));
+
+    apu.set_voice_muted(0, false);
+    let (left, _) = render_samples(&mut apu, 2000);
+    assert!(left.iter().any(|&s| s != 0));
+}
+
+#[test]
+fn solo_silences_other_voices() {
+    let mut apu = apu_with_test_tone();
+    apu.set_output_filter_enabled(false);
+    apu.set_voice_mask(0x00, 0x02);
+    assert_eq!(apu.voice_mask(), (0x00, 0x02));
+
+    let (left, right) = render_samples(&mut apu, 2000);
+    assert!(left.iter().chain(right.iter()).all(|&s| s == 0));
+
+    apu.set_voice_solo(0, true);
+    let (left, _) = render_samples(&mut apu, 2000);
+    assert!(left.iter().any(|&s| s != 0));
+}
+
+#[test]
+fn spc_export_round_trip() {
+    use std::time::Duration;
+    use spc_spcp::spc::{Emulator, ExtendedId666Chunk, ExtendedId666Data, Id666Tag, Spc};
+
+    let mut apu = apu_with_test_tone();
+    render_samples(&mut apu, 1000);
+
+    let mut spc = apu.to_spc();
+    spc.id666_tag = Some(Id666Tag {
+        song_title: "Test Tone".to_string(),
+        game_title: "Tests".to_string(),
+        dumper_name: "spcp".to_string(),
+        comments: String::new(),
+        date_dumped: "10/18/2026".to_string(),
+        play_time: Duration::from_secs(95),
+        fadeout_time: Duration::from_millis(5000),
+        artist_name: "Nobody".to_string(),
+        muted_voices: [false, true, false, false, false, false, false, true],
+        dumping_emulator: Emulator::Snes9x
+    });
+    spc.extended_id666 = Some(ExtendedId666Data::new(vec![
+        ExtendedId666Chunk::SongTitle("A considerably longer test tone title".to_string()),
+        ExtendedId666Chunk::OstTrack((3, Some('b'))),
+        ExtendedId666Chunk::LoopLength(Duration::from_secs(30)),
+        ExtendedId666Chunk::PreampLevel(0x18000)
+    ]));
+
+    let mut data = Vec::new();
+    spc.to_writer(&mut data).unwrap();
+    let loaded = Spc::from_reader(std::io::Cursor::new(data)).unwrap();
+
+    assert_eq!((loaded.pc, loaded.a, loaded.x, loaded.y, loaded.psw, loaded.sp), (spc.pc, spc.a, spc.x, spc.y, spc.psw, spc.sp));
+    assert_eq!(loaded.ram, spc.ram);
+    assert_eq!(loaded.regs, spc.regs);
+    assert_eq!(loaded.ipl_rom, spc.ipl_rom);
+
+    let id666_tag = loaded.id666_tag.as_ref().unwrap();
+    assert_eq!(id666_tag.game_title, "Tests");
+    assert_eq!(id666_tag.date_dumped, "10/18/2026");
+    assert_eq!(id666_tag.play_time, Duration::from_secs(95));
+    assert_eq!(id666_tag.fadeout_time, Duration::from_millis(5000));
+    assert_eq!(id666_tag.muted_voices, [false, true, false, false, false, false, false, true]);
+
+    let metadata = loaded.metadata();
+    assert_eq!(metadata.song_title().unwrap(), "A considerably longer test tone title");
+    assert_eq!(metadata.preamp_level(), Some(0x18000));
+    assert_eq!(metadata.play_time(Some(2)).unwrap().0, Duration::from_secs(95 + 60));
+
+    // The exported state picks up where the original left off
+    let mut reloaded_apu = crate::Apu::from_spc(&loaded);
+    assert_eq!(reloaded_apu.to_spc().ram, spc.ram);
+}
+
+#[test]
+fn disassembler_decodes_ipl_rom() {
+    let apu = crate::Apu::new();
+
+    let mut address = 0xffc0;
+    let mut lines = Vec::new();
+    for _ in 0..8 {
+        let instruction = apu.disassemble(address);
+        lines.push(format!("{:04x} {}", instruction.address, instruction));
+        address = instruction.next_address();
+    }
+
+    assert_eq!(lines, vec![
+        "ffc0 mov x, #$ef",
+        "ffc2 mov sp, x",
+        "ffc3 mov a, #$00",
+        "ffc5 mov (x), a",
+        "ffc6 dec x",
+        "ffc7 bne $ffc5",
+        "ffc9 mov $f4, #$aa",
+        "ffcc mov $f5, #$bb"
+    ]);
+}
+
+#[test]
+fn disassembler_operand_order() {
+    use crate::disassemble_range;
+
+    let code = [
+        0x03, 0x12, 0xfd,       // bbs $12.0, $0400
+        0xfa, 0x34, 0x56,       // mov $56, $34
+        0x4a, 0x45, 0x63,       // and1 c, $0345.3
+        0x1f, 0x00, 0x02,       // jmp [!$0200+x]
+        0x4f, 0x80,             // pcall $ff80
+        0xde, 0x20, 0x00        // cbne $20+x, $0411
+    ];
+    let text: Vec<_> = disassemble_range(&code, 0x0400)
+        .iter()
+        .map(|instruction| instruction.text.clone())
+        .collect();
+
+    assert_eq!(text, vec![
+        "bbs $12.0, $0400",
+        "mov $56, $34",
+        "and1 c, $0345.3",
+        "jmp [!$0200+x]",
+        "pcall $ff80",
+        "cbne $20+x, $0411"
+    ]);
+}
+
+#[test]
+fn trace_sink_receives_instructions() {
+    use std::sync::{Arc, Mutex};
+    use crate::TraceEntry;
+
+    let entries: Arc<Mutex<Vec<TraceEntry>>> = Arc::new(Mutex::new(Vec::new()));
+    let mut apu = crate::Apu::new();
+    {
+        let entries = entries.clone();
+        apu.set_trace_sink(Some(Box::new(move |entry: &TraceEntry| {
+            entries.lock().unwrap().push(entry.clone());
+        })));
+    }
+    render_samples(&mut apu, 4);
+
+    let entries = entries.lock().unwrap();
+    assert!(entries.len() > 4);
+    assert_eq!(entries[0].pc(), 0xffc0);
+    assert_eq!(entries[0].cycles, 0);
+    assert_eq!(entries[1].instruction.text, "mov sp, x");
+    assert_eq!(entries[1].reg_x, 0xef);
+    assert!(entries.windows(2).all(|pair| pair[0].cycles < pair[1].cycles));
+    assert!(entries[1].to_string().starts_with("ffc2  bd        mov sp, x"));
+}
+
+fn apu_with_program(program: &[u8]) -> Box<crate::Apu> {
+    let mut apu = crate::Apu::new();
+    for (i, &byte) in program.iter().enumerate() {
+        apu.write_u8(0x0400 + i as u32, byte);
+    }
+    apu.smp.as_mut().unwrap().reg_pc = 0x0400;
+    apu
+}
+
+// mov $30, #$00; loop: inc $30; bra loop
+const COUNTER_PROGRAM: [u8; 7] = [0x8f, 0x00, 0x30, 0xab, 0x30, 0x2f, 0xfc];
+
+#[test]
+fn debugger_breakpoint_stops_render() {
+    use crate::BreakReason;
+
+    let mut apu = apu_with_program(&COUNTER_PROGRAM);
+    apu.debugger_mut().add_breakpoint(0x0403);
+
+    let (mut left, mut right) = (vec![0i16; 32], vec![0i16; 32]);
+    let event = apu.render(&mut left, &mut right, 32).unwrap();
+    assert_eq!(event.reason, BreakReason::Breakpoint(0x0403));
+    assert_eq!(event.pc, 0x0403);
+    assert!(event.samples_rendered < 32);
+
+    // Resuming executes the instruction at the breakpoint and stops on the next pass
+    let event = apu.render(&mut left, &mut right, 32).unwrap();
+    assert_eq!(event.reason, BreakReason::Breakpoint(0x0403));
+    assert_eq!(apu.read_u8(0x30), 1);
+
+    apu.debugger_mut().remove_breakpoint(0x0403);
+    assert!(apu.render(&mut left, &mut right, 32).is_none());
+}
+
+#[test]
+fn debugger_ram_watchpoint_tracks_counter_wrap() {
+    use crate::{BreakReason, WatchKind};
+
+    let mut apu = apu_with_program(&COUNTER_PROGRAM);
+    apu.debugger_mut().add_ram_watchpoint(0x0030..=0x0030, WatchKind::Write);
+
+    let (mut left, mut right) = (vec![0i16; 32], vec![0i16; 32]);
+    let mut values = Vec::new();
+    while values.len() < 258 {
+        if let Some(event) = apu.render(&mut left, &mut right, 32) {
+            match event.reason {
+                BreakReason::RamWrite { address: 0x0030, value } => values.push(value),
+                reason => panic!("unexpected break reason {:?}", reason)
+            }
+        }
+    }
+
+    let expected: Vec<u8> = (0..258).map(|i| (i % 256) as u8).collect();
+    assert_eq!(values, expected);
+}
+
+#[test]
+fn debugger_dsp_watchpoint() {
+    use crate::{BreakReason, WatchKind};
+
+    // mov $f2, #$4c; mov $f3, #$01; bra *
+    let mut apu = apu_with_program(&[0x8f, 0x4c, 0xf2, 0x8f, 0x01, 0xf3, 0x2f, 0xfe]);
+    apu.debugger_mut().add_dsp_watchpoint(0x4c..=0x4c, WatchKind::Write);
+
+    let (mut left, mut right) = (vec![0i16; 32], vec![0i16; 32]);
+    let event = apu.render(&mut left, &mut right, 32).unwrap();
+    assert_eq!(event.reason, BreakReason::DspWrite { register: 0x4c, value: 0x01 });
+    assert_eq!(event.pc, 0x0406);
+}
+
+#[test]
+fn debugger_single_step() {
+    use crate::BreakReason;
+
+    let mut apu = apu_with_program(&COUNTER_PROGRAM);
+
+    let pcs: Vec<_> = (0..5)
+        .map(|_| {
+            let event = apu.step().unwrap();
+            assert_eq!(event.reason, BreakReason::Step);
+            event.pc
+        })
+        .collect();
+    assert_eq!(pcs, vec![0x0403, 0x0405, 0x0403, 0x0405, 0x0403]);
+    assert_eq!(apu.read_u8(0x30), 2);
+}
+
+// loop: incw $30; bra loop
+const WIDE_COUNTER_PROGRAM: [u8; 4] = [0x3a, 0x30, 0x2f, 0xfc];
+
+fn count_loops_with_test_reg(test_reg: u8) -> u16 {
+    let mut apu = apu_with_program(&WIDE_COUNTER_PROGRAM);
+    apu.write_u8(0xf0, test_reg);
+    render_samples(&mut apu, 32);
+    u16::from_le_bytes([apu.read_u8(0x30), apu.read_u8(0x31)])
+}
+
+fn timer0_ticks_with_test_reg(test_reg: u8) -> u8 {
+    // bra *
+    let mut apu = apu_with_program(&[0x2f, 0xfe]);
+    apu.write_u8(0xfa, 1);
+    apu.write_u8(0xf1, 0x01);
+    apu.write_u8(0xf0, test_reg);
+    render_samples(&mut apu, 8);
+    apu.read_u8(0xfd)
+}
+
+#[test]
+fn test_reg_timer_enable() {
+    assert!(timer0_ticks_with_test_reg(0x0a) > 0);
+    assert_eq!(timer0_ticks_with_test_reg(0x02), 0);
+}
+
+#[test]
+fn test_reg_timer_halt() {
+    assert_eq!(timer0_ticks_with_test_reg(0x0b), 0);
+}
+
+#[test]
+fn test_reg_ram_write_disable() {
+    let mut apu = apu_with_program(&COUNTER_PROGRAM);
+    apu.write_u8(0x30, 0x55);
+    apu.write_u8(0xf0, 0x08);
+    render_samples(&mut apu, 32);
+    assert_eq!(apu.read_u8(0x30), 0x55);
+
+    // I/O registers are still writable
+    apu.write_u8(0xf2, 0x4c);
+    assert_eq!(apu.read_u8(0xf2), 0x4c);
+}
+
+#[test]
+fn test_reg_unsupported_bit_is_ignored() {
+    let mut apu = apu_with_program(&COUNTER_PROGRAM);
+    apu.write_u8(0xf0, 0x0e);
+    assert_eq!(apu.test_reg(), 0x0e);
+    render_samples(&mut apu, 32);
+    assert_ne!(apu.read_u8(0x30), 0);
+}
+
+#[test]
+fn test_reg_ram_wait_states() {
+    let fast = count_loops_with_test_reg(0x0a);
+    let slow = count_loops_with_test_reg(0x3a);
+    assert!(slow > 0);
+    assert!(slow * 4 < fast);
+}
+
+#[test]
+fn test_reg_io_wait_states() {
+    let fast = count_loops_with_test_reg(0x0a);
+    let slow = count_loops_with_test_reg(0xca);
+    assert!(slow > 0);
+    assert!(slow * 2 < fast);
+}
+
+#[test]
+fn test_reg_ignores_writes_with_p_flag_set() {
+    let mut apu = apu_with_program(&COUNTER_PROGRAM);
+    let psw = apu.smp.as_ref().unwrap().get_psw();
+    apu.smp.as_mut().unwrap().set_psw(psw | 0x20);
+    apu.write_u8(0xf0, 0x00);
+    assert_eq!(apu.test_reg(), 0x0a);
+}
+
+// Plays the test tone from an SMP program, then sweeps the pitch and echoes it to port 0
+fn apu_with_pitch_sweep_program() -> Box<crate::Apu> {
+    let registers = [
+        (0x5d, 0x02), (0x0c, 0x7f), (0x1c, 0x7f), (0x00, 0x7f), (0x01, 0x7f), (0x03, 0x10),
+        (0x04, 0x00), (0x05, 0x8f), (0x06, 0xe0), (0x6c, 0x20), (0x4c, 0x01), (0x02, 0x00)
+    ];
+    let mut program: Vec<u8> = registers.iter()
+        .flat_map(|&(register, value)| [0x8f, register, 0xf2, 0x8f, value, 0xf3])
+        .collect();
+    // loop: inc $30; mov a, $30; mov $f3, a; mov $f4, a; bra loop
+    program.extend_from_slice(&[0xab, 0x30, 0xe4, 0x30, 0xc4, 0xf3, 0xc4, 0xf4, 0x2f, 0xf6]);
+
+    let mut apu = apu_with_program(&program);
+    apu.write_u8(0x0200, 0x00);
+    apu.write_u8(0x0201, 0x03);
+    apu.write_u8(0x0202, 0x00);
+    apu.write_u8(0x0203, 0x03);
+    apu.write_u8(0x0300, 0xb3);
+    for i in 0..8 {
+        apu.write_u8(0x0301 + i, if i % 2 == 0 { 0x77 } else { 0x99 });
+    }
+    apu
+}
+
+#[test]
+fn register_log_replay_matches_render() {
+    use crate::{RegisterLogPlayer, RegisterWrite};
+
+    let mut apu = apu_with_pitch_sweep_program();
+    apu.start_register_log();
+    let rendered = render_samples(&mut apu, 2000);
+    let log = apu.stop_register_log().unwrap();
+    assert!(rendered.0.iter().any(|&s| s != 0), "test tone should be audible");
+
+    assert_eq!(log.entries[10].write, RegisterWrite::Dsp { register: 0x4c, value: 0x01 });
+    assert!(log.entries.iter().any(|entry| matches!(entry.write, RegisterWrite::OutputPort { port: 0, .. })));
+    assert!(log.entries.windows(2).all(|w| (w[0].sample, w[0].cycle) <= (w[1].sample, w[1].cycle)));
+
+    let mut player = RegisterLogPlayer::new(&log).unwrap();
+    let (mut left, mut right) = (vec![0i16; 2000], vec![0i16; 2000]);
+    player.render(&mut left, &mut right, 2000);
+    assert_eq!(rendered, (left, right));
+
+    // The SMP runs slightly ahead of the rendered audio, so drain the rest of the log
+    let (mut left, mut right) = ([0i16; 1], [0i16; 1]);
+    while !player.is_finished() {
+        player.render(&mut left, &mut right, 1);
+    }
+    assert_eq!(player.apu().output_ports[0], apu.output_ports[0]);
+}
+
+#[test]
+fn register_log_replay_from_mid_song() {
+    // Voices, envelopes and echo are already running when the capture starts
+    let mut apu = apu_with_pitch_sweep_program();
+    render_samples(&mut apu, 3000);
+    apu.start_register_log();
+    let rendered = render_samples(&mut apu, 2000);
+    let log = apu.stop_register_log().unwrap();
+
+    let mut player = crate::RegisterLogPlayer::new(&log).unwrap();
+    let (mut left, mut right) = (vec![0i16; 2000], vec![0i16; 2000]);
+    player.render(&mut left, &mut right, 2000);
+    assert_eq!(rendered, (left, right));
+}
+
+#[test]
+fn register_log_file_round_trip() {
+    let mut apu = apu_with_pitch_sweep_program();
+    apu.start_register_log();
+    apu.write_to_input_port(1, 0x42);
+    render_samples(&mut apu, 500);
+    let log = apu.stop_register_log().unwrap();
+    assert!(apu.stop_register_log().is_none());
+
+    let mut data = Vec::new();
+    log.write(&mut data).unwrap();
+    let loaded = crate::RegisterLog::from_reader(&data[..]).unwrap();
+    assert_eq!(loaded.state, log.state);
+    assert_eq!(loaded.entries, log.entries);
+    assert_eq!(loaded.entries[0].write, crate::RegisterWrite::InputPort { port: 1, value: 0x42 });
+
+    assert!(crate::RegisterLog::from_reader(&data[..data.len() - 1]).is_err());
+    assert!(crate::RegisterLog::from_reader(&b"not a register log"[..]).is_err());
+}
+
+#[derive(Default)]
+struct ChannelStateRecorder(Vec<crate::ApuChannelState>);
+
+impl crate::ApuStateReceiver for ChannelStateRecorder {
+    fn receive_channel(&mut self, channel: usize, state: crate::ApuChannelState) {
+        if channel == 0 {
+            self.0.push(state);
+        }
+    }
+}
+
+#[test]
+fn channel_state_reports_envelope_and_key_events() {
+    use std::sync::{Arc, Mutex};
+    use crate::EnvelopePhase;
+
+    let mut apu = apu_with_test_tone();
+    let recorder = Arc::new(Mutex::new(ChannelStateRecorder::default()));
+    apu.set_state_receiver(Some(recorder.clone()));
+    render_samples(&mut apu, 2000);
+
+    {
+        let states = &recorder.lock().unwrap().0;
+        assert_eq!(states.iter().filter(|state| state.key_on).count(), 1);
+        assert!(states.iter().all(|state| !state.key_off));
+        assert!(states.iter().all(|state| state.adsr == (0x8f, 0xe0)));
+
+        let key_on_index = states.iter().position(|state| state.key_on).unwrap();
+        let phases: Vec<_> = states[key_on_index..].iter().map(|state| state.envelope_phase).collect();
+        assert_eq!(phases[phases.len() / 2], EnvelopePhase::Sustain);
+        assert!(phases.contains(&EnvelopePhase::Attack));
+        assert!(phases.contains(&EnvelopePhase::Decay));
+
+        // The single BRR block has its end and loop flags set
+        let last = states.last().unwrap();
+        assert!(last.endx);
+        assert!(last.in_loop);
+        assert!(!states[key_on_index].in_loop);
+    }
+
+    recorder.lock().unwrap().0.clear();
+    write_dsp_register(&mut apu, 0x5c, 0x01);
+    render_samples(&mut apu, 100);
+    write_dsp_register(&mut apu, 0x5c, 0x00);
+    write_dsp_register(&mut apu, 0x07, 0xc0);
+    write_dsp_register(&mut apu, 0x05, 0x00);
+    write_dsp_register(&mut apu, 0x4c, 0x01);
+    render_samples(&mut apu, 100);
+
+    let states = &recorder.lock().unwrap().0;
+    assert_eq!(states.iter().filter(|state| state.key_off).count(), 1);
+    assert_eq!(states.iter().filter(|state| state.key_on).count(), 1);
+    let key_off_index = states.iter().position(|state| state.key_off).unwrap();
+    assert_eq!(states[key_off_index].envelope_phase, EnvelopePhase::Release);
+    assert_eq!(states.last().unwrap().envelope_phase, EnvelopePhase::Gain(crate::GainMode::LinearIncrease));
+    assert_eq!(states.last().unwrap().gain, 0xc0);
+}
+
+#[test]
+fn key_events_are_not_reported_late() {
+    use std::sync::{Arc, Mutex};
+
+    // The test tone is keyed on while no receiver is attached
+    let mut apu = apu_with_test_tone();
+    render_samples(&mut apu, 100);
+    write_dsp_register(&mut apu, 0x5c, 0x01);
+    apu.fast_forward(100);
+
+    let recorder = Arc::new(Mutex::new(ChannelStateRecorder::default()));
+    apu.set_state_receiver(Some(recorder.clone()));
+    render_samples(&mut apu, 100);
+
+    let states = &recorder.lock().unwrap().0;
+    assert!(states.iter().all(|state| !state.key_on && !state.key_off));
+}
+
+fn timer0_stage2_ticks_with_test_reg(test_reg: u8) -> u32 {
+    // bra *
+    let mut apu = apu_with_program(&[0x2f, 0xfe]);
+    apu.write_u8(0xfa, 0);
+    apu.write_u8(0xf1, 0x01);
+    apu.write_u8(0xf0, test_reg);
+    render_samples(&mut apu, 8192);
+    apu.read_u8(0xfd) as u32
+}
+
+#[test]
+fn test_reg_timer_wait_states() {
+    // RAM wait state 3 stretches each opcode fetch to 10 cycles, but the timers only see 8,
+    // so they run at 18/22 of their usual rate through this loop
+    let normal = timer0_stage2_ticks_with_test_reg(0x0a);
+    let slow = timer0_stage2_ticks_with_test_reg(0x3a);
+    assert_eq!((normal, slow), (8, 6));
+}
@@ -1,3 +1,6 @@
+use std::io;
+use crate::save_state::{SaveState, StateReader, StateWriter};
+
 // pub struct Timer {
 //     resolution: i32,
 //     is_running: bool,
@@ -70,21 +73,25 @@ impl<const F: u8> Timer<F> {
         Self::default()
     }
 
-    pub fn cpu_cycles_callback(&mut self, num_cycles: i32) {
-        // The 2 is from the internal SPC700 clock divider.
-        self.stage0 += (num_cycles * 2) as u8;
-        if self.stage0 < F {
-            return;
-        }
+    pub fn cpu_cycles_callback(&mut self, num_cycles: i32, timers_enabled: bool) {
+        // Wait states can stretch a single access past several stage 0 periods
+        for _ in 0..num_cycles {
+            // The 2 is from the internal SPC700 clock divider.
+            self.stage0 += 2;
+            if self.stage0 < F {
+                continue;
+            }
 
-        self.stage0 -= F;
-        self.stage1 ^= 1;
-        self.synchronize_stage1();
+            self.stage0 -= F;
+            self.stage1 ^= 1;
+            self.synchronize_stage1(timers_enabled);
+        }
     }
 
-    pub fn synchronize_stage1(&mut self) {
+    /// `timers_enabled` reflects the TEST register; halted timers hold their stage 1 output low.
+    pub fn synchronize_stage1(&mut self, timers_enabled: bool) {
         let line = self.line;
-        self.line = self.stage1 != 0;
+        self.line = self.stage1 != 0 && timers_enabled;
         if !line || self.line {
             return;
         }
@@ -118,4 +125,33 @@ impl<const F: u8> Timer<F> {
     pub fn set_target(&mut self, target: u8) {
         self.target = target;
     }
+
+    pub fn enabled(&self) -> bool {
+        self.enable
+    }
+
+    pub fn target(&self) -> u8 {
+        self.target
+    }
+
+    pub fn counter(&self) -> u8 {
+        self.stage3
+    }
+}
+
+impl<const F: u8> SaveState for Timer<F> {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write(&[self.stage0, self.stage1, self.stage2, self.stage3]);
+        writer.write(&self.line);
+        writer.write(&self.enable);
+        writer.write(&self.target);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> io::Result<()> {
+        [self.stage0, self.stage1, self.stage2, self.stage3] = reader.read()?;
+        self.line = reader.read()?;
+        self.enable = reader.read()?;
+        self.target = reader.read()?;
+        Ok(())
+    }
 }
@@ -0,0 +1,80 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use crate::disassembler::Instruction;
+
+/// CPU state captured right before an instruction executes.
+#[derive(Debug, Clone)]
+pub struct TraceEntry {
+    pub instruction: Instruction,
+    pub reg_a: u8,
+    pub reg_x: u8,
+    pub reg_y: u8,
+    pub reg_sp: u8,
+    pub reg_psw: u8,
+    pub cycles: u64
+}
+
+impl TraceEntry {
+    pub fn pc(&self) -> u16 {
+        self.instruction.address
+    }
+}
+
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bytes: Vec<String> = self.instruction.bytes()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        let flags: String = "nvpbhizc".chars()
+            .enumerate()
+            .map(|(i, c)| if (self.reg_psw & (0x80 >> i)) != 0 { c.to_ascii_uppercase() } else { c })
+            .collect();
+
+        write!(
+            f,
+            "{:04x}  {:<8}  {:<24}  A:{:02x} X:{:02x} Y:{:02x} SP:{:02x} PSW:{} CYC:{}",
+            self.pc(),
+            bytes.join(" "),
+            self.instruction.text,
+            self.reg_a,
+            self.reg_x,
+            self.reg_y,
+            self.reg_sp,
+            flags,
+            self.cycles
+        )
+    }
+}
+
+pub trait TraceSink {
+    fn trace(&mut self, entry: &TraceEntry);
+}
+
+impl<F: FnMut(&TraceEntry)> TraceSink for F {
+    fn trace(&mut self, entry: &TraceEntry) {
+        self(entry)
+    }
+}
+
+/// Writes one line per executed instruction to a file.
+pub struct FileTraceSink {
+    writer: BufWriter<File>
+}
+
+impl FileTraceSink {
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Self {
+            writer: BufWriter::new(File::create(path)?)
+        })
+    }
+}
+
+impl TraceSink for FileTraceSink {
+    fn trace(&mut self, entry: &TraceEntry) {
+        // Tracing is best-effort; a failed write should not stop emulation
+        let _ = writeln!(self.writer, "{}", entry);
+    }
+}
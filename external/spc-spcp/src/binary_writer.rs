@@ -0,0 +1,58 @@
+use std::io::{Result, Write};
+use super::string_decoder::encode_string;
+
+pub trait BinaryWrite : Write {
+    fn write_u8(&mut self, value: u8) -> Result<()>;
+    fn write_le_u16(&mut self, value: u16) -> Result<()>;
+    fn write_le_u32(&mut self, value: u32) -> Result<()>;
+    fn write_string(&mut self, s: &str, len: usize) -> Result<()>;
+    fn write_zeros(&mut self, len: usize) -> Result<()>;
+}
+
+pub struct BinaryWriter<W> {
+    inner: W
+}
+
+impl<W: Write> BinaryWriter<W> {
+    pub fn new(inner: W) -> BinaryWriter<W> {
+        BinaryWriter { inner }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write> Write for BinaryWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> BinaryWrite for BinaryWriter<W> {
+    fn write_u8(&mut self, value: u8) -> Result<()> {
+        self.write_all(&[value])
+    }
+
+    fn write_le_u16(&mut self, value: u16) -> Result<()> {
+        self.write_all(&value.to_le_bytes())
+    }
+
+    fn write_le_u32(&mut self, value: u32) -> Result<()> {
+        self.write_all(&value.to_le_bytes())
+    }
+
+    fn write_string(&mut self, s: &str, len: usize) -> Result<()> {
+        let string_bytes = encode_string(s, len);
+        self.write_all(&string_bytes)?;
+        self.write_zeros(len - string_bytes.len())
+    }
+
+    fn write_zeros(&mut self, len: usize) -> Result<()> {
+        self.write_all(&vec![0; len])
+    }
+}
@@ -3,6 +3,8 @@ use std::io::{Result, Error, ErrorKind, Seek};
 use std::ops::Deref;
 use std::time::Duration;
 use super::binary_reader::BinaryRead;
+use super::binary_writer::{BinaryWrite, BinaryWriter};
+use super::string_decoder::encode_string;
 use super::id666::Emulator;
 
 #[derive(Clone, Debug)]
@@ -28,7 +30,7 @@ pub enum ExtendedId666Chunk {
     FadeoutLength(Duration),
     MutedVoices([bool; 8]),
     PreferredLoopCount(u16),
-    PreampLevel(u16)
+    PreampLevel(u32)
 }
 
 impl ExtendedId666Chunk {
@@ -77,14 +79,14 @@ impl ExtendedId666Chunk {
                 0x34 => {
                     let raw_muted = Self::read_data_item(r)?;
                     let muted: [bool; 8] = (0..8)
-                        .map(|i| ((raw_muted >> (7 - i)) & 1) == 1)
+                        .map(|i| ((raw_muted >> i) & 1) == 1)
                         .collect::<Vec<_>>()
                         .try_into()
                         .unwrap();
                     Self::MutedVoices(muted)
                 },
                 0x35 => Self::PreferredLoopCount(Self::read_data_item(r)?),
-                0x36 => Self::PreampLevel(Self::read_data_item(r)?),
+                0x36 => Self::PreampLevel(Self::read_data_or_integer_item(r)?),
 
                 _ => return Err(Error::new(ErrorKind::InvalidData, "Unknown extended ID"))
             },
@@ -98,6 +100,85 @@ impl ExtendedId666Chunk {
         }))
     }
 
+    fn write<W: BinaryWrite>(&self, w: &mut W) -> Result<()> {
+        match self {
+            Self::SongTitle(s) => Self::write_string_item(w, 0x01, s),
+            Self::GameTitle(s) => Self::write_string_item(w, 0x02, s),
+            Self::ArtistName(s) => Self::write_string_item(w, 0x03, s),
+            Self::DumperName(s) => Self::write_string_item(w, 0x04, s),
+            Self::DateDumped(date) => {
+                let parts: Vec<u32> = date.split('/')
+                    .map(|part| part.parse::<u32>())
+                    .collect::<std::result::Result<_, _>>()
+                    .map_err(|_| Error::new(ErrorKind::InvalidInput, format!("Invalid dump date {}", date)))?;
+                let [month, day, year] = parts[..] else {
+                    return Err(Error::new(ErrorKind::InvalidInput, format!("Invalid dump date {}", date)));
+                };
+                Self::write_integer_item(w, 0x05, (year << 16) | ((month & 0xFF) << 8) | (day & 0xFF))
+            },
+            Self::DumpingEmulator(emulator) => Self::write_data_item(w, 0x06, match emulator {
+                Emulator::Unknown => 0,
+                Emulator::ZSnes => 1,
+                Emulator::Snes9x => 2
+            }),
+            Self::Comments(s) => Self::write_string_item(w, 0x07, s),
+
+            Self::OstTitle(s) => Self::write_string_item(w, 0x10, s),
+            Self::OstDisc(disc) => Self::write_data_item(w, 0x11, *disc),
+            Self::OstTrack((track, track_char)) => {
+                let raw_track = ((*track as u16) << 8) | track_char.map(|c| c as u8 as u16).unwrap_or(0);
+                Self::write_data_item(w, 0x12, raw_track)
+            },
+            Self::PublisherName(s) => Self::write_string_item(w, 0x13, s),
+            Self::CopyrightYear(year) => Self::write_data_item(w, 0x14, *year),
+
+            Self::IntroductionLength(duration) => Self::write_duration_integer(w, 0x30, duration),
+            Self::LoopLength(duration) => Self::write_duration_integer(w, 0x31, duration),
+            Self::EndLength(duration) => Self::write_duration_integer(w, 0x32, duration),
+            Self::FadeoutLength(duration) => Self::write_duration_integer(w, 0x33, duration),
+            Self::MutedVoices(muted) => {
+                let raw_muted = muted
+                    .iter()
+                    .enumerate()
+                    .fold(0u16, |raw_muted, (i, &muted)| raw_muted | ((muted as u16) << i));
+                Self::write_data_item(w, 0x34, raw_muted)
+            },
+            Self::PreferredLoopCount(count) => Self::write_data_item(w, 0x35, *count),
+            Self::PreampLevel(level) => Self::write_integer_item(w, 0x36, *level)
+        }
+    }
+
+    fn write_data_item<W: BinaryWrite>(w: &mut W, id: u8, value: u16) -> Result<()> {
+        w.write_u8(id)?;
+        w.write_u8(0)?;
+        w.write_le_u16(value)
+    }
+
+    fn write_integer_item<W: BinaryWrite>(w: &mut W, id: u8, value: u32) -> Result<()> {
+        w.write_u8(id)?;
+        w.write_u8(4)?;
+        w.write_le_u16(4)?;
+        w.write_le_u32(value)
+    }
+
+    fn write_duration_integer<W: BinaryWrite>(w: &mut W, id: u8, duration: &Duration) -> Result<()> {
+        let raw_duration = (duration.as_secs_f64() * 64000.0).round().min(u32::MAX as f64) as u32;
+        Self::write_integer_item(w, id, raw_duration)
+    }
+
+    fn write_string_item<W: BinaryWrite>(w: &mut W, id: u8, s: &str) -> Result<()> {
+        // Strings include their null terminator and are padded to a multiple of 4 bytes
+        let mut string_bytes = encode_string(s, 255);
+        string_bytes.push(0);
+        let len = string_bytes.len();
+
+        w.write_u8(id)?;
+        w.write_u8(1)?;
+        w.write_le_u16(len as u16)?;
+        w.write_all(&string_bytes)?;
+        w.write_zeros((4 - (len % 4)) % 4)
+    }
+
     fn read_data_item<R: BinaryRead + Seek>(r: &mut R) -> Result<u16> {
         if r.read_u8()? != 0 {
             return Err(Error::new(ErrorKind::InvalidData, "Expected data item"));
@@ -117,6 +198,19 @@ impl ExtendedId666Chunk {
         r.read_le_u32()
     }
 
+    fn read_data_or_integer_item<R: BinaryRead + Seek>(r: &mut R) -> Result<u32> {
+        match r.read_u8()? {
+            0 => Ok(r.read_le_u16()? as u32),
+            4 => {
+                if r.read_le_u16()? != 4 {
+                    return Err(Error::new(ErrorKind::InvalidData, "Invalid integer item length"));
+                }
+                r.read_le_u32()
+            },
+            _ => Err(Error::new(ErrorKind::InvalidData, "Expected data or integer item"))
+        }
+    }
+
     fn read_duration_integer<R: BinaryRead + Seek>(r: &mut R) -> Result<Duration> {
         let raw_duration = Self::read_integer_item(r)?;
         Ok(Duration::from_secs_f64(raw_duration as f64 / 64000.0))
@@ -138,6 +232,36 @@ pub struct ExtendedId666Data {
 }
 
 impl ExtendedId666Data {
+    pub fn new(chunks: Vec<ExtendedId666Chunk>) -> Self {
+        Self {
+            chunks
+        }
+    }
+
+    /// Replaces the existing chunk of the same kind, or adds the chunk if there is none.
+    pub fn set(&mut self, chunk: ExtendedId666Chunk) {
+        match self.chunks.iter_mut().find(|c| std::mem::discriminant(*c) == std::mem::discriminant(&chunk)) {
+            Some(existing) => *existing = chunk,
+            None => self.chunks.push(chunk)
+        }
+    }
+
+    pub fn retain<F: FnMut(&ExtendedId666Chunk) -> bool>(&mut self, f: F) {
+        self.chunks.retain(f);
+    }
+
+    pub(super) fn save<W: BinaryWrite>(&self, w: &mut W) -> Result<()> {
+        let mut chunk_writer = BinaryWriter::new(Vec::new());
+        for chunk in self.chunks.iter() {
+            chunk.write(&mut chunk_writer)?;
+        }
+        let chunk_data = chunk_writer.into_inner();
+
+        w.write_all(b"xid6")?;
+        w.write_le_u32(chunk_data.len() as u32)?;
+        w.write_all(&chunk_data)
+    }
+
     pub(super) fn load<R: BinaryRead + Seek>(r: &mut R) -> Result<Option<Self>> {
         let mut magic = [0u8; 4];
         let mut size = [0u8; 4];
@@ -3,11 +3,13 @@ use std::io::{Result, Error, ErrorKind, Seek, SeekFrom};
 use std::time::Duration;
 use super::spc::fail;
 use super::binary_reader::BinaryRead;
+use super::binary_writer::BinaryWrite;
 
 const DEFAULT_PLAY_TIME_SEC: i32 = 120;
 const DEFAULT_FADEOUT_TIME_MS: i32 = 10000;
+pub(super) const ID666_TAG_LEN: usize = 0xd2;
 
-#[derive(Clone)]
+#[derive(Clone, Default)]
 pub struct Id666Tag {
     pub song_title: String,
     pub game_title: String,
@@ -21,8 +23,9 @@ pub struct Id666Tag {
     pub dumping_emulator: Emulator
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default)]
 pub enum Emulator {
+    #[default]
     Unknown,
     ZSnes,
     Snes9x
@@ -81,7 +84,7 @@ impl Id666Tag {
 
         let raw_muted = r.read_u8()?;
         let muted_voices: [bool; 8] = (0..8)
-            .map(|i| ((raw_muted >> (7 - i)) & 1) == 1)
+            .map(|i| ((raw_muted >> i) & 1) == 1)
             .collect::<Vec<_>>()
             .try_into()
             .unwrap();
@@ -106,6 +109,46 @@ impl Id666Tag {
         })
     }
 
+    /// Writes the tag in text format. Fields that do not fit are truncated.
+    pub(super) fn save<W: BinaryWrite>(&self, w: &mut W) -> Result<()> {
+        w.write_string(&self.song_title, 32)?;
+        w.write_string(&self.game_title, 32)?;
+        w.write_string(&self.dumper_name, 16)?;
+        w.write_string(&self.comments, 32)?;
+
+        // Anything but digits and slashes would make the tag look like it's in binary format
+        let date_dumped = match self.date_dumped.chars().all(|c| c.is_ascii_digit() || c == '/') {
+            true => self.date_dumped.as_str(),
+            false => ""
+        };
+        w.write_string(date_dumped, 11)?;
+        Id666Tag::write_number(w, self.play_time.as_secs().min(999), 3)?;
+        Id666Tag::write_number(w, (self.fadeout_time.as_millis() as u64).min(99999), 5)?;
+
+        w.write_string(&self.artist_name, 32)?;
+
+        let raw_muted = self.muted_voices
+            .iter()
+            .enumerate()
+            .fold(0u8, |raw_muted, (i, &muted)| raw_muted | ((muted as u8) << i));
+        w.write_u8(raw_muted)?;
+
+        w.write_u8(match self.dumping_emulator {
+            Emulator::Unknown => 0,
+            Emulator::ZSnes => b'1',
+            Emulator::Snes9x => b'2'
+        })?;
+
+        w.write_zeros(45)
+    }
+
+    fn write_number<W: BinaryWrite>(w: &mut W, value: u64, len: usize) -> Result<()> {
+        match value {
+            0 => w.write_zeros(len),
+            _ => w.write_string(&value.to_string(), len)
+        }
+    }
+
     fn is_text_region<R: BinaryRead>(r: &mut R, len: i32) -> Result<bool> {
         let region_bytes = (0..len)
             .map(|_| r.read_u8())
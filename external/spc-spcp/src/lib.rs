@@ -1,6 +1,12 @@
 mod binary_reader;
+mod binary_writer;
 pub mod spc;
+pub mod spc2;
 mod string_decoder;
 mod id666;
 mod extended_id666;
 mod metadata;
+pub mod nspc;
+
+#[cfg(test)]
+mod tests;
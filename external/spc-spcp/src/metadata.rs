@@ -65,6 +65,16 @@ impl<'a> Metadata<'a> {
         Some((play_time, fadeout_time))
     }
 
+    /// Length of the xid6 ending, if the song ends on its own instead of fading out.
+    pub fn end_length(&self) -> Option<Duration> {
+        self.xid6.and_then(|xid6| search_xid6!(xid6, EndLength))
+    }
+
+    /// Output amplification, where 0x10000 is unity gain.
+    pub fn preamp_level(&self) -> Option<u32> {
+        self.xid6.and_then(|xid6| search_xid6!(xid6, PreampLevel))
+    }
+
     pub fn ost_info(&self) -> Option<OstInfo> {
         let xid6 = self.xid6?;
         let title = search_xid6!(xid6, OstTitle)?;
@@ -0,0 +1,143 @@
+use std::fmt;
+use super::spc::Spc;
+
+/// A version of Nintendo's N-SPC sound driver (or a driver built on it, like AddmusicK)
+/// recognized from the contents of APU RAM, along with the addresses of its data structures
+/// where they could be found. Drivers not based on N-SPC are not recognized.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NspcDriver {
+    pub name: &'static str,
+    pub version: Option<&'static str>,
+    pub instrument_table: Option<u16>,
+    pub song_table: Option<u16>,
+    /// The pointer to the sequence data currently being played.
+    pub sequence_pointer: Option<u16>,
+    pub vcmd_length_table: Option<u16>
+}
+
+impl fmt::Display for NspcDriver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name)?;
+        if let Some(version) = self.version {
+            write!(f, " {}", version)?;
+        }
+
+        let addresses = [
+            ("instruments", self.instrument_table),
+            ("songs", self.song_table),
+            ("sequence", self.sequence_pointer),
+            ("vcmd lengths", self.vcmd_length_table)
+        ];
+        for (i, (label, address)) in addresses.iter().filter_map(|(label, address)| Some((label, (*address)?))).enumerate() {
+            let separator = if i == 0 { " (" } else { ", " };
+            write!(f, "{}{} at ${:04x}", separator, label, address)?;
+        }
+        if addresses.iter().any(|(_, address)| address.is_some()) {
+            f.write_str(")")?;
+        }
+        Ok(())
+    }
+}
+
+// Patterns are hex bytes separated by spaces, with ?? matching any byte
+fn parse_pattern(pattern: &str) -> Vec<Option<u8>> {
+    pattern.split_ascii_whitespace()
+        .map(|byte| u8::from_str_radix(byte, 16).ok())
+        .collect()
+}
+
+pub(crate) fn find_pattern(ram: &[u8], pattern: &str) -> Option<usize> {
+    let pattern = parse_pattern(pattern);
+    ram.windows(pattern.len())
+        .position(|window| window.iter().zip(pattern.iter()).all(|(byte, expected)| expected.is_none_or(|expected| *byte == expected)))
+}
+
+fn read_u16(ram: &[u8], address: usize) -> u16 {
+    u16::from_le_bytes([ram[address], ram[(address + 1) & 0xffff]])
+}
+
+// Lengths of the operands of voice commands $E0-$FA
+const NSPC_VCMD_LENGTHS: &str = "01 01 02 03 00 01 02 01 02 01 01 03 00 01 02 03 01 03 03 00 01 03 00 03 03 03 01";
+// Early N-SPC (Super Mario World, F-Zero) uses voice commands $DA-$F2, without per-voice transpose
+const NSPC_EARLY_VCMD_LENGTHS: &str = "01 01 02 03 03 00 01 02 01 02 01 03 00 01 02 03 01 03 03 00 01 03 00 03 03";
+// AddmusicK reuses the early command set with its own additions, and counts the command byte itself.
+// $E6 is subloop instead of tremolo off, $ED sets ADSR instead of turning the pitch envelope off,
+// and $F5 sets the 8 FIR coefficients.
+const AMK_VCMD_LENGTHS: &str = "02 02 03 04 04 01 02 03 02 03 02 04 02 02 03 04 02 04 04 03 02 04 01 04 04 03 02 09";
+// mov y, #$06; mul ya; movw dp, ya; clrc; adc dp, #lo; adc dp+1, #hi
+const NSPC_SET_INSTRUMENT: &str = "8d 06 cf da ?? 60 98 ?? ?? 98 ?? ??";
+// asl a; mov x, a; mov a, !songs-1+x; mov y, a; mov a, !songs-2+x; movw dp, ya
+const NSPC_LOAD_SONG: &str = "1c 5d f5 ?? ?? fd f5 ?? ?? da ??";
+
+struct Signature {
+    name: &'static str,
+    version: Option<&'static str>,
+    identify: fn(&[u8]) -> Option<NspcDriver>
+}
+
+fn nspc_driver(ram: &[u8], vcmd_length_table: Option<usize>) -> NspcDriver {
+    let instrument_table = find_pattern(ram, NSPC_SET_INSTRUMENT)
+        .map(|offset| u16::from_le_bytes([ram[offset + 7], ram[offset + 10]]));
+    let song_loader = find_pattern(ram, NSPC_LOAD_SONG);
+
+    NspcDriver {
+        name: "",
+        version: None,
+        instrument_table,
+        song_table: song_loader.map(|offset| read_u16(ram, offset + 7).wrapping_add(2)),
+        sequence_pointer: song_loader.map(|offset| read_u16(ram, ram[offset + 10] as usize)),
+        vcmd_length_table: vcmd_length_table.map(|offset| offset as u16)
+    }
+}
+
+// The voice command length table tells the N-SPC versions apart
+fn identify_nspc_vcmds(ram: &[u8], vcmd_lengths: &str) -> Option<NspcDriver> {
+    find_pattern(ram, vcmd_lengths).map(|offset| nspc_driver(ram, Some(offset)))
+}
+
+// Fallback for versions with an unknown command set, where both code patterns must be present
+pub(crate) fn identify_nspc(ram: &[u8]) -> Option<NspcDriver> {
+    let driver = nspc_driver(ram, None);
+    (driver.instrument_table.is_some() && driver.song_table.is_some()).then_some(driver)
+}
+
+// More specific signatures (e.g. derivatives of N-SPC) must come before the drivers they are based on
+static SIGNATURES: &[Signature] = &[
+    Signature {
+        name: "AddmusicK",
+        version: None,
+        identify: |ram| identify_nspc_vcmds(ram, AMK_VCMD_LENGTHS)
+    },
+    Signature {
+        name: "N-SPC",
+        version: Some("early"),
+        identify: |ram| identify_nspc_vcmds(ram, NSPC_EARLY_VCMD_LENGTHS)
+    },
+    Signature {
+        name: "N-SPC",
+        version: Some("standard"),
+        identify: |ram| identify_nspc_vcmds(ram, NSPC_VCMD_LENGTHS)
+    },
+    Signature {
+        name: "N-SPC",
+        version: None,
+        identify: identify_nspc
+    }
+];
+
+/// Identifies the N-SPC version in a 64KiB APU RAM image by matching code and data signatures.
+pub fn identify_nspc_driver(ram: &[u8]) -> Option<NspcDriver> {
+    SIGNATURES.iter().find_map(|signature| {
+        (signature.identify)(ram).map(|driver| NspcDriver {
+            name: signature.name,
+            version: signature.version,
+            ..driver
+        })
+    })
+}
+
+impl Spc {
+    pub fn nspc_driver(&self) -> Option<NspcDriver> {
+        identify_nspc_driver(&self.ram)
+    }
+}
@@ -1,8 +1,10 @@
-use std::io::{Read, Result, Error, ErrorKind, Seek, SeekFrom, BufReader};
+use std::io::{Read, Write, Result, Error, ErrorKind, Seek, SeekFrom, BufReader, BufWriter};
 use std::path::Path;
 use std::fs::File;
 use super::binary_reader::{BinaryRead, BinaryReader};
-use crate::extended_id666::ExtendedId666Data;
+use super::binary_writer::{BinaryWrite, BinaryWriter};
+use crate::id666::ID666_TAG_LEN;
+pub use crate::extended_id666::{ExtendedId666Chunk, ExtendedId666Data};
 pub use super::id666::{Emulator, Id666Tag};
 
 macro_rules! fail {
@@ -109,6 +111,46 @@ impl Spc {
         })
     }
 
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let file = File::create(path)?;
+        let mut writer = BufWriter::new(file);
+        self.to_writer(&mut writer)?;
+        writer.flush()
+    }
+
+    pub fn to_writer<W: Write>(&self, writer: W) -> Result<()> {
+        let mut w = BinaryWriter::new(writer);
+
+        w.write_all(HEADER_BYTES)?;
+        w.write_le_u16(0x1a1a)?;
+        w.write_u8(if self.id666_tag.is_some() { 0x1a } else { 0x1b })?;
+        w.write_u8(self.version_minor)?;
+
+        w.write_le_u16(self.pc)?;
+        w.write_u8(self.a)?;
+        w.write_u8(self.x)?;
+        w.write_u8(self.y)?;
+        w.write_u8(self.psw)?;
+        w.write_u8(self.sp)?;
+        w.write_zeros(2)?;
+
+        match &self.id666_tag {
+            Some(id666_tag) => id666_tag.save(&mut w)?,
+            None => w.write_zeros(ID666_TAG_LEN)?
+        }
+
+        w.write_all(&self.ram)?;
+        w.write_all(&self.regs)?;
+        w.write_zeros(0x40)?;
+        w.write_all(&self.ipl_rom)?;
+
+        if let Some(extended_id666) = &self.extended_id666 {
+            extended_id666.save(&mut w)?;
+        }
+
+        Ok(())
+    }
+
     pub fn metadata(&self) -> Metadata {
         Metadata::new(self.id666_tag.as_ref(), self.extended_id666.as_ref())
     }
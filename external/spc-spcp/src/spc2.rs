@@ -0,0 +1,245 @@
+use std::io::{Read, Result, Error, ErrorKind, Seek, SeekFrom, BufReader};
+use std::path::Path;
+use std::fs::File;
+use std::time::Duration;
+use super::binary_reader::{BinaryRead, BinaryReader};
+use super::spc::{fail, Spc, Id666Tag, Emulator, ExtendedId666Chunk, ExtendedId666Data, RAM_LEN, REG_LEN, IPL_ROM_LEN};
+
+const HEADER_BYTES: &[u8; 5] = b"KSPC\x1a";
+const HEADER_LEN: u64 = 16;
+const SONG_ENTRY_LEN: u64 = 1024;
+const RAM_BLOCK_LEN: usize = 256;
+const RAM_BLOCK_COUNT: usize = RAM_LEN / RAM_BLOCK_LEN;
+
+// Offsets within a song entry
+const DATE_OFFSET: u64 = 0x2c0;
+const STRINGS_OFFSET: u64 = 0x300;
+const FILE_NAME_OFFSET: u64 = 0x3e4;
+
+#[derive(Clone)]
+pub struct Spc2Song {
+    pub file_name: String,
+    pub spc: Spc
+}
+
+/// An SPC2 archive. Songs share a pool of 256-byte RAM blocks, and each song is rebuilt into a
+/// standalone SPC when the archive is loaded.
+#[derive(Clone)]
+pub struct Spc2 {
+    pub version: (u8, u8),
+    pub songs: Vec<Spc2Song>
+}
+
+struct Spc2Entry {
+    regs: [u8; REG_LEN],
+    ipl_rom: [u8; IPL_ROM_LEN],
+    ram_blocks: [u16; RAM_BLOCK_COUNT],
+    file_name: String,
+    pc: u16,
+    a: u8,
+    x: u8,
+    y: u8,
+    psw: u8,
+    sp: u8,
+    id666_tag: Id666Tag,
+    extended_id666: ExtendedId666Data
+}
+
+impl Spc2 {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Spc2> {
+        let file = File::open(path)?;
+        Spc2::from_reader(BufReader::new(file))
+    }
+
+    /// Returns true if the data starts with the SPC2 signature.
+    pub fn is_spc2<R: Read + Seek>(reader: &mut R) -> Result<bool> {
+        let mut header = [0u8; 5];
+        reader.seek(SeekFrom::Start(0))?;
+        let result = reader.read_exact(&mut header).is_ok() && &header == HEADER_BYTES;
+        reader.seek(SeekFrom::Start(0))?;
+        Ok(result)
+    }
+
+    pub fn from_reader<R: Read + Seek>(reader: R) -> Result<Spc2> {
+        let mut r = BinaryReader::new(reader);
+
+        let mut header = [0u8; 5];
+        r.read_exact(&mut header)?;
+        if &header != HEADER_BYTES {
+            fail!("Invalid SPC2 header");
+        }
+
+        let version_major = r.read_u8()?;
+        let version_minor = r.read_u8()?;
+        if version_major != 1 {
+            fail!(format!("Unsupported SPC2 version {}.{}", version_major, version_minor));
+        }
+
+        let song_count = r.read_le_u16()? as u64;
+
+        let entries = (0..song_count)
+            .map(|index| {
+                r.seek(SeekFrom::Start(HEADER_LEN + index * SONG_ENTRY_LEN))?;
+                Spc2Entry::load(&mut r)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let ram_blocks_start = HEADER_LEN + song_count * SONG_ENTRY_LEN;
+        let songs = entries
+            .into_iter()
+            .map(|entry| {
+                let mut ram = [0u8; RAM_LEN];
+                for (page, &block) in entry.ram_blocks.iter().enumerate() {
+                    r.seek(SeekFrom::Start(ram_blocks_start + block as u64 * RAM_BLOCK_LEN as u64))?;
+                    r.read_exact(&mut ram[(page * RAM_BLOCK_LEN)..((page + 1) * RAM_BLOCK_LEN)])?;
+                }
+
+                Ok(Spc2Song {
+                    file_name: entry.file_name,
+                    spc: Spc {
+                        version_minor: 30,
+                        pc: entry.pc,
+                        a: entry.a,
+                        x: entry.x,
+                        y: entry.y,
+                        psw: entry.psw,
+                        sp: entry.sp,
+                        id666_tag: Some(entry.id666_tag),
+                        extended_id666: Some(entry.extended_id666),
+                        ram,
+                        regs: entry.regs,
+                        ipl_rom: entry.ipl_rom
+                    }
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Spc2 {
+            version: (version_major, version_minor),
+            songs
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.songs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.songs.is_empty()
+    }
+}
+
+impl Spc2Entry {
+    fn load<R: BinaryRead + Seek>(r: &mut R) -> Result<Self> {
+        let start = r.stream_position()?;
+
+        let mut regs = [0u8; REG_LEN];
+        r.read_exact(&mut regs)?;
+        let mut ipl_rom = [0u8; IPL_ROM_LEN];
+        r.read_exact(&mut ipl_rom)?;
+        let mut ram_blocks = [0u16; RAM_BLOCK_COUNT];
+        for block in ram_blocks.iter_mut() {
+            *block = r.read_le_u16()?;
+        }
+
+        r.seek(SeekFrom::Start(start + DATE_OFFSET))?;
+        let raw_date = r.read_le_u32()?;
+        let intro_length = Spc2Entry::read_duration(r)?;
+        let loop_length = Spc2Entry::read_duration(r)?;
+        let end_length = Spc2Entry::read_duration(r)?;
+        let fadeout_length = Spc2Entry::read_duration(r)?;
+
+        let raw_muted = r.read_u8()?;
+        let muted_voices: [bool; 8] = std::array::from_fn(|i| ((raw_muted >> i) & 1) == 1);
+        let loop_count = r.read_u8()?;
+
+        let pc = r.read_le_u16()?;
+        let a = r.read_u8()?;
+        let x = r.read_u8()?;
+        let y = r.read_u8()?;
+        let psw = r.read_u8()?;
+        let sp = r.read_u8()?;
+
+        r.seek(SeekFrom::Start(start + STRINGS_OFFSET))?;
+        let song_title = r.read_string(32)?;
+        let game_title = r.read_string(32)?;
+        let artist_name = r.read_string(32)?;
+        let dumper_name = r.read_string(32)?;
+        let comments = r.read_string(32)?;
+        let ost_title = r.read_string(32)?;
+        let ost_disc = r.read_u8()?;
+        let raw_ost_track = r.read_le_u16()?;
+        let publisher_name = r.read_string(32)?;
+
+        r.seek(SeekFrom::Start(start + FILE_NAME_OFFSET))?;
+        let file_name = r.read_string(28)?;
+
+        let date_dumped = match raw_date {
+            0 => String::new(),
+            _ => format!("{}/{}/{}", (raw_date >> 8) & 0xFF, raw_date & 0xFF, raw_date >> 16)
+        };
+
+        let id666_tag = Id666Tag {
+            song_title: song_title.clone(),
+            game_title: game_title.clone(),
+            dumper_name: dumper_name.clone(),
+            comments: comments.clone(),
+            date_dumped,
+            play_time: intro_length + loop_length * loop_count.max(1) as u32 + end_length,
+            fadeout_time: fadeout_length,
+            artist_name: artist_name.clone(),
+            muted_voices,
+            dumping_emulator: Emulator::Unknown
+        };
+
+        let mut chunks = vec![
+            ExtendedId666Chunk::SongTitle(song_title),
+            ExtendedId666Chunk::GameTitle(game_title),
+            ExtendedId666Chunk::ArtistName(artist_name),
+            ExtendedId666Chunk::DumperName(dumper_name),
+            ExtendedId666Chunk::Comments(comments),
+            ExtendedId666Chunk::IntroductionLength(intro_length),
+            ExtendedId666Chunk::FadeoutLength(fadeout_length),
+            ExtendedId666Chunk::MutedVoices(muted_voices)
+        ];
+        if !loop_length.is_zero() {
+            chunks.push(ExtendedId666Chunk::LoopLength(loop_length));
+            chunks.push(ExtendedId666Chunk::PreferredLoopCount(loop_count as u16));
+        }
+        if !end_length.is_zero() {
+            chunks.push(ExtendedId666Chunk::EndLength(end_length));
+        }
+        if !ost_title.is_empty() {
+            let track_char = match (raw_ost_track & 0xFF) as u8 {
+                0 => None,
+                c => Some(c as char)
+            };
+            chunks.push(ExtendedId666Chunk::OstTitle(ost_title));
+            chunks.push(ExtendedId666Chunk::OstDisc(ost_disc as u16));
+            chunks.push(ExtendedId666Chunk::OstTrack(((raw_ost_track >> 8) as u8, track_char)));
+        }
+        if !publisher_name.is_empty() {
+            chunks.push(ExtendedId666Chunk::PublisherName(publisher_name));
+        }
+
+        Ok(Spc2Entry {
+            regs,
+            ipl_rom,
+            ram_blocks,
+            file_name,
+            pc,
+            a,
+            x,
+            y,
+            psw,
+            sp,
+            id666_tag,
+            extended_id666: ExtendedId666Data::new(chunks)
+        })
+    }
+
+    fn read_duration<R: BinaryRead>(r: &mut R) -> Result<Duration> {
+        let raw_duration = r.read_le_u32()?;
+        Ok(Duration::from_secs_f64(raw_duration as f64 / 64000.0))
+    }
+}
@@ -25,3 +25,27 @@ pub fn decode_string(s: &[u8]) -> Result<String> {
         Err(e) => Err(Error::new(ErrorKind::InvalidData, e))
     }
 }
+
+/// Encodes a string in the first encoding that can represent it, truncated to at most `max_len`
+/// bytes without splitting characters.
+pub fn encode_string(s: &str, max_len: usize) -> Vec<u8> {
+    let encoding = [WINDOWS_1252, SHIFT_JIS]
+        .into_iter()
+        .find(|encoding| !encoding.encode(s).2);
+
+    let mut result = Vec::with_capacity(max_len);
+    let mut char_buf = [0u8; 4];
+    for c in s.chars() {
+        let char_str = c.encode_utf8(&mut char_buf);
+        let char_bytes = match encoding {
+            Some(encoding) => encoding.encode(char_str).0.into_owned(),
+            None => char_str.as_bytes().to_vec()
+        };
+
+        if result.len() + char_bytes.len() > max_len {
+            break;
+        }
+        result.extend_from_slice(&char_bytes);
+    }
+    result
+}
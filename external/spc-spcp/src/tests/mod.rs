@@ -0,0 +1,176 @@
+use std::io::Cursor;
+use crate::spc::Spc;
+use crate::nspc::{find_pattern, identify_nspc_driver, identify_nspc};
+
+fn blank_spc() -> Spc {
+    Spc {
+        version_minor: 30,
+        pc: 0x0200,
+        a: 0,
+        x: 0,
+        y: 0,
+        psw: 0x02,
+        sp: 0xef,
+        id666_tag: None,
+        extended_id666: None,
+        ram: [0; crate::spc::RAM_LEN],
+        regs: [0; crate::spc::REG_LEN],
+        ipl_rom: [0; crate::spc::IPL_ROM_LEN]
+    }
+}
+
+fn spc_bytes(spc: &Spc) -> Vec<u8> {
+    let mut data = Vec::new();
+    spc.to_writer(&mut data).unwrap();
+    data
+}
+
+#[test]
+fn xid6_muted_voices_bit_order() {
+    // Bit 0 is voice 0, as in the DSP's own voice masks
+    let mut data = spc_bytes(&blank_spc());
+    data.extend_from_slice(b"xid6");
+    data.extend_from_slice(&4u32.to_le_bytes());
+    data.extend_from_slice(&[0x34, 0x00, 0x05, 0x00]);
+
+    let spc = Spc::from_reader(Cursor::new(data)).unwrap();
+    assert_eq!(spc.metadata().muted_voices(), Some([true, false, true, false, false, false, false, false]));
+
+    // And it is written back the same way
+    let data = spc_bytes(&spc);
+    assert_eq!(&data[(data.len() - 4)..], &[0x34, 0x00, 0x05, 0x00]);
+}
+
+#[test]
+fn spc_tags_round_trip() {
+    use std::time::Duration;
+    use crate::spc::{Emulator, ExtendedId666Chunk, ExtendedId666Data, Id666Tag};
+
+    let mut spc = blank_spc();
+    (spc.a, spc.x, spc.y) = (0x12, 0x34, 0x56);
+    spc.ram[0x1234] = 0xab;
+    spc.regs[0x5d] = 0x02;
+    spc.ipl_rom[0] = 0xcd;
+    spc.id666_tag = Some(Id666Tag {
+        song_title: "Test Song".to_string(),
+        game_title: "Tests".to_string(),
+        dumper_name: "spcp".to_string(),
+        comments: "Round trip".to_string(),
+        date_dumped: "10/18/2026".to_string(),
+        play_time: Duration::from_secs(95),
+        fadeout_time: Duration::from_millis(5500),
+        artist_name: "Nobody".to_string(),
+        muted_voices: [false, true, false, false, false, false, false, true],
+        dumping_emulator: Emulator::Snes9x
+    });
+
+    let mut xid6 = ExtendedId666Data::new(vec![
+        ExtendedId666Chunk::SongTitle("A considerably longer title than ID666 can hold".to_string()),
+        ExtendedId666Chunk::DateDumped("10/18/2026".to_string()),
+        ExtendedId666Chunk::DumpingEmulator(Emulator::ZSnes),
+        ExtendedId666Chunk::OstTitle("Test Soundtrack".to_string()),
+        ExtendedId666Chunk::OstDisc(2),
+        ExtendedId666Chunk::OstTrack((3, Some('b'))),
+        ExtendedId666Chunk::PublisherName("Nobody Inc.".to_string()),
+        ExtendedId666Chunk::CopyrightYear(2026),
+        ExtendedId666Chunk::IntroductionLength(Duration::from_secs(20)),
+        ExtendedId666Chunk::LoopLength(Duration::from_secs(30)),
+        ExtendedId666Chunk::EndLength(Duration::from_secs(4)),
+        ExtendedId666Chunk::MutedVoices([false, false, true, false, false, false, false, false]),
+        ExtendedId666Chunk::PreferredLoopCount(3),
+        ExtendedId666Chunk::PreampLevel(0x18000)
+    ]);
+    xid6.set(ExtendedId666Chunk::OstDisc(1));
+    spc.extended_id666 = Some(xid6);
+
+    let data = spc_bytes(&spc);
+    assert_eq!(data.len() % 4, 0);
+    let loaded = Spc::from_reader(Cursor::new(data)).unwrap();
+
+    assert_eq!((loaded.pc, loaded.a, loaded.x, loaded.y, loaded.psw, loaded.sp), (spc.pc, spc.a, spc.x, spc.y, spc.psw, spc.sp));
+    assert_eq!(loaded.ram, spc.ram);
+    assert_eq!(loaded.regs, spc.regs);
+    assert_eq!(loaded.ipl_rom, spc.ipl_rom);
+
+    let id666_tag = loaded.id666_tag.as_ref().unwrap();
+    assert_eq!(id666_tag.song_title, "Test Song");
+    assert_eq!(id666_tag.game_title, "Tests");
+    assert_eq!(id666_tag.dumper_name, "spcp");
+    assert_eq!(id666_tag.comments, "Round trip");
+    assert_eq!(id666_tag.date_dumped, "10/18/2026");
+    assert_eq!(id666_tag.play_time, Duration::from_secs(95));
+    assert_eq!(id666_tag.fadeout_time, Duration::from_millis(5500));
+    assert_eq!(id666_tag.artist_name, "Nobody");
+    assert_eq!(id666_tag.muted_voices, [false, true, false, false, false, false, false, true]);
+    assert!(matches!(id666_tag.dumping_emulator, Emulator::Snes9x));
+
+    let expected_chunks = format!("{:?}", &spc.extended_id666.as_ref().unwrap()[..]);
+    assert_eq!(format!("{:?}", &loaded.extended_id666.as_ref().unwrap()[..]), expected_chunks);
+
+    let metadata = loaded.metadata();
+    assert_eq!(metadata.song_title().unwrap(), "A considerably longer title than ID666 can hold");
+    assert_eq!(metadata.play_time(None).unwrap(), (Duration::from_secs(20 + 3 * 30 + 4), Duration::ZERO));
+}
+
+fn nspc_ram(vcmd_lengths: Option<&[u8]>, load_song: bool) -> Vec<u8> {
+    let mut ram = vec![0; 0x10000];
+    // Instruments at $3d00
+    ram[0x0800..0x080c].copy_from_slice(&[0x8d, 0x06, 0xcf, 0xda, 0x10, 0x60, 0x98, 0x00, 0x10, 0x98, 0x3d, 0x11]);
+    if load_song {
+        // Songs at $2000, with the sequence pointer at $40
+        ram[0x0900..0x090b].copy_from_slice(&[0x1c, 0x5d, 0xf5, 0xff, 0x1f, 0xfd, 0xf5, 0xfe, 0x1f, 0xda, 0x40]);
+        ram[0x40..0x42].copy_from_slice(&[0x34, 0x12]);
+    }
+    if let Some(vcmd_lengths) = vcmd_lengths {
+        ram[0x0a00..0x0a00 + vcmd_lengths.len()].copy_from_slice(vcmd_lengths);
+    }
+    ram
+}
+
+#[test]
+fn nspc_find_pattern() {
+    let ram = [0x00, 0x12, 0x34, 0x56, 0x12, 0x99, 0x56];
+    assert_eq!(find_pattern(&ram, "12 ?? 56"), Some(1));
+    assert_eq!(find_pattern(&ram, "12 99 56"), Some(4));
+    assert_eq!(find_pattern(&ram, "56 12 ??"), Some(3));
+    assert_eq!(find_pattern(&ram, "99 56 ??"), None);
+    assert_eq!(find_pattern(&ram, "ab"), None);
+}
+
+#[test]
+fn nspc_identify_generic() {
+    // Without a known command table, both code patterns have to be found
+    assert_eq!(identify_nspc(&nspc_ram(None, false)), None);
+    assert_eq!(identify_nspc_driver(&nspc_ram(None, false)), None);
+    assert_eq!(identify_nspc_driver(&vec![0; 0x10000]), None);
+
+    let nspc = identify_nspc(&nspc_ram(None, true)).unwrap();
+    assert_eq!(nspc.instrument_table, Some(0x3d00));
+    assert_eq!(nspc.song_table, Some(0x2000));
+    assert_eq!(nspc.sequence_pointer, Some(0x1234));
+    assert_eq!(nspc.vcmd_length_table, None);
+}
+
+#[test]
+fn nspc_identify_versions() {
+    let drivers: [(&[u8], &str, Option<&str>); 3] = [
+        (&[1, 1, 2, 3, 0, 1, 2, 1, 2, 1, 1, 3, 0, 1, 2, 3, 1, 3, 3, 0, 1, 3, 0, 3, 3, 3, 1], "N-SPC", Some("standard")),
+        (&[1, 1, 2, 3, 3, 0, 1, 2, 1, 2, 1, 3, 0, 1, 2, 3, 1, 3, 3, 0, 1, 3, 0, 3, 3], "N-SPC", Some("early")),
+        (&[2, 2, 3, 4, 4, 1, 2, 3, 2, 3, 2, 4, 2, 2, 3, 4, 2, 4, 4, 3, 2, 4, 1, 4, 4, 3, 2, 9], "AddmusicK", None)
+    ];
+    for (vcmd_lengths, name, version) in drivers {
+        // The command table alone is enough
+        let driver = identify_nspc_driver(&nspc_ram(Some(vcmd_lengths), false)).unwrap();
+        assert_eq!((driver.name, driver.version), (name, version));
+        assert_eq!(driver.vcmd_length_table, Some(0x0a00));
+        assert_eq!(driver.instrument_table, Some(0x3d00));
+        assert_eq!(driver.song_table, None);
+
+        let driver = identify_nspc_driver(&nspc_ram(Some(vcmd_lengths), true)).unwrap();
+        assert_eq!((driver.name, driver.version), (name, version));
+        assert_eq!(driver.song_table, Some(0x2000));
+    }
+
+    let driver = identify_nspc_driver(&nspc_ram(None, true)).unwrap();
+    assert_eq!(driver.to_string(), "N-SPC (instruments at $3d00, songs at $2000, sequence at $1234)");
+}
@@ -58,6 +58,7 @@ enum EmulatorThreadMessage {
 }
 
 const SAMPLES_PER_STATE: usize = 320;
+const STATES_PER_SNAPSHOT: usize = 1000;
 
 fn spawn_emulator_thread(channel: mpsc::Receiver<EmulatorThreadMessage>, buffered_audio: BufferedAudio, buffered_states: BufferedStates, seek_position: SeekPosition) -> thread::JoinHandle<()> {
     thread::spawn(move || {
@@ -71,6 +72,7 @@ fn spawn_emulator_thread(channel: mpsc::Receiver<EmulatorThreadMessage>, buffere
         let mut script700_path: Option<PathBuf> = None;
         let state = Arc::new(Mutex::new(EmulatorState::new()));
         let mut minimum_end_position = 0usize;
+        let mut snapshots: Vec<(usize, Vec<u8>)> = Vec::new();
 
         'main: loop {
             match channel.try_recv() {
@@ -96,6 +98,7 @@ fn spawn_emulator_thread(channel: mpsc::Receiver<EmulatorThreadMessage>, buffere
                     apu.set_state_receiver(Some(state.clone()));
 
                     seek_position.store(0, Ordering::Release);
+                    snapshots.clear();
                     buffered_audio.write().unwrap().clear();
                     buffered_audio.write().unwrap().shrink_to(minimum_end_position);
                     buffered_states.write().unwrap().clear();
@@ -104,6 +107,20 @@ fn spawn_emulator_thread(channel: mpsc::Receiver<EmulatorThreadMessage>, buffere
                 Ok(EmulatorThreadMessage::SetResamplingMode(mode)) => {
                     resampling_mode = mode;
 
+                    // Resume from the last snapshot before the play position instead of re-emulating everything
+                    let state_position = seek_position.load(Ordering::Acquire) / (2 * SAMPLES_PER_STATE);
+                    snapshots.retain(|(position, _)| *position <= state_position);
+                    if let Some((position, snapshot)) = snapshots.last() {
+                        if apu.load_state(snapshot).is_ok() {
+                            apu.set_resampling_mode(mode);
+
+                            buffered_audio.write().unwrap().truncate(position * 2 * SAMPLES_PER_STATE);
+                            buffered_states.write().unwrap().truncate(*position);
+                            continue 'main;
+                        }
+                    }
+
+                    snapshots.clear();
                     apu = Apu::from_spc(spc.as_ref().unwrap());
                     apu.clear_echo_buffer();
                     apu.set_resampling_mode(mode);
@@ -123,6 +140,11 @@ fn spawn_emulator_thread(channel: mpsc::Receiver<EmulatorThreadMessage>, buffere
             }
 
             if spc.is_some() && seek_position.load(Ordering::Acquire).max(minimum_end_position) >= buffered_audio.read().unwrap().len().saturating_sub(1024000) {
+                let state_count = buffered_states.read().unwrap().len();
+                if state_count % STATES_PER_SNAPSHOT == 0 && snapshots.last().map_or(true, |(position, _)| *position < state_count) {
+                    snapshots.push((state_count, apu.save_state()));
+                }
+
                 for _ in 0..100 {
                     let mut l_audio_buffer = [0i16; SAMPLES_PER_STATE];
                     let mut r_audio_buffer = [0i16; SAMPLES_PER_STATE];
@@ -1,17 +1,24 @@
 use anyhow::{Result, Context, bail};
-use clap::{arg, ArgAction, value_parser, Command};
-use std::path::PathBuf;
+use clap::{arg, ArgAction, ArgMatches, value_parser, Command};
+use clap::parser::ValueSource;
+use std::path::{Path, PathBuf};
 use indicatif::{FormattedDuration, HumanBytes, ProgressBar, ProgressStyle};
 use std::fmt::Write;
 use std::fs;
+use std::io::{self, Read};
 use std::str::FromStr;
+use std::time::Duration;
+use std::mem;
 use csscolorparser::Color as CssColor;
 use tiny_skia::Color;
 use snes_apu_spcp::ResamplingMode;
+use spc_spcp::spc::{ExtendedId666Chunk, ExtendedId666Data, Id666Tag, Spc};
 use crate::config::Config;
-use crate::renderer::{Renderer, render_options::{RendererOptions, StopCondition}};
-use crate::sample_processing::{SampleProcessor, SampleProcessorProgress};
+use crate::emulator::{Emulator, split_track_selector, list_tracks, load_spc, encode_brr, PortTimeline};
+use crate::renderer::{Renderer, skip_to_start, skip_to_loop_start, render_options::{RendererOptions, StartCondition, StopCondition}};
+use crate::sample_processing::{SampleProcessor, SampleProcessorProgress, dump_all_samples, read_wav};
 use crate::tuning;
+use crate::tuning::tuning_file::TuningFile;
 
 fn codec_option_value_parser(s: &str) -> Result<(String, String)> {
     let (key, value) = s.split_once('=')
@@ -49,10 +56,10 @@ fn sample_tuning_value_parser<'a>(s: &'a str) -> Result<(u8, f64)> {
             let (raw_tuning, raw_subtuning) = raw_params.split_once(',')
                 .context("Invalid AddMusicK tuning parameters (must be of the form 'tuning,subtuning').")?;
 
-            let tuning = sample_tuning_numeric_parser(raw_tuning)? as f64;
-            let subtuning = sample_tuning_numeric_parser(raw_subtuning)? as f64;
+            let tuning = sample_tuning_numeric_parser(raw_tuning)?;
+            let subtuning = sample_tuning_numeric_parser(raw_subtuning)?;
 
-            32000.0 / (16.0 * (tuning + subtuning / 256.0))
+            tuning::amk_tuning_frequency(tuning, subtuning)
         }
         invalid => bail!("Invalid tuning type '{}' (must be one of 'hz', 'amk').", invalid)
     };
@@ -86,8 +93,113 @@ fn resampling_mode_parser(s: &str) -> Result<ResamplingMode> {
     }
 }
 
-fn get_renderer_options() -> RendererOptions {
-    let matches = Command::new("SPCPresenter")
+fn stems_format_parser(s: &str) -> Result<String> {
+    match s {
+        "wav" | "flac" => Ok(s.to_string()),
+        invalid => bail!("Invalid stems format '{}' (must be one of 'wav', 'flac').", invalid)
+    }
+}
+
+fn seconds_parser(s: &str) -> Result<Duration> {
+    let seconds = f64::from_str(s).with_context(|| format!("Invalid length '{}'", s))?;
+    Duration::try_from_secs_f64(seconds).with_context(|| format!("Invalid length '{}' (must not be negative).", s))
+}
+
+fn channel_list_parser(s: &str) -> Result<Vec<usize>> {
+    s.split(',')
+        .map(|channel_str| {
+            let channel = usize::from_str(channel_str.trim())
+                .with_context(|| format!("Invalid channel number {}", channel_str))?;
+            if !(1..=8).contains(&channel) {
+                bail!("Invalid channel number {} (must be between 1 and 8).", channel);
+            }
+            Ok(channel - 1)
+        })
+        .collect()
+}
+
+fn get_matches() -> ArgMatches {
+    Command::new("SPCPresenter")
+        .subcommand_negates_reqs(true)
+        .args_conflicts_with_subcommands(true)
+        .subcommand(Command::new("info")
+            .about("Show an SPC's tags and N-SPC driver version")
+            .arg(arg!(<spc> "SPC to inspect (SPC2 and ZIP files list every song)")
+                .value_parser(value_parser!(PathBuf))
+                .required(true)))
+        .subcommand(Command::new("dump-samples")
+            .about("Export every sample in the SPC's source directory as BRR and WAV files")
+            .arg(arg!(<spc> "SPC to dump the samples of")
+                .value_parser(value_parser!(PathBuf))
+                .required(true))
+            .arg(arg!(<output_dir> "Directory to write the samples to")
+                .value_parser(value_parser!(PathBuf))
+                .required(true)))
+        .subcommand(Command::new("encode-brr")
+            .about("Encode a WAV file as BRR, optionally patching it into an SPC")
+            .arg(arg!(<input> "WAV file to encode")
+                .value_parser(value_parser!(PathBuf))
+                .required(true))
+            .arg(arg!(<output> "BRR file to write (with a loop header if the sample loops)")
+                .value_parser(value_parser!(PathBuf))
+                .required(true))
+            .arg(arg!(--loop <N> "Loop the sample from this sample index (defaults to the WAV's loop, if any)")
+                .value_parser(value_parser!(usize))
+                .required(false))
+            .arg(arg!(--"anti-clip" "Attenuate loud samples so the filters cannot overflow on playback")
+                .action(ArgAction::SetTrue)
+                .required(false))
+            .arg(arg!(--"patch-spc" <SPC> "Replace a sample in this SPC with the encoded one")
+                .value_parser(value_parser!(PathBuf))
+                .requires_all(["source", "patched-spc"])
+                .required(false))
+            .arg(arg!(--source <INDEX> "Source directory index of the sample to replace")
+                .value_parser(sample_tuning_numeric_parser)
+                .requires("patch-spc")
+                .required(false))
+            .arg(arg!(--"patched-spc" <FILE> "Where to save the patched SPC")
+                .value_parser(value_parser!(PathBuf))
+                .requires("patch-spc")
+                .required(false)))
+        .subcommand(Command::new("tag")
+            .about("Edit an SPC's ID666 and xid6 tags")
+            .arg(arg!(<spc> "SPC to edit")
+                .value_parser(value_parser!(PathBuf))
+                .required(true))
+            .arg(arg!(-o --output <FILE> "Save the edited SPC here instead of overwriting it")
+                .value_parser(value_parser!(PathBuf))
+                .required(false))
+            .arg(arg!(--title <TITLE> "Song title").required(false))
+            .arg(arg!(--game <TITLE> "Game title").required(false))
+            .arg(arg!(--artist <NAME> "Artist name").required(false))
+            .arg(arg!(--dumper <NAME> "Dumper name").required(false))
+            .arg(arg!(--comments <TEXT> "Comments").required(false))
+            .arg(arg!(--length <SECS> "Play time before the fadeout (the introduction length if the song has a loop length)")
+                .value_parser(seconds_parser)
+                .required(false))
+            .arg(arg!(--fadeout <SECS> "Fadeout length")
+                .value_parser(seconds_parser)
+                .required(false))
+            .arg(arg!(--"loop-length" <SECS> "Length of one loop of the song")
+                .value_parser(seconds_parser)
+                .required(false))
+            .arg(arg!(--"end-length" <SECS> "Length of the song's ending after the last loop")
+                .value_parser(seconds_parser)
+                .required(false))
+            .arg(arg!(--"loop-count" <N> "Number of times to play the loop")
+                .value_parser(value_parser!(u16))
+                .required(false)))
+        .subcommand(Command::new("save-spc")
+            .about("Run an SPC up to a point and save the state there as a new SPC, keeping its tags")
+            .arg(arg!(<spc> "SPC to run")
+                .value_parser(value_parser!(PathBuf))
+                .required(true))
+            .arg(arg!(<output> "SPC file to write")
+                .value_parser(value_parser!(PathBuf))
+                .required(true))
+            .arg(arg!(--at <CONDITION> "Where to save the state (time:SECS, frames:N, first-note, or loop for the start of the first loop)")
+                .required(false)
+                .default_value("loop")))
         .arg(arg!(-c --"video-codec" <CODEC> "Set the output video codec")
             .required(false)
             .default_value("libx264"))
@@ -104,6 +216,9 @@ fn get_renderer_options() -> RendererOptions {
             .required(false)
             .value_parser(value_parser!(i32))
             .default_value("44100"))
+        .arg(arg!(--"start-at" <CONDITION> "Set the start condition (time:SECS, frames:N, or first-note)")
+            .required(false)
+            .value_parser(value_parser!(StartCondition)))
         .arg(arg!(-s --"stop-at" <CONDITION> "Set the stop condition")
             .required(false)
             .value_parser(value_parser!(StopCondition))
@@ -112,6 +227,9 @@ fn get_renderer_options() -> RendererOptions {
             .required(false)
             .value_parser(value_parser!(u64))
             .default_value("180"))
+        .arg(arg!(--"stems" <FORMAT> "Also export per-voice audio stems next to the output video (wav, flac)")
+            .required(false)
+            .value_parser(stems_format_parser))
         .arg(arg!(--"ow" <WIDTH> "Set the output video width")
             .required(false)
             .value_parser(value_parser!(u32))
@@ -142,30 +260,69 @@ fn get_renderer_options() -> RendererOptions {
         .arg(arg!(--"super-midi-pak-session" <SESSIONJSON> "Tune samples using a Super MIDI Pak session JSON file")
             .required(false)
             .value_parser(value_parser!(PathBuf)))
+        .arg(arg!(--"tuning-file" <FILE> "Tune, name and color samples using a tuning file (TOML, or JSON if it ends in .json)")
+            .required(false)
+            .value_parser(value_parser!(PathBuf)))
+        .arg(arg!(--"mute" <CHANNELS> "Mute channels in the audio (comma-separated, 1-8)")
+            .required(false)
+            .value_parser(channel_list_parser))
+        .arg(arg!(--"solo" <CHANNELS> "Only play these channels in the audio (comma-separated, 1-8)")
+            .required(false)
+            .value_parser(channel_list_parser))
         .arg(arg!(-B --"background" <BACKGROUND> "Set the output background")
             .required(false)
             .value_parser(value_parser!(PathBuf)))
         .arg(arg!(--"no-dim" "Disable background dimming")
             .required(false)
             .action(ArgAction::SetTrue))
+        .arg(arg!(--"trace" <FILE> "Log every SPC700 instruction executed during the render to a file")
+            .required(false)
+            .value_parser(value_parser!(PathBuf)))
+        .arg(arg!(--"port-timeline" <FILE> "Write to the APU input ports at set frames while rendering (TOML or JSON)")
+            .required(false)
+            .value_parser(value_parser!(PathBuf)))
+        .arg(arg!(--"export-midi" <FILE> "Write the notes played by each voice to a Standard MIDI File")
+            .required(false)
+            .value_parser(value_parser!(PathBuf)))
+        .arg(arg!(--"register-log" <FILE> "Record every DSP register and I/O port write during the render to a file")
+            .required(false)
+            .value_parser(value_parser!(PathBuf)))
+        .arg(arg!(--"ignore-xid6" "Ignore the muted voices and preamp level set in the SPC's tags")
+            .required(false)
+            .action(ArgAction::SetTrue))
+        .arg(arg!(--"no-sample-cache" "Analyze every sample again instead of reusing earlier results")
+            .required(false)
+            .action(ArgAction::SetTrue))
         .arg(arg!(-i --"import-config" <CONFIGFILE> "Import configuration from a RusticNES TOML file")
             .value_parser(value_parser!(PathBuf))
             .required(false))
         .arg(arg!(-'7' --"script700" <SCRIPT> "Load Script700 file")
             .value_parser(value_parser!(PathBuf))
             .required(false))
-        .arg(arg!(<spc> "SPC to render")
+        .arg(arg!(<spc> "SPC to render (use file.sp2#N or file.zip#name.spc to select a song from an archive, or - to read from standard input)")
             .value_parser(value_parser!(PathBuf))
             .required(true))
         .arg(arg!(<output> "Output video file")
             .value_parser(value_parser!(PathBuf))
             .required(true))
-        .get_matches();
+        .get_matches()
+}
+
+fn numbered_output_path(output_path: &str, track: usize) -> String {
+    let path = Path::new(output_path);
+    let stem = path.file_stem().unwrap_or_default().to_str().unwrap();
+    let file_name = match path.extension() {
+        Some(extension) => format!("{}_{:02}.{}", stem, track, extension.to_str().unwrap()),
+        None => format!("{}_{:02}", stem, track)
+    };
+    path.with_file_name(file_name).to_str().unwrap().to_string()
+}
 
+fn get_renderer_options(matches: &ArgMatches, input_path: String, output_path: String, spc_data: Option<&[u8]>) -> RendererOptions {
     let mut options = RendererOptions::default();
 
-    options.input_path = matches.get_one::<PathBuf>("spc").cloned().unwrap().to_str().unwrap().to_string();
-    options.video_options.output_path = matches.get_one::<PathBuf>("output").cloned().unwrap().to_str().unwrap().to_string();
+    options.input_path = input_path;
+    options.video_options.output_path = output_path;
     options.video_options.video_codec = matches.get_one::<String>("video-codec").cloned().unwrap();
     options.video_options.audio_codec = matches.get_one::<String>("audio-codec").cloned().unwrap();
     options.video_options.pixel_format_out = matches.get_one::<String>("pixel-format").cloned().unwrap();
@@ -175,8 +332,17 @@ fn get_renderer_options() -> RendererOptions {
     options.video_options.sample_rate = sample_rate;
     options.video_options.audio_time_base = (1, sample_rate).into();
 
+    if let Some(start_condition) = matches.get_one::<StartCondition>("start-at").cloned() {
+        options.start_condition = start_condition;
+    }
     options.stop_condition = matches.get_one::<StopCondition>("stop-at").cloned().unwrap();
     options.fadeout_length = matches.get_one::<u64>("stop-fadeout").cloned().unwrap();
+    options.fadeout_length_explicit = matches.value_source("stop-fadeout") == Some(ValueSource::CommandLine);
+    options.stems_format = matches.get_one::<String>("stems").cloned();
+    options.trace_path = matches.get_one::<PathBuf>("trace").map(|path| path.to_str().unwrap().to_string());
+    options.port_timeline_path = matches.get_one::<PathBuf>("port-timeline").map(|path| path.to_str().unwrap().to_string());
+    options.register_log_path = matches.get_one::<PathBuf>("register-log").map(|path| path.to_str().unwrap().to_string());
+    options.midi_path = matches.get_one::<PathBuf>("export-midi").map(|path| path.to_str().unwrap().to_string());
 
     let ow = matches.get_one::<u32>("ow").cloned().unwrap();
     let oh = matches.get_one::<u32>("oh").cloned().unwrap();
@@ -205,12 +371,24 @@ fn get_renderer_options() -> RendererOptions {
         options.script700_path = script700_path.to_str().unwrap().to_string();
     }
 
-    let mut sample_processor = SampleProcessor::from_spc(options.input_path.clone()).expect("Failed to initialize sample processor");
+    let mut sample_processor = match spc_data {
+        Some(spc_data) => SampleProcessor::from_spc_bytes(spc_data),
+        None => SampleProcessor::from_spc(options.input_path.clone())
+    }.expect("Failed to initialize sample processor");
     if !options.script700_path.is_empty() {
         sample_processor.load_script700(&options.script700_path).unwrap();
     }
+    if let Some(port_timeline_path) = &options.port_timeline_path {
+        let port_timeline = PortTimeline::load(port_timeline_path).expect("Failed to load port timeline");
+        sample_processor.set_port_timeline(port_timeline);
+    }
+    sample_processor.set_cache_enabled(!matches.get_flag("no-sample-cache"));
     if let StopCondition::Frames(frames) = options.stop_condition {
-        sample_processor.set_frame_count(frames as usize + options.fadeout_length as usize + 60);
+        let start_frames = match options.start_condition {
+            StartCondition::Frames(start_frames) => start_frames,
+            StartCondition::FirstNote => 0
+        };
+        sample_processor.set_frame_count((start_frames + frames) as usize + options.fadeout_length as usize + 60);
     }
     loop {
         match sample_processor.step().unwrap() {
@@ -220,10 +398,36 @@ fn get_renderer_options() -> RendererOptions {
     }
     options.sample_tunings = sample_processor.finish();
 
+    if matches.get_flag("ignore-xid6") {
+        options.config.emulator.apply_xid6_settings = false;
+    }
+
     if let Some(resampling_mode) = matches.get_one::<ResamplingMode>("interpolation-type").cloned() {
         options.config.emulator.resampling_mode = resampling_mode;
     }
 
+    if let Some(tuning_file_path) = matches.get_one::<PathBuf>("tuning-file") {
+        let tuning_file = TuningFile::load(tuning_file_path).unwrap();
+        for (&source, tuning) in options.sample_tunings.iter_mut() {
+            let Some(entry) = tuning_file.find(source, tuning.sample()) else {
+                continue;
+            };
+            if let Some(name) = &entry.name {
+                println!("Tuning sample ${:02x} ({})", source, name);
+            }
+
+            if let Some(pitch) = entry.pitch() {
+                tuning.set_custom_tuning(Some(pitch));
+            }
+            if let Some(octave_offset) = entry.octave_offset {
+                tuning.set_temporal_pitch_octave_offset(octave_offset as f64);
+            }
+            if let Some(color) = entry.color() {
+                options.per_sample_colors.insert(source, color);
+            }
+            tuning.set_percussion(entry.percussion);
+        }
+    }
     if let Some(super_midi_pak_session_path) = matches.get_one::<PathBuf>("super-midi-pak-session").cloned() {
         let session_json = fs::read_to_string(super_midi_pak_session_path).unwrap();
         let session = tuning::super_midi_pak_session::SuperMidiPakSession::from_json(session_json.as_str()).unwrap();
@@ -247,6 +451,16 @@ fn get_renderer_options() -> RendererOptions {
             options.per_sample_colors.insert(sample_index, color);
         }
     }
+    if let Some(channels) = matches.get_one::<Vec<usize>>("mute") {
+        for &channel in channels.iter() {
+            options.config.piano_roll.settings.settings_mut(channel).unwrap().set_muted(true);
+        }
+    }
+    if let Some(channels) = matches.get_one::<Vec<usize>>("solo") {
+        for &channel in channels.iter() {
+            options.config.piano_roll.settings.settings_mut(channel).unwrap().set_solo(true);
+        }
+    }
     if let Some(background_path) = matches.get_one::<PathBuf>("background").cloned() {
         options.video_options.background_path = Some(background_path.to_str().unwrap().to_string());
     }
@@ -255,9 +469,216 @@ fn get_renderer_options() -> RendererOptions {
     options
 }
 
+fn print_spc_info(path: &str, spc: &Spc) {
+    let metadata = spc.metadata();
+
+    println!("{}", path);
+    let fields = [
+        ("Title", metadata.song_title()),
+        ("Game", metadata.game_title()),
+        ("Artist", metadata.artist_name()),
+        ("Dumper", metadata.dumper_name())
+    ];
+    for (label, value) in fields {
+        if let Some(value) = value.filter(|value| !value.is_empty()) {
+            println!("  {:<8}{}", format!("{}:", label), value);
+        }
+    }
+    if let Some((play_time, fadeout_time)) = metadata.play_time(None) {
+        println!("  Length: {} (fade {})", FormattedDuration(play_time), FormattedDuration(fadeout_time));
+    }
+    match spc.nspc_driver() {
+        Some(driver) => println!("  Driver: {}", driver),
+        None => println!("  Driver: not N-SPC")
+    }
+}
+
+fn info(input_path: &str) -> Result<()> {
+    let paths = match split_track_selector(input_path) {
+        (file_path, None) => list_tracks(file_path)?
+            .map(|tracks| tracks.into_iter().map(|track| track.path).collect()),
+        (_, Some(_)) => None
+    }.unwrap_or_else(|| vec![input_path.to_string()]);
+
+    for path in paths {
+        print_spc_info(&path, &load_spc(&path)?);
+    }
+    Ok(())
+}
+
+fn encode_brr(matches: &ArgMatches) -> Result<()> {
+    let input_path = matches.get_one::<PathBuf>("input").unwrap();
+    let output_path = matches.get_one::<PathBuf>("output").unwrap();
+
+    let wav = read_wav(input_path)?;
+    if wav.sample_rate != 32000 {
+        println!("Note: {} is {} Hz, but BRR samples play at 32000 Hz with pitch $1000", input_path.display(), wav.sample_rate);
+    }
+
+    let loop_start = matches.get_one::<usize>("loop").cloned().or(wav.loop_start);
+    let sample = encode_brr(&wav.samples, loop_start, matches.get_flag("anti-clip"))?;
+    fs::write(output_path, sample.to_bytes())?;
+    println!("Encoded {} blocks to {}", sample.start_block_count() + sample.loop_block_count(), output_path.display());
+
+    if let Some(spc_path) = matches.get_one::<PathBuf>("patch-spc") {
+        let source = *matches.get_one::<u8>("source").unwrap();
+        let patched_path = matches.get_one::<PathBuf>("patched-spc").unwrap();
+
+        let mut spc = load_spc(spc_path)?;
+        sample.write_to_spc(&mut spc, source)?;
+        spc.save(patched_path)?;
+        println!("Replaced source ${:02x} in {}", source, patched_path.display());
+    }
+
+    Ok(())
+}
+
+// Argument name, ID666 field length, ID666 field, and xid6 item
+type TextTagField = (&'static str, usize, fn(&mut Id666Tag) -> &mut String, fn(String) -> ExtendedId666Chunk);
+
+// The xid6 item takes precedence over the ID666 field when reading, so update it if there is one,
+// and add one if the value doesn't fit in the ID666 field
+fn update_xid6_item(spc: &mut Spc, chunk: ExtendedId666Chunk, fits_id666: bool) {
+    let has_item = spc.extended_id666.as_ref()
+        .is_some_and(|xid6| xid6.iter().any(|item| mem::discriminant(item) == mem::discriminant(&chunk)));
+
+    if has_item || !fits_id666 {
+        spc.extended_id666.get_or_insert_with(|| ExtendedId666Data::new(Vec::new())).set(chunk);
+    }
+}
+
+fn edit_tags(matches: &ArgMatches) -> Result<()> {
+    let input_path = matches.get_one::<PathBuf>("spc").unwrap();
+    let output_path = match matches.get_one::<PathBuf>("output") {
+        Some(output_path) => output_path,
+        None if split_track_selector(input_path.to_str().unwrap()).1.is_some() || list_tracks(input_path)?.is_some() => {
+            bail!("Songs in SPC2 and ZIP files can't be edited in place, use -o to save a copy");
+        },
+        None => input_path
+    };
+    let mut spc = load_spc(input_path)?;
+
+    let text_fields: [TextTagField; 5] = [
+        ("title", 32, |tag| &mut tag.song_title, ExtendedId666Chunk::SongTitle),
+        ("game", 32, |tag| &mut tag.game_title, ExtendedId666Chunk::GameTitle),
+        ("artist", 32, |tag| &mut tag.artist_name, ExtendedId666Chunk::ArtistName),
+        ("dumper", 16, |tag| &mut tag.dumper_name, ExtendedId666Chunk::DumperName),
+        ("comments", 32, |tag| &mut tag.comments, ExtendedId666Chunk::Comments)
+    ];
+    for (name, id666_len, field, chunk) in text_fields {
+        if let Some(value) = matches.get_one::<String>(name) {
+            *field(spc.id666_tag.get_or_insert_with(Id666Tag::default)) = value.clone();
+            update_xid6_item(&mut spc, chunk(value.clone()), value.len() <= id666_len);
+        }
+    }
+
+    if let Some(&length) = matches.get_one::<Duration>("length") {
+        spc.id666_tag.get_or_insert_with(Id666Tag::default).play_time = length;
+        let fits_id666 = length.subsec_nanos() == 0 && length.as_secs() <= 999;
+        update_xid6_item(&mut spc, ExtendedId666Chunk::IntroductionLength(length), fits_id666);
+    }
+    if let Some(&fadeout) = matches.get_one::<Duration>("fadeout") {
+        spc.id666_tag.get_or_insert_with(Id666Tag::default).fadeout_time = fadeout;
+        let fits_id666 = fadeout.subsec_nanos() % 1_000_000 == 0 && fadeout.as_millis() <= 99999;
+        update_xid6_item(&mut spc, ExtendedId666Chunk::FadeoutLength(fadeout), fits_id666);
+    }
+    if let Some(&loop_length) = matches.get_one::<Duration>("loop-length") {
+        update_xid6_item(&mut spc, ExtendedId666Chunk::LoopLength(loop_length), false);
+    }
+    if let Some(&end_length) = matches.get_one::<Duration>("end-length") {
+        update_xid6_item(&mut spc, ExtendedId666Chunk::EndLength(end_length), false);
+    }
+    if let Some(&loop_count) = matches.get_one::<u16>("loop-count") {
+        update_xid6_item(&mut spc, ExtendedId666Chunk::PreferredLoopCount(loop_count), false);
+    }
+
+    spc.save(output_path)?;
+    print_spc_info(output_path.to_str().unwrap(), &spc);
+    Ok(())
+}
+
+fn save_spc(matches: &ArgMatches) -> Result<()> {
+    let input_path = matches.get_one::<PathBuf>("spc").unwrap();
+    let output_path = matches.get_one::<PathBuf>("output").unwrap();
+
+    let mut emulator = Emulator::from_spc(input_path, 44_100)?;
+    emulator.init();
+
+    let frames = match matches.get_one::<String>("at").unwrap().as_str() {
+        "loop" => skip_to_loop_start(&mut emulator)?,
+        condition => skip_to_start(&mut emulator, StartCondition::from_str(condition).map_err(anyhow::Error::msg)?)?
+    };
+
+    emulator.save_spc(output_path)?;
+    println!("Saved the state at {} to {}", FormattedDuration(Duration::from_secs_f64(frames as f64 / 60.0)), output_path.display());
+    Ok(())
+}
+
 pub fn run() {
-    let options = get_renderer_options();
-    let mut renderer = Renderer::new(options).unwrap();
+    let matches = get_matches();
+    if let Some(("info", info_matches)) = matches.subcommand() {
+        let input_path = info_matches.get_one::<PathBuf>("spc").unwrap().to_str().unwrap();
+        info(input_path).expect("Failed to read input file");
+        return;
+    }
+    if let Some(("dump-samples", dump_matches)) = matches.subcommand() {
+        let input_path = dump_matches.get_one::<PathBuf>("spc").unwrap();
+        let output_dir = dump_matches.get_one::<PathBuf>("output_dir").unwrap();
+        let sample_count = dump_all_samples(input_path, output_dir).expect("Failed to dump samples");
+        println!("Dumped {} samples to {}", sample_count, output_dir.display());
+        return;
+    }
+    if let Some(("encode-brr", encode_matches)) = matches.subcommand() {
+        encode_brr(encode_matches).expect("Failed to encode sample");
+        return;
+    }
+    if let Some(("tag", tag_matches)) = matches.subcommand() {
+        edit_tags(tag_matches).expect("Failed to edit tags");
+        return;
+    }
+    if let Some(("save-spc", save_matches)) = matches.subcommand() {
+        save_spc(save_matches).expect("Failed to save SPC");
+        return;
+    }
+
+    let input_path = matches.get_one::<PathBuf>("spc").cloned().unwrap().to_str().unwrap().to_string();
+    let output_path = matches.get_one::<PathBuf>("output").cloned().unwrap().to_str().unwrap().to_string();
+
+    if input_path == "-" {
+        let mut spc_data = Vec::new();
+        io::stdin().read_to_end(&mut spc_data).expect("Failed to read SPC from standard input");
+        render(get_renderer_options(&matches, input_path, output_path, Some(&spc_data)), Some(&spc_data));
+        return;
+    }
+
+    // An SPC2 or ZIP file without a track selector renders every song in the file
+    let tracks = match split_track_selector(&input_path) {
+        (file_path, None) => list_tracks(file_path).expect("Failed to read input file"),
+        (_, Some(_)) => None
+    };
+
+    match tracks {
+        Some(tracks) => {
+            for (i, track) in tracks.iter().enumerate() {
+                println!("Rendering song {}/{}: {}", i + 1, tracks.len(), track.title);
+                let options = get_renderer_options(
+                    &matches,
+                    track.path.clone(),
+                    numbered_output_path(&output_path, i + 1),
+                    None
+                );
+                render(options, None);
+            }
+        },
+        None => render(get_renderer_options(&matches, input_path, output_path, None), None)
+    }
+}
+
+fn render(options: RendererOptions, spc_data: Option<&[u8]>) {
+    let mut renderer = match spc_data {
+        Some(spc_data) => Renderer::from_spc_bytes(options, spc_data),
+        None => Renderer::new(options)
+    }.unwrap();
 
     let pb = ProgressBar::new(0);
     let pb_style_initial = ProgressStyle::with_template("{msg}\n{spinner} Waiting for loop detection...")
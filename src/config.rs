@@ -106,6 +106,7 @@ fn deserialize_resampling_mode<'de, D: Deserializer<'de>>(deserializer: D) -> Re
 #[serde(default)]
 pub struct EmulatorConfig {
     pub filter_enabled: bool,
+    pub apply_xid6_settings: bool,
     #[serde(serialize_with = "serialize_resampling_mode", deserialize_with = "deserialize_resampling_mode")]
     pub resampling_mode: ResamplingMode
 }
@@ -114,6 +115,7 @@ impl Default for EmulatorConfig {
     fn default() -> Self {
         Self {
             filter_enabled: true,
+            apply_xid6_settings: true,
             resampling_mode: ResamplingMode::default()
         }
     }
@@ -0,0 +1,150 @@
+use anyhow::{Result, bail};
+use super::brr_sample::{decode_brr_sample, BrrSample, BrrSampleBuilder};
+
+const BLOCK_SAMPLES: usize = 16;
+const MAX_SHIFT: u8 = 12;
+// Peak level targeted by the anti-clipping pass. Prediction filters overshoot near full scale,
+// which wraps around in the S-DSP's 15-bit decoder and overflows the Gaussian interpolator.
+const ANTI_CLIP_PEAK: f64 = 0x7000 as f64;
+
+#[derive(Clone, Copy)]
+struct EncodedBlock {
+    header: u8,
+    nybbles: [u8; BLOCK_SAMPLES],
+    error: u64,
+    last_sample: i16,
+    last_last_sample: i16
+}
+
+impl EncodedBlock {
+    fn to_bytes(self) -> [u8; 9] {
+        let mut result = [self.header, 0, 0, 0, 0, 0, 0, 0, 0];
+        for (i, pair) in self.nybbles.chunks_exact(2).enumerate() {
+            result[i + 1] = (pair[0] << 4) | pair[1];
+        }
+        result
+    }
+}
+
+// Picks each nybble greedily so the decoded sample lands as close to the input as possible
+fn encode_block_with(samples: &[i16], shift: u8, filter: u8, mut last_sample: i16, mut last_last_sample: i16) -> EncodedBlock {
+    let mut nybbles = [0; BLOCK_SAMPLES];
+    let mut error = 0;
+
+    for (nybble_out, &target) in nybbles.iter_mut().zip(samples) {
+        let (nybble, decoded, sample_error) = (-8..=7)
+            .map(|nybble| {
+                let decoded = decode_brr_sample(nybble, shift, filter, last_sample, last_last_sample);
+                let difference = decoded as i64 - target as i64;
+                (nybble, decoded, (difference * difference) as u64)
+            })
+            .min_by_key(|&(_, _, sample_error)| sample_error)
+            .unwrap();
+
+        *nybble_out = (nybble & 0x0f) as u8;
+        error += sample_error;
+        last_last_sample = last_sample;
+        last_sample = decoded;
+    }
+
+    EncodedBlock {
+        header: (shift << 4) | (filter << 2),
+        nybbles,
+        error,
+        last_sample,
+        last_last_sample
+    }
+}
+
+fn encode_block(samples: &[i16], allowed_filters: &[u8], last_sample: i16, last_last_sample: i16) -> EncodedBlock {
+    allowed_filters.iter()
+        .flat_map(|&filter| (0..=MAX_SHIFT).map(move |shift| (shift, filter)))
+        .map(|(shift, filter)| encode_block_with(samples, shift, filter, last_sample, last_last_sample))
+        .min_by_key(|block| block.error)
+        .unwrap()
+}
+
+fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+// BRR loops can only start on a block boundary and must span whole blocks. Pad the start with
+// silence so the loop point lands on a boundary, then repeat the loop until it fills whole blocks.
+fn align_loop(samples: &[i16], loop_start: Option<usize>) -> Result<(Vec<i16>, Option<usize>)> {
+    let Some(loop_start) = loop_start else {
+        let mut aligned = samples.to_vec();
+        aligned.resize(samples.len().div_ceil(BLOCK_SAMPLES) * BLOCK_SAMPLES, 0);
+        return Ok((aligned, None));
+    };
+
+    if loop_start >= samples.len() {
+        bail!("Loop point {} is past the end of the sample ({} samples)", loop_start, samples.len());
+    }
+
+    let padding = (BLOCK_SAMPLES - loop_start % BLOCK_SAMPLES) % BLOCK_SAMPLES;
+    let loop_samples = &samples[loop_start..];
+    let repeats = BLOCK_SAMPLES / gcd(loop_samples.len(), BLOCK_SAMPLES);
+
+    let mut aligned = vec![0; padding];
+    aligned.extend_from_slice(&samples[..loop_start]);
+    for _ in 0..repeats {
+        aligned.extend_from_slice(loop_samples);
+    }
+
+    Ok((aligned, Some(loop_start + padding)))
+}
+
+fn prevent_clipping(samples: &mut [i16]) {
+    let peak = samples.iter().map(|&s| (s as f64).abs()).fold(0.0, f64::max);
+    if peak <= ANTI_CLIP_PEAK {
+        return;
+    }
+
+    let gain = ANTI_CLIP_PEAK / peak;
+    for sample in samples.iter_mut() {
+        *sample = (*sample as f64 * gain).round() as i16;
+    }
+}
+
+/// Encodes 16-bit PCM as BRR, choosing the filter and shift with the least error for each block.
+/// `loop_start` is the index of the first looped sample; the loop is realigned to whole blocks,
+/// which may add up to 15 samples of leading silence and repeat the loop up to 16 times.
+pub fn encode_brr(samples: &[i16], loop_start: Option<usize>, anti_clip: bool) -> Result<BrrSample> {
+    if samples.is_empty() {
+        bail!("Cannot encode an empty sample");
+    }
+
+    let (mut samples, loop_start) = align_loop(samples, loop_start)?;
+    if anti_clip {
+        prevent_clipping(&mut samples);
+    }
+
+    let loop_block = loop_start.map(|loop_start| loop_start / BLOCK_SAMPLES);
+    let block_count = samples.len() / BLOCK_SAMPLES;
+
+    let mut result = BrrSampleBuilder::new();
+    let (mut last_sample, mut last_last_sample) = (0, 0);
+    for (block_index, block_samples) in samples.chunks_exact(BLOCK_SAMPLES).enumerate() {
+        // The first block and the loop block can be reached with unrelated history, so they
+        // must not depend on the previous samples
+        let allowed_filters: &[u8] = if block_index == 0 || Some(block_index) == loop_block {
+            &[0]
+        } else {
+            &[0, 1, 2, 3]
+        };
+
+        let mut block = encode_block(block_samples, allowed_filters, last_sample, last_last_sample);
+        (last_sample, last_last_sample) = (block.last_sample, block.last_last_sample);
+
+        if block_index == block_count - 1 {
+            block.header |= 0x01 | ((loop_start.is_some() as u8) << 1);
+        }
+
+        match loop_block {
+            Some(loop_block) if block_index >= loop_block => result.add_loop_block(&block.to_bytes()),
+            _ => result.add_start_block(&block.to_bytes())
+        }
+    }
+
+    Ok(result.into_inner())
+}
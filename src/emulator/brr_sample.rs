@@ -1,6 +1,48 @@
 use std::time::Duration;
 use rodio::Source;
 use anyhow::{Result, bail};
+use spc_spcp::spc::{Spc, RAM_LEN};
+use super::DIR_REGISTER;
+
+/// Decodes one 4-bit BRR sample given the previous two decoded samples.
+pub(super) fn decode_brr_sample(nybble: i32, shift: u8, filter: u8, last_sample: i16, last_last_sample: i16) -> i16 {
+    let mut sample = nybble;
+    if shift <= 12 {
+        sample <<= shift;
+        sample >>= 1;
+    } else {
+        sample &= !0x07ff;
+    }
+
+    let p1 = last_sample as i32;
+    let p2 = (last_last_sample >> 1) as i32;
+
+    match filter {
+        1 => {
+            // sample += p1 * 0.46875
+            sample += p1 >> 1;
+            sample += (-p1) >> 5;
+        },
+        2 => {
+            // sample += p1 * 0.953125 - p2 * 0.46875
+            sample += p1;
+            sample -= p2;
+            sample += p2 >> 4;
+            sample += (p1 * -3) >> 6;
+        },
+        3 => {
+            // sample += p1 * 0.8984375 - p2 * 0.40625
+            sample += p1;
+            sample -= p2;
+            sample += (p1 * -13) >> 7;
+            sample += (p2 * 3) >> 4;
+        },
+        _ => ()
+    }
+
+    sample = sample.clamp(i16::MIN as _, i16::MAX as _);
+    (sample << 1) as i16
+}
 
 struct BrrBlockDecoder {
     pub is_end: bool,
@@ -45,44 +87,10 @@ impl BrrBlockDecoder {
             buf_pos += 1;
 
             for _ in 0..4 {
-                let mut sample = ((nybbles as i16) >> 12) as i32;
+                let nybble = ((nybbles as i16) >> 12) as i32;
                 nybbles <<= 4;
 
-                if shift <= 12 {
-                    sample <<= shift;
-                    sample >>= 1;
-                } else {
-                    sample &= !0x07ff;
-                }
-
-                let p1 = self.last_sample as i32;
-                let p2 = (self.last_last_sample >> 1) as i32;
-
-                match filter {
-                    1 => {
-                        // sample += p1 * 0.46875
-                        sample += p1 >> 1;
-                        sample += (-p1) >> 5;
-                    },
-                    2 => {
-                        // sample += p1 * 0.953125 - p2 * 0.46875
-                        sample += p1;
-                        sample -= p2;
-                        sample += p2 >> 4;
-                        sample += (p1 * -3) >> 6;
-                    },
-                    3 => {
-                        // sample += p1 * 0.8984375 - p2 * 0.40625
-                        sample += p1;
-                        sample -= p2;
-                        sample += (p1 * -13) >> 7;
-                        sample += (p2 * 3) >> 4;
-                    },
-                    _ => ()
-                }
-
-                sample = sample.clamp(i16::MIN as _, i16::MAX as _);
-                let sample_16 = (sample << 1) as i16;
+                let sample_16 = decode_brr_sample(nybble, shift, filter, self.last_sample, self.last_last_sample);
                 self.samples[out_pos] = sample_16;
                 out_pos += 1;
                 self.last_last_sample = self.last_sample;
@@ -130,7 +138,7 @@ impl BrrSample {
             ptr += 9;
 
             if let Some(loop_offset) = loop_offset {
-                if loop_offset <= ptr {
+                if loop_offset < ptr {
                     result.add_loop_block(block);
                 }
             }
@@ -141,6 +149,51 @@ impl BrrSample {
         Ok(result.into_inner())
     }
 
+    /// Reads the sample starting at `start_address` the way the S-DSP would play it, or returns
+    /// None if the BRR data (or its loop) runs past the end of RAM without an end block.
+    pub fn from_ram(ram: &[u8], start_address: usize, loop_address: usize) -> Option<Self> {
+        let mut result = BrrSampleBuilder::new();
+
+        let start_blocks = brr_block_chain(ram, start_address)?;
+        for block in start_blocks.chunks_exact(9) {
+            result.add_start_block(block);
+        }
+
+        let loop_flag = (start_blocks[start_blocks.len() - 9] & 0b0000_0010) != 0;
+        if loop_flag {
+            for block in brr_block_chain(ram, loop_address)?.chunks_exact(9) {
+                result.add_loop_block(block);
+            }
+        }
+
+        result.simplify();
+        Some(result.into_inner())
+    }
+
+    /// Writes the sample over the BRR data of `source` in the SPC's RAM and points the source's
+    /// directory entry at it. Fails if the sample is larger than the data it replaces.
+    pub fn write_to_spc(&self, spc: &mut Spc, source: u8) -> Result<()> {
+        let entry_address = spc.regs[DIR_REGISTER] as usize * 0x100 + source as usize * 4;
+        if entry_address + 4 > RAM_LEN {
+            bail!("Source ${:02x} is past the end of RAM", source);
+        }
+
+        let start_address = u16::from_le_bytes(spc.ram[entry_address..(entry_address + 2)].try_into().unwrap()) as usize;
+        let Some(available) = brr_block_chain(&spc.ram, start_address).map(|blocks| blocks.len()) else {
+            bail!("Source ${:02x} does not point to valid BRR data", source);
+        };
+
+        let data = [self.0.as_slice(), self.1.as_slice()].concat();
+        if data.len() > available {
+            bail!("Sample is {} bytes, but source ${:02x} only has room for {} bytes at ${:04x}", data.len(), source, available, start_address);
+        }
+
+        let loop_address = (start_address + self.loop_offset().unwrap_or(0)) as u16;
+        spc.ram[start_address..(start_address + data.len())].copy_from_slice(&data);
+        spc.ram[(entry_address + 2)..(entry_address + 4)].copy_from_slice(&loop_address.to_le_bytes());
+        Ok(())
+    }
+
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut result: Vec<u8> = Vec::with_capacity(self.0.len() + self.1.len() + 2);
 
@@ -187,6 +240,17 @@ impl BrrSample {
     }
 }
 
+pub(super) fn brr_block_chain(ram: &[u8], address: usize) -> Option<&[u8]> {
+    let mut end_address = address;
+    loop {
+        let header = ram.get(end_address..(end_address + 9))?[0];
+        end_address += 9;
+        if (header & 0b0000_0001) != 0 {
+            return Some(&ram[address..end_address]);
+        }
+    }
+}
+
 pub struct BrrSampleBuilder(BrrSample);
 
 impl BrrSampleBuilder {
@@ -1,13 +1,27 @@
 mod resampler;
 mod brr_sample;
+mod brr_encoder;
+mod spc_file;
+mod port_timeline;
 
-use anyhow::Result;
+use anyhow::{Result, anyhow, bail};
 use std::sync::{Arc, Mutex};
-use std::collections::VecDeque;
+use std::collections::{HashSet, VecDeque};
 use std::path::Path;
-use spc_spcp::spc::Spc;
-use snes_apu_spcp::{Apu, ApuStateReceiver, ResamplingMode};
+use std::ops::Range;
+use std::io::{Cursor, Read, Seek};
+use spc_spcp::spc::{Spc, RAM_LEN};
+use snes_apu_spcp::{Apu, ApuStateReceiver, FileTraceSink, ResamplingMode};
 pub use brr_sample::{BrrSample, BrrSampleBuilder};
+pub use brr_encoder::encode_brr;
+pub use spc_file::{TrackInfo, split_track_selector, list_tracks, read_spc, load_spc};
+pub use port_timeline::{PortEvent, PortTimeline};
+
+// One stem per voice, plus the echo return
+pub const STEM_COUNT: usize = 9;
+const DIR_REGISTER: usize = 0x5d;
+// Consecutive invalid directory entries after which the rest of the directory is taken to be unused
+const MAX_INVALID_SOURCES: usize = 4;
 
 pub struct SpcMetadata {
     pub title: String,
@@ -17,18 +31,115 @@ pub struct SpcMetadata {
     pub fadeout_frames: u64
 }
 
+pub struct EmulatorState {
+    frame_count: usize,
+    apu_state: Vec<u8>
+}
+
+impl EmulatorState {
+    pub fn frame_count(&self) -> usize {
+        self.frame_count
+    }
+}
+
 pub struct Emulator {
     spc_file: Spc,
     apu: Box<Apu>,
     frame_count: usize,
     frame_delay: usize,
     sample_buffer: VecDeque<i16>,
-    resampler: resampler::Resampler
+    sample_rate: u32,
+    preamp: f64,
+    tag_mute_mask: u8,
+    mute_mask: u8,
+    solo_mask: u8,
+    resampler: resampler::Resampler,
+    stem_buffers: Vec<VecDeque<i16>>,
+    stem_resamplers: Vec<resampler::Resampler>,
+    port_timeline: PortTimeline,
+    port_timeline_position: usize
+}
+
+fn amplify_samples(buffer: &mut [i16], gain: f64) {
+    for sample in buffer.iter_mut() {
+        *sample = (*sample as f64 * gain).round().clamp(i16::MIN as f64, i16::MAX as f64) as i16;
+    }
+}
+
+/// Returns the address range of the source directory. The directory has no length, so it is
+/// taken to end where the first BRR data after it begins, or at the first entry that points
+/// back into the directory.
+pub fn source_directory_range(spc: &Spc) -> Range<usize> {
+    let dir_address = spc.regs[DIR_REGISTER] as usize * 0x100;
+    let mut end = (dir_address + 256 * 4).min(RAM_LEN);
+
+    let mut entry_address = dir_address;
+    while entry_address + 4 <= end {
+        let entry = &spc.ram[entry_address..(entry_address + 4)];
+        for address in [u16::from_le_bytes([entry[0], entry[1]]), u16::from_le_bytes([entry[2], entry[3]])] {
+            let address = address as usize;
+            if (dir_address..(entry_address + 4)).contains(&address) {
+                return dir_address..entry_address;
+            }
+            if address > entry_address {
+                end = end.min(address);
+            }
+        }
+        entry_address += 4;
+    }
+
+    dir_address..entry_address
+}
+
+fn read_directory_sample(ram: &[u8], directory: &Range<usize>, sample_address: usize, loop_address: usize) -> Result<BrrSample> {
+    if directory.contains(&sample_address) || directory.contains(&loop_address) {
+        bail!("start ${:04x} or loop ${:04x} is inside the source directory", sample_address, loop_address);
+    }
+
+    let Some(start_blocks) = brr_sample::brr_block_chain(ram, sample_address) else {
+        bail!("BRR data at ${:04x} does not end within RAM", sample_address);
+    };
+    let loop_flag = (start_blocks[start_blocks.len() - 9] & 0b0000_0010) != 0;
+    let loop_offset = loop_address.wrapping_sub(sample_address);
+    if loop_flag && (loop_offset >= start_blocks.len() || loop_offset % 9 != 0) {
+        bail!("loop ${:04x} is not a block of the BRR data at ${:04x}", loop_address, sample_address);
+    }
+
+    BrrSample::from_ram(ram, sample_address, loop_address)
+        .ok_or_else(|| anyhow!("BRR data at ${:04x} does not end within RAM", sample_address))
+}
+
+fn drain_samples(buffer: &mut VecDeque<i16>, frame_size: Option<usize>) -> Option<Vec<i16>> {
+    match frame_size {
+        Some(frame_size) => {
+            if buffer.len() < frame_size * 2 {
+                return None;
+            }
+            let result: Vec<i16> = buffer.drain(0..(frame_size * 2)).collect();
+            Some(result)
+        },
+        None => {
+            let result: Vec<i16> = buffer.clone().into_iter().collect();
+            buffer.clear();
+            Some(result)
+        }
+    }
 }
 
 impl Emulator {
     pub fn from_spc<P: AsRef<Path>>(spc_path: P, sample_rate: u32) -> Result<Self> {
-        let spc_file = Spc::load(spc_path)?;
+        Self::from_spc_file(load_spc(spc_path)?, sample_rate)
+    }
+
+    pub fn from_spc_reader<R: Read + Seek>(reader: R, sample_rate: u32) -> Result<Self> {
+        Self::from_spc_file(read_spc(reader, None)?, sample_rate)
+    }
+
+    pub fn from_spc_bytes(data: &[u8], sample_rate: u32) -> Result<Self> {
+        Self::from_spc_reader(Cursor::new(data), sample_rate)
+    }
+
+    pub fn from_spc_file(spc_file: Spc, sample_rate: u32) -> Result<Self> {
         let apu = Apu::from_spc(&spc_file);
 
         Ok(Self {
@@ -37,7 +148,16 @@ impl Emulator {
             frame_count: 0,
             frame_delay: 0,
             sample_buffer: VecDeque::new(),
-            resampler: resampler::Resampler::new(sample_rate)?
+            sample_rate,
+            preamp: 1.0,
+            tag_mute_mask: 0,
+            mute_mask: 0,
+            solo_mask: 0,
+            resampler: resampler::Resampler::new(sample_rate)?,
+            stem_buffers: Vec::new(),
+            stem_resamplers: Vec::new(),
+            port_timeline: PortTimeline::default(),
+            port_timeline_position: 0
         })
     }
 
@@ -45,50 +165,195 @@ impl Emulator {
         Ok(self.apu.load_script700(script_path)?)
     }
 
+    /// Logs every instruction the SMP executes to a file.
+    pub fn set_trace_file<P: AsRef<Path>>(&mut self, trace_path: P) -> Result<()> {
+        let trace_sink = FileTraceSink::create(trace_path)?;
+        self.apu.set_trace_sink(Some(Box::new(trace_sink)));
+        Ok(())
+    }
+
+    /// Starts capturing DSP register and I/O port writes.
+    pub fn start_register_log(&mut self) {
+        self.apu.start_register_log();
+    }
+
+    /// Stops capturing and writes the register log to a file.
+    pub fn save_register_log<P: AsRef<Path>>(&mut self, register_log_path: P) -> Result<()> {
+        if let Some(register_log) = self.apu.stop_register_log() {
+            register_log.save(register_log_path)?;
+        }
+        Ok(())
+    }
+
+    /// Writes to the input ports at the frames given in the timeline, replacing any previous timeline.
+    pub fn set_port_timeline(&mut self, port_timeline: PortTimeline) {
+        self.port_timeline_position = port_timeline.position_at(self.frame_count);
+        self.port_timeline = port_timeline;
+    }
+
+    pub fn load_port_timeline<P: AsRef<Path>>(&mut self, port_timeline_path: P) -> Result<()> {
+        self.set_port_timeline(PortTimeline::load(port_timeline_path)?);
+        Ok(())
+    }
+
+    fn apply_port_timeline(&mut self) {
+        while let Some(event) = self.port_timeline.events.get(self.port_timeline_position) {
+            if event.frame > self.frame_count {
+                break;
+            }
+            for (port, value) in event.writes() {
+                self.apu.write_to_input_port(port, value);
+            }
+            self.port_timeline_position += 1;
+        }
+    }
+
+    /// Number of frames that can be skipped before the next timeline event is due, up to `frames`.
+    fn frames_until_port_event(&self, frames: usize) -> usize {
+        match self.port_timeline.events.get(self.port_timeline_position) {
+            Some(event) => (event.frame - self.frame_count).clamp(1, frames),
+            None => frames
+        }
+    }
+
     pub fn init(&mut self) {
         self.apu.clear_echo_buffer();
     }
 
+    fn frame_sample_count(&self) -> usize {
+        if self.frame_count % 3 == 0 { 534 } else { 533 }
+    }
+
+    fn pending_sample_count(&mut self, frames: usize) -> usize {
+        let mut sample_count = 0;
+        for _ in 0..frames {
+            if self.frame_count >= self.frame_delay {
+                sample_count += self.frame_sample_count();
+            }
+            self.frame_count += 1;
+        }
+        sample_count
+    }
+
+    /// Advances by `frames` frames without producing audio. State receivers are still notified.
+    pub fn skip(&mut self, frames: usize) {
+        let mut remaining = frames;
+        while remaining > 0 {
+            self.apply_port_timeline();
+            let chunk = self.frames_until_port_event(remaining);
+            let sample_count = self.pending_sample_count(chunk);
+            self.apu.skip(sample_count);
+            remaining -= chunk;
+        }
+    }
+
+    /// Advances by `frames` frames without producing audio or notifying state receivers.
+    pub fn fast_forward(&mut self, frames: usize) {
+        let mut remaining = frames;
+        while remaining > 0 {
+            self.apply_port_timeline();
+            let chunk = self.frames_until_port_event(remaining);
+            let sample_count = self.pending_sample_count(chunk);
+            self.apu.fast_forward(sample_count);
+            remaining -= chunk;
+        }
+    }
+
     pub fn step(&mut self) -> Result<()> {
-        let sample_count = if self.frame_count % 3 == 0 { 534 } else { 533 };
+        self.apply_port_timeline();
+        let sample_count = self.frame_sample_count();
 
         let mut l_sample_buffer = vec![0i16; sample_count];
         let mut r_sample_buffer = vec![0i16; sample_count];
         if self.frame_count >= self.frame_delay {
             self.apu.render(&mut l_sample_buffer, &mut r_sample_buffer, sample_count as i32);
+            if self.preamp != 1.0 {
+                amplify_samples(&mut l_sample_buffer, self.preamp);
+                amplify_samples(&mut r_sample_buffer, self.preamp);
+            }
         }
 
-        let mut combined_sample_buffer = self.resampler.run(&l_sample_buffer, &r_sample_buffer)?;
+        let combined_sample_buffer = self.resampler.run(&l_sample_buffer, &r_sample_buffer)?;
         self.sample_buffer.extend(combined_sample_buffer.iter());
 
+        for stem in 0..self.stem_resamplers.len() {
+            if self.frame_count >= self.frame_delay {
+                if stem == STEM_COUNT - 1 {
+                    self.apu.read_echo_output(&mut l_sample_buffer, &mut r_sample_buffer, sample_count);
+                } else {
+                    self.apu.read_voice_output(stem, &mut l_sample_buffer, &mut r_sample_buffer, sample_count);
+                }
+                if self.preamp != 1.0 {
+                    amplify_samples(&mut l_sample_buffer, self.preamp);
+                    amplify_samples(&mut r_sample_buffer, self.preamp);
+                }
+            }
+
+            let combined_stem_buffer = self.stem_resamplers[stem].run(&l_sample_buffer, &r_sample_buffer)?;
+            self.stem_buffers[stem].extend(combined_stem_buffer.iter());
+        }
+
         self.frame_count += 1;
 
         Ok(())
     }
 
+    pub fn save_state(&self) -> EmulatorState {
+        EmulatorState {
+            frame_count: self.frame_count,
+            apu_state: self.apu.save_state()
+        }
+    }
+
+    pub fn load_state(&mut self, state: &EmulatorState) -> Result<()> {
+        self.apu.load_state(&state.apu_state)?;
+        self.frame_count = state.frame_count;
+        self.port_timeline_position = self.port_timeline.position_at(self.frame_count);
+
+        // The resamplers keep a tail of the old audio, which would otherwise bleed into the restored state
+        self.resampler = resampler::Resampler::new(self.sample_rate)?;
+        for stem_resampler in self.stem_resamplers.iter_mut() {
+            *stem_resampler = resampler::Resampler::new(self.sample_rate)?;
+        }
+
+        self.sample_buffer.clear();
+        for stem_buffer in self.stem_buffers.iter_mut() {
+            stem_buffer.clear();
+        }
+
+        Ok(())
+    }
+
     pub fn get_audio_samples(&mut self, frame_size: Option<usize>) -> Option<Vec<i16>> {
-        match frame_size {
-            Some(frame_size) => {
-                if self.sample_buffer.len() < frame_size * 2 {
-                    return None;
-                }
-                let result: Vec<i16> = self.sample_buffer.drain(0..(frame_size * 2)).collect();
-                Some(result)
-            },
-            None => {
-                let result: Vec<i16> = self.sample_buffer.clone().into_iter().collect();
-                self.sample_buffer.clear();
-                Some(result)
+        drain_samples(&mut self.sample_buffer, frame_size)
+    }
+
+    pub fn set_stems_enabled(&mut self, enabled: bool) -> Result<()> {
+        self.apu.set_voice_output_enabled(enabled);
+        self.stem_buffers.clear();
+        self.stem_resamplers.clear();
+
+        if enabled {
+            for _ in 0..STEM_COUNT {
+                self.stem_buffers.push(VecDeque::new());
+                self.stem_resamplers.push(resampler::Resampler::new(self.sample_rate)?);
             }
         }
+
+        Ok(())
+    }
+
+    pub fn get_stem_samples(&mut self, stem: usize, frame_size: Option<usize>) -> Option<Vec<i16>> {
+        drain_samples(self.stem_buffers.get_mut(stem)?, frame_size)
     }
 
     pub fn set_state_receiver(&mut self, state_receiver: Option<Arc<Mutex<dyn ApuStateReceiver>>>) {
         self.apu.set_state_receiver(state_receiver);
     }
 
+    /// Outputs silence for the next `frame_delay` frames.
     pub fn set_frame_delay(&mut self, frame_delay: usize) {
-        self.frame_delay = frame_delay;
+        self.frame_delay = self.frame_count + frame_delay;
     }
 
     pub fn set_resampling_mode(&mut self, resampling_mode: ResamplingMode) {
@@ -99,26 +364,122 @@ impl Emulator {
         self.apu.set_output_filter_enabled(filter_enabled);
     }
 
+    /// Applies the muted voices and preamp level from the SPC's tags, or resets them when disabled.
+    pub fn set_xid6_settings_enabled(&mut self, enabled: bool) {
+        let metadata = self.spc_file.metadata();
+
+        self.tag_mute_mask = match enabled {
+            true => metadata.muted_voices()
+                .unwrap_or_default()
+                .iter()
+                .enumerate()
+                .fold(0, |mask, (voice, &muted)| mask | ((muted as u8) << voice)),
+            false => 0
+        };
+        self.apu.set_voice_mask(self.tag_mute_mask | self.mute_mask, self.solo_mask);
+
+        // Levels outside of the range SNESAPU accepts are ignored
+        self.preamp = match metadata.preamp_level() {
+            Some(level) if enabled && (0x8000..=0x80000).contains(&level) => level as f64 / 65536.0,
+            _ => 1.0
+        };
+    }
+
+    /// Bit N of each mask refers to voice N. Voices muted by the SPC's tags stay muted.
+    pub fn set_voice_mask(&mut self, mute_mask: u8, solo_mask: u8) {
+        self.mute_mask = mute_mask;
+        self.solo_mask = solo_mask;
+        self.apu.set_voice_mask(self.tag_mute_mask | self.mute_mask, self.solo_mask);
+    }
+
+    /// Writes the current state as a new SPC file, keeping the original tags.
+    pub fn save_spc<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let mut spc = self.apu.to_spc();
+        spc.id666_tag = self.spc_file.id666_tag.clone();
+        spc.extended_id666 = self.spc_file.extended_id666.clone();
+        Ok(spc.save(path)?)
+    }
+
+    pub fn spc_file(&self) -> &Spc {
+        &self.spc_file
+    }
+
     pub fn get_spc_metadata(&self) -> Option<SpcMetadata> {
-        if self.spc_file.id666_tag.is_none() {
+        if self.spc_file.id666_tag.is_none() && self.spc_file.extended_id666.is_none() {
             return None;
         }
 
-        let title = self.spc_file.id666_tag.as_ref().unwrap().song_title.clone();
-        let artist = self.spc_file.id666_tag.as_ref().unwrap().artist_name.clone();
-        let game = self.spc_file.id666_tag.as_ref().unwrap().game_title.clone();
-        let duration_frames = (60.0 * self.spc_file.id666_tag.as_ref().unwrap().play_time.as_secs_f64()).round() as u64;
-        let fadeout_frames = (60.0 * self.spc_file.id666_tag.as_ref().unwrap().fadeout_time.as_secs_f64()).round() as u64;
+        let metadata = self.spc_file.metadata();
+        let (duration_frames, fadeout_frames) = self.get_spc_duration(None).unwrap_or_default();
 
         Some(SpcMetadata {
-            title,
-            artist,
-            game,
+            title: metadata.song_title().unwrap_or_default(),
+            artist: metadata.artist_name().unwrap_or_default(),
+            game: metadata.game_title().unwrap_or_default(),
             duration_frames,
             fadeout_frames
         })
     }
 
+    /// Whether the SPC's xid6 tag has an end length, meaning the song ends without a fadeout.
+    pub fn has_spc_end_length(&self) -> bool {
+        self.spc_file.metadata().end_length().is_some()
+    }
+
+    /// Returns the play time and fadeout length in frames, taking xid6 loop and end lengths into account.
+    pub fn get_spc_duration(&self, loop_count: Option<u32>) -> Option<(u64, u64)> {
+        let (play_time, fadeout_time) = self.spc_file.metadata().play_time(loop_count)?;
+        if play_time.is_zero() {
+            return None;
+        }
+
+        let duration_frames = (60.0 * play_time.as_secs_f64()).round() as u64;
+        let fadeout_frames = (60.0 * fadeout_time.as_secs_f64()).round() as u64;
+        Some((duration_frames, fadeout_frames))
+    }
+
+    /// Dumps every entry in the source directory that points to valid BRR data: the data has to
+    /// end within RAM, start and loop outside the directory, and loop back into its own blocks.
+    /// Entries that point to the same data as an earlier entry are skipped, and dumping stops
+    /// at the first run of invalid entries, which is taken to be the end of the directory.
+    pub fn dump_all_samples(&mut self) -> Vec<(u8, BrrSample)> {
+        let spc = self.apu.to_spc();
+        let directory = source_directory_range(&spc);
+
+        let mut seen_entries = HashSet::new();
+        let mut invalid_entries = 0;
+        let mut result = Vec::new();
+        for source in 0..=u8::MAX {
+            let entry_address = directory.start + source as usize * 4;
+            if entry_address + 4 > directory.end {
+                break;
+            }
+
+            let entry = &spc.ram[entry_address..(entry_address + 4)];
+            let sample_address = u16::from_le_bytes([entry[0], entry[1]]) as usize;
+            let loop_address = u16::from_le_bytes([entry[2], entry[3]]) as usize;
+            if !seen_entries.insert((sample_address, loop_address)) {
+                continue;
+            }
+
+            match read_directory_sample(&spc.ram, &directory, sample_address, loop_address) {
+                Ok(sample) => {
+                    invalid_entries = 0;
+                    result.push((source, sample));
+                },
+                Err(e) => {
+                    println!("Skipping source ${:02x}: {}", source, e);
+                    invalid_entries += 1;
+                    if invalid_entries == MAX_INVALID_SOURCES {
+                        break;
+                    }
+                }
+            }
+        }
+
+        result
+    }
+
     pub fn dump_sample(&mut self, source: u8) -> BrrSample {
         let mut result = BrrSampleBuilder::new();
 
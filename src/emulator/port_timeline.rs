@@ -0,0 +1,62 @@
+use anyhow::{Result, Context};
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+
+/// Values written to the APU input ports ($F4-$F7) at the start of a frame.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct PortEvent {
+    pub frame: usize,
+    pub port0: Option<u8>,
+    pub port1: Option<u8>,
+    pub port2: Option<u8>,
+    pub port3: Option<u8>
+}
+
+impl PortEvent {
+    pub fn writes(&self) -> impl Iterator<Item = (usize, u8)> {
+        [self.port0, self.port1, self.port2, self.port3]
+            .into_iter()
+            .enumerate()
+            .filter_map(|(port, value)| Some((port, value?)))
+    }
+}
+
+/// A list of input port writes keyed by frame, for sending commands to a sound driver while
+/// rendering. Frames are counted from when the SPC is loaded, at 60 frames per second.
+///
+/// ```toml
+/// [[events]]
+/// frame = 60
+/// port0 = 0x12
+/// port1 = 0x01
+/// ```
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct PortTimeline {
+    pub events: Vec<PortEvent>
+}
+
+impl PortTimeline {
+    /// Loads a timeline from a TOML file, or a JSON file if the extension is `.json`.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let data = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read port timeline '{}'", path.display()))?;
+
+        let is_json = path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("json"));
+        let mut result: Self = match is_json {
+            true => serde_json::from_str(&data)?,
+            false => toml::from_str(&data)?
+        };
+        // Events on the same frame keep their file order
+        result.events.sort_by_key(|event| event.frame);
+        Ok(result)
+    }
+
+    /// Index of the first event at or after `frame`.
+    pub fn position_at(&self, frame: usize) -> usize {
+        self.events.partition_point(|event| event.frame < frame)
+    }
+}
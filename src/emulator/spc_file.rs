@@ -0,0 +1,176 @@
+use anyhow::{Result, bail, Context};
+use std::fs::File;
+use std::io::{BufReader, Cursor, Read, Seek, SeekFrom};
+use std::path::Path;
+use spc_spcp::spc::Spc;
+use spc_spcp::spc2::Spc2;
+use zip::ZipArchive;
+
+const ZIP_SIGNATURE: &[u8; 4] = b"PK\x03\x04";
+
+/// A single song inside an SPC2 or ZIP archive.
+pub struct TrackInfo {
+    /// Path including the track selector, loadable with [`load_spc`].
+    pub path: String,
+    pub title: String
+}
+
+/// Splits a `file.sp2#track` or `file.zip#entry` path into the file path and the selector.
+/// The split happens at the first `#` that follows an existing file, so file names containing
+/// `#` are left intact.
+pub fn split_track_selector(path: &str) -> (&str, Option<&str>) {
+    if Path::new(path).is_file() {
+        return (path, None);
+    }
+
+    path.match_indices('#')
+        .map(|(i, _)| (&path[..i], &path[(i + 1)..]))
+        .find(|(file_path, _)| Path::new(file_path).is_file())
+        .map_or((path, None), |(file_path, selector)| (file_path, Some(selector)))
+}
+
+fn is_zip<R: Read + Seek>(reader: &mut R) -> Result<bool> {
+    let mut signature = [0u8; 4];
+    reader.seek(SeekFrom::Start(0))?;
+    let result = reader.read_exact(&mut signature).is_ok() && &signature == ZIP_SIGNATURE;
+    reader.seek(SeekFrom::Start(0))?;
+    Ok(result)
+}
+
+fn is_spc_entry_name(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    name.ends_with(".spc") || name.ends_with(".sp2")
+}
+
+fn zip_spc_entries<R: Read + Seek>(archive: &ZipArchive<R>) -> Vec<String> {
+    archive.file_names()
+        .filter(|name| is_spc_entry_name(name))
+        .map(|name| name.to_string())
+        .collect()
+}
+
+fn read_zip_entry<R: Read + Seek>(reader: R, entry_selector: Option<&str>) -> Result<Vec<u8>> {
+    let mut archive = ZipArchive::new(reader)?;
+    let mut entries = zip_spc_entries(&archive);
+    // Keep the order stable regardless of how the archive was written
+    entries.sort();
+
+    let entry_name = match entry_selector {
+        Some(selector) => match selector.parse::<usize>() {
+            Ok(index) => entries.get(index.wrapping_sub(1))
+                .with_context(|| format!("Track {} does not exist (the ZIP file contains {} SPCs)", index, entries.len()))?,
+            Err(_) => entries.iter()
+                .find(|name| name.as_str() == selector)
+                .with_context(|| format!("'{}' is not an SPC in the ZIP file", selector))?
+        },
+        None => entries.first().context("The ZIP file does not contain any SPCs")?
+    }.clone();
+
+    let mut data = Vec::new();
+    archive.by_name(&entry_name)?.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+fn read_spc2_track(spc2: Spc2, track_selector: Option<&str>) -> Result<Spc> {
+    let track = match track_selector {
+        Some(selector) => selector.parse::<usize>()
+            .with_context(|| format!("Invalid SPC2 track number '{}'", selector))?,
+        None => 1
+    };
+
+    let song_count = spc2.len();
+    if track == 0 || track > song_count {
+        bail!("Track {} does not exist (the SPC2 file contains {} songs)", track, song_count);
+    }
+
+    Ok(spc2.songs.into_iter().nth(track - 1).unwrap().spc)
+}
+
+/// Reads an SPC from a plain SPC, an SPC2 archive or a ZIP archive of either.
+/// Archives without a selector load their first song.
+pub fn read_spc<R: Read + Seek>(mut reader: R, selector: Option<&str>) -> Result<Spc> {
+    if is_zip(&mut reader)? {
+        let (entry_selector, inner_selector) = match selector {
+            Some(selector) => match selector.split_once('#') {
+                Some((entry, inner)) => (Some(entry), Some(inner)),
+                None => (Some(selector), None)
+            },
+            None => (None, None)
+        };
+        let data = read_zip_entry(reader, entry_selector)?;
+        return read_spc(Cursor::new(data), inner_selector);
+    }
+
+    if Spc2::is_spc2(&mut reader)? {
+        return read_spc2_track(Spc2::from_reader(reader)?, selector);
+    }
+
+    if selector.is_some() {
+        bail!("Track selectors are only supported for SPC2 and ZIP files");
+    }
+    Ok(Spc::from_reader(reader)?)
+}
+
+/// Loads an SPC from a path, which may include a track selector (see [`split_track_selector`]).
+pub fn load_spc<P: AsRef<Path>>(spc_path: P) -> Result<Spc> {
+    let path = spc_path.as_ref().to_string_lossy();
+    let (file_path, selector) = split_track_selector(&path);
+
+    let file = File::open(file_path)
+        .with_context(|| format!("Failed to open '{}'", file_path))?;
+    read_spc(BufReader::new(file), selector)
+}
+
+fn song_title(spc: &Spc, fallback: &str) -> String {
+    spc.metadata().song_title()
+        .filter(|title| !title.is_empty())
+        .unwrap_or(fallback.to_string())
+}
+
+fn spc2_tracks(spc2: &Spc2, base_path: &str) -> Vec<TrackInfo> {
+    spc2.songs.iter()
+        .enumerate()
+        .map(|(i, song)| TrackInfo {
+            path: format!("{}#{}", base_path, i + 1),
+            title: song_title(&song.spc, &song.file_name)
+        })
+        .collect()
+}
+
+/// Lists the songs in an SPC2 or ZIP archive, or returns `None` if the file is a plain SPC.
+pub fn list_tracks<P: AsRef<Path>>(path: P) -> Result<Option<Vec<TrackInfo>>> {
+    let path = path.as_ref().to_string_lossy();
+    let mut reader = BufReader::new(File::open(path.as_ref())?);
+
+    if Spc2::is_spc2(&mut reader)? {
+        return Ok(Some(spc2_tracks(&Spc2::from_reader(reader)?, &path)));
+    }
+
+    if !is_zip(&mut reader)? {
+        return Ok(None);
+    }
+
+    let mut archive = ZipArchive::new(reader)?;
+    let mut entries = zip_spc_entries(&archive);
+    entries.sort();
+
+    let mut tracks = Vec::new();
+    for entry_name in entries {
+        let mut data = Vec::new();
+        archive.by_name(&entry_name)?.read_to_end(&mut data)?;
+        let mut entry_reader = Cursor::new(data);
+        let entry_path = format!("{}#{}", path, entry_name);
+
+        if Spc2::is_spc2(&mut entry_reader)? {
+            tracks.extend(spc2_tracks(&Spc2::from_reader(entry_reader)?, &entry_path));
+        } else {
+            let spc = Spc::from_reader(entry_reader)?;
+            tracks.push(TrackInfo {
+                path: entry_path,
+                title: song_title(&spc, &entry_name)
+            });
+        }
+    }
+
+    Ok(Some(tracks))
+}
@@ -3,7 +3,7 @@ mod sample_processing_thread;
 mod audio_previewer;
 mod localization;
 
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, mpsc};
 use std::fs;
 use std::path::Path;
 use std::time::Duration;
@@ -20,9 +20,11 @@ use localization::{LocalizationAdapter, fluent_args};
 use fluent::FluentArgs;
 use snes_apu_spcp::{ResamplingMode, search_for_script700_file};
 use crate::config::Config;
+use crate::emulator::{BrrSample, TrackInfo, split_track_selector, list_tracks, load_spc};
 use crate::renderer::render_options::{RendererOptions, StopCondition};
-use crate::sample_processing::SampleProcessorProgress;
+use crate::sample_processing::{SampleProcessorProgress, brr_hash, dump_all_samples, sample_to_wav};
 use crate::tuning;
+use crate::tuning::tuning_file::{SampleTuningEntry, TuningFile};
 
 slint::include_modules!();
 
@@ -49,6 +51,11 @@ fn slint_int_arr<I>(a: I) -> slint::ModelRc<i32>
     slint::ModelRc::new(slint::VecModel::from(int_vec))
 }
 
+fn slint_bool_arr<I: IntoIterator<Item = bool>>(a: I) -> slint::ModelRc<bool> {
+    let bool_vec: Vec<bool> = a.into_iter().collect();
+    slint::ModelRc::new(slint::VecModel::from(bool_vec))
+}
+
 fn slint_color_component_arr<I: IntoIterator<Item = Color>>(a: I) -> slint::ModelRc<slint::ModelRc<i32>> {
     let color_vecs: Vec<slint::ModelRc<i32>> = a.into_iter()
         .map(|c| c.to_color_u8())
@@ -65,11 +72,15 @@ fn browse_for_module_dialog(localization_adapter: Arc<Mutex<LocalizationAdapter>
     let localization_adapter = localization_adapter.lock().unwrap();
     let filter_all = localization_adapter.get("file-dialog-filter-all", None, true);
     let filter_spc = localization_adapter.get("file-dialog-filter-spc", None, true);
+    let filter_spc2 = localization_adapter.get("file-dialog-filter-spc2", None, true);
+    let filter_zip = localization_adapter.get("file-dialog-filter-zip", None, true);
     drop(localization_adapter);
 
     let dialog = FileDialog::new()
-        .add_filter(filter_all.as_str(), &["spc"])
-        .add_filter(filter_spc.as_str(), &["spc"]);
+        .add_filter(filter_all.as_str(), &["spc", "sp2", "zip"])
+        .add_filter(filter_spc.as_str(), &["spc"])
+        .add_filter(filter_spc2.as_str(), &["sp2"])
+        .add_filter(filter_zip.as_str(), &["zip"]);
 
     match dialog.show_open_single_file() {
         Ok(Some(path)) => Some(path.to_str().unwrap().to_string()),
@@ -98,11 +109,13 @@ fn browse_for_background_dialog(localization_adapter: Arc<Mutex<LocalizationAdap
 fn browse_for_tuning_data(localization_adapter: Arc<Mutex<LocalizationAdapter>>) -> Option<String> {
     let localization_adapter = localization_adapter.lock().unwrap();
     let filter_all = localization_adapter.get("file-dialog-filter-all", None, true);
+    let filter_tuning_file = localization_adapter.get("file-dialog-filter-tuning-file", None, true);
     let filter_super_midi_pak = localization_adapter.get("file-dialog-filter-super-midi-pak-session", None, true);
     drop(localization_adapter);
 
     let dialog = FileDialog::new()
-        .add_filter(filter_all.as_str(), &["json"])
+        .add_filter(filter_all.as_str(), &["toml", "json"])
+        .add_filter(filter_tuning_file.as_str(), &["toml", "json"])
         .add_filter(filter_super_midi_pak.as_str(), &["json"]);
 
     match dialog.show_open_single_file() {
@@ -111,6 +124,20 @@ fn browse_for_tuning_data(localization_adapter: Arc<Mutex<LocalizationAdapter>>)
     }
 }
 
+fn browse_for_tuning_export_dialog(localization_adapter: Arc<Mutex<LocalizationAdapter>>) -> Option<String> {
+    let localization_adapter = localization_adapter.lock().unwrap();
+    let filter_tuning_file = localization_adapter.get("file-dialog-filter-tuning-file", None, true);
+    drop(localization_adapter);
+
+    let dialog = FileDialog::new()
+        .add_filter(filter_tuning_file.as_str(), &["toml", "json"]);
+
+    match dialog.show_save_single_file() {
+        Ok(Some(path)) => Some(path.to_str().unwrap().to_string()),
+        _ => None
+    }
+}
+
 fn browse_for_video_dialog(localization_adapter: Arc<Mutex<LocalizationAdapter>>) -> Option<String> {
     let localization_adapter = localization_adapter.lock().unwrap();
     let filter_all = localization_adapter.get("file-dialog-filter-all", None, true);
@@ -189,10 +216,33 @@ fn confirm_prores_export_dialog(localization_adapter: Arc<Mutex<LocalizationAdap
 fn browse_for_dump_dialog(localization_adapter: Arc<Mutex<LocalizationAdapter>>) -> Option<String> {
     let localization_adapter = localization_adapter.lock().unwrap();
     let filter_brr = localization_adapter.get("file-dialog-filter-brr", None, true);
+    let filter_wav = localization_adapter.get("file-dialog-filter-wav", None, true);
+    drop(localization_adapter);
+
+    let dialog = FileDialog::new()
+        .add_filter(filter_brr.as_str(), &["brr"])
+        .add_filter(filter_wav.as_str(), &["wav"]);
+
+    match dialog.show_save_single_file() {
+        Ok(Some(path)) => Some(path.to_str().unwrap().to_string()),
+        _ => None
+    }
+}
+
+fn browse_for_dump_all_dialog() -> Option<String> {
+    match FileDialog::new().show_open_single_dir() {
+        Ok(Some(path)) => Some(path.to_str().unwrap().to_string()),
+        _ => None
+    }
+}
+
+fn browse_for_midi_export_dialog(localization_adapter: Arc<Mutex<LocalizationAdapter>>) -> Option<String> {
+    let localization_adapter = localization_adapter.lock().unwrap();
+    let filter_midi = localization_adapter.get("file-dialog-filter-midi", None, true);
     drop(localization_adapter);
 
     let dialog = FileDialog::new()
-        .add_filter(filter_brr.as_str(), &["brr"]);
+        .add_filter(filter_midi.as_str(), &["mid"]);
 
     match dialog.show_save_single_file() {
         Ok(Some(path)) => Some(path.to_str().unwrap().to_string()),
@@ -219,25 +269,122 @@ fn parse_hex(s: slint::SharedString) -> Option<u16> {
     }
 }
 
-fn get_spc_metadata<P: AsRef<Path>>(spc_path: P) -> Result<(Option<Duration>, slint::ModelRc<slint::SharedString>)> {
-    let spc_file = spc_spcp::spc::Spc::load(spc_path)?;
-
-    let (duration, lines) = match spc_file.id666_tag {
-        Some(metadata) => (
-            Some(metadata.play_time),
-            vec![
-                metadata.song_title,
-                metadata.artist_name,
-                metadata.game_title,
-                metadata.dumper_name
-            ]
-        ),
-        None => (None, vec![])
+fn get_spc_metadata<P: AsRef<Path>>(spc_path: P, localization_adapter: &Arc<Mutex<LocalizationAdapter>>) -> Result<(Option<Duration>, slint::ModelRc<slint::SharedString>)> {
+    let spc_file = load_spc(spc_path)?;
+
+    let duration = spc_file.metadata().play_time(None).map(|(play_time, _fadeout_time)| play_time);
+    let mut lines = match &spc_file.id666_tag {
+        Some(metadata) => vec![
+            metadata.song_title.clone(),
+            metadata.artist_name.clone(),
+            metadata.game_title.clone(),
+            metadata.dumper_name.clone()
+        ],
+        None => vec![]
     };
+    if let Some(driver) = spc_file.nspc_driver() {
+        lines.push(localization_adapter
+            .lock()
+            .unwrap()
+            .get("spc-metadata-nspc-driver", Some(&fluent_args!(driver: driver.to_string())), true));
+    }
 
     Ok((duration, slint_string_arr(lines)))
 }
 
+fn load_module(
+    main_window: &MainWindow,
+    options: &Arc<Mutex<RendererOptions>>,
+    spt_tx: &mpsc::Sender<SampleProcessingThreadRequest>,
+    localization_adapter: &Arc<Mutex<LocalizationAdapter>>,
+    path: String
+) -> bool {
+    let metadata_lines = match get_spc_metadata(&path, localization_adapter) {
+        Ok((_duration, metadata_lines)) => metadata_lines,
+        Err(e) => {
+            let message = localization_adapter
+                .lock()
+                .unwrap()
+                .get("error-message-spc-file-invalid", Some(&fluent_args!(error: e.to_string())), true);
+            display_error_dialog(&message);
+            return false;
+        }
+    };
+
+    options.lock().unwrap().input_path = path.clone();
+    main_window.set_metadata_lines(metadata_lines);
+
+    let script700_path = search_for_script700_file(split_track_selector(&path).0)
+        .and_then(|script700_path| {
+            confirm_load_script700_dialog(localization_adapter.clone(), &script700_path)
+                .then_some(script700_path)
+        })
+        .unwrap_or_default();
+    main_window.set_script700_path(script700_path.file_name().unwrap_or_default().to_str().unwrap().into());
+
+    let script700_path = script700_path.to_str().unwrap().to_string();
+    options.lock().unwrap().script700_path = script700_path.clone();
+
+    main_window.invoke_reformat_duration();
+
+    main_window.invoke_reset_sample_configs();
+    main_window.set_sample_configs(slint::ModelRc::new(slint::VecModel::from(vec![])));
+    spt_tx.send(SampleProcessingThreadRequest::CancelProcessing).unwrap();
+    spt_tx.send(SampleProcessingThreadRequest::StartProcessing(path, script700_path)).unwrap();
+
+    true
+}
+
+fn sample_config_custom_tuning(config: &SampleConfig) -> Option<f64> {
+    match config.pitch_type {
+        PitchType::Frequency => Some(config.frequency as f64),
+        PitchType::AddMusicK => Some(tuning::amk_tuning_frequency(config.amk_tuning as u8, config.amk_subtuning as u8)),
+        PitchType::Automatic => None
+    }
+}
+
+fn apply_tuning_entry(config: &mut SampleConfig, entry: &SampleTuningEntry) {
+    if let Some(name) = &entry.name {
+        config.name = name.clone().into();
+    }
+    if let Some(frequency) = entry.frequency {
+        config.pitch_type = PitchType::Frequency;
+        config.frequency = frequency as f32;
+    }
+    if let Some((tuning, subtuning)) = entry.amk {
+        config.pitch_type = PitchType::AddMusicK;
+        config.amk_tuning = tuning as i32;
+        config.amk_subtuning = subtuning as i32;
+    }
+    if let Some(octave_offset) = entry.octave_offset {
+        config.auto_octave_offset = octave_offset;
+    }
+    if let Some(color) = entry.color() {
+        let color = color.to_color_u8();
+        config.color = slint_int_arr([color.red(), color.green(), color.blue()]);
+        config.use_color = true;
+    }
+    config.percussion = entry.percussion;
+}
+
+fn sample_config_tuning_entry(config: &SampleConfig, sample: Option<&BrrSample>) -> SampleTuningEntry {
+    let color = config.use_color.then(|| {
+        let rgb: Vec<i32> = config.color.iter().collect();
+        format!("#{:02x}{:02x}{:02x}", rgb[0], rgb[1], rgb[2])
+    });
+
+    SampleTuningEntry {
+        source: Some(config.source as u8),
+        brr_hash: sample.map(|sample| format!("{:016x}", brr_hash(sample))),
+        name: (!config.name.is_empty()).then(|| config.name.to_string()),
+        frequency: matches!(config.pitch_type, PitchType::Frequency).then_some(config.frequency as f64),
+        amk: matches!(config.pitch_type, PitchType::AddMusicK).then_some((config.amk_tuning as u8, config.amk_subtuning as u8)),
+        octave_offset: (config.auto_octave_offset != 0).then_some(config.auto_octave_offset),
+        color,
+        percussion: config.percussion
+    }
+}
+
 fn random_slint_color() -> slint::ModelRc<i32> {
     let h = rand::random::<f64>() * 360.0;
     let s = (rand::random::<f64>() * 0.25) + 0.75;
@@ -413,7 +560,16 @@ pub fn run() {
                             .set_colors(&[color]);
                     });
 
+                let channel_muted = main_window_weak.unwrap().get_channel_muted();
+                let channel_solo = main_window_weak.unwrap().get_channel_solo();
+                for (channel, (muted, solo)) in channel_muted.iter().zip(channel_solo.iter()).enumerate() {
+                    let settings = config.piano_roll.settings.settings_mut(channel).unwrap();
+                    settings.set_muted(muted);
+                    settings.set_solo(solo);
+                }
+
                 config.emulator.filter_enabled = main_window_weak.unwrap().get_filter_enabled();
+                config.emulator.apply_xid6_settings = main_window_weak.unwrap().get_xid6_settings_enabled();
                 config.emulator.resampling_mode = match main_window_weak.unwrap().invoke_resampling_type() {
                     ResamplingType::Accurate => ResamplingMode::Accurate,
                     ResamplingType::Gaussian => ResamplingMode::Gaussian,
@@ -433,7 +589,13 @@ pub fn run() {
                     .collect();
                 main_window_weak.unwrap().set_channel_base_colors(slint_color_component_arr(base_colors));
 
+                let channel_muted = (0..8).map(|channel| config.piano_roll.settings.settings(channel).unwrap().muted());
+                let channel_solo = (0..8).map(|channel| config.piano_roll.settings.settings(channel).unwrap().solo());
+                main_window_weak.unwrap().set_channel_muted(slint_bool_arr(channel_muted));
+                main_window_weak.unwrap().set_channel_solo(slint_bool_arr(channel_solo));
+
                 main_window_weak.unwrap().set_filter_enabled(config.emulator.filter_enabled);
+                main_window_weak.unwrap().set_xid6_settings_enabled(config.emulator.apply_xid6_settings);
                 main_window_weak.unwrap().invoke_set_resampling_type(match &config.emulator.resampling_mode {
                     ResamplingMode::Accurate => ResamplingType::Accurate,
                     ResamplingMode::Gaussian => ResamplingType::Gaussian,
@@ -652,17 +814,26 @@ pub fn run() {
                             .unwrap()
                             .sample_tunings
                             .iter()
-                            .map(|(source, data)| SampleConfig {
-                                name: "".into(),
-                                source: *source as i32,
-                                pitch_type: PitchType::Automatic,
-                                auto_octave_offset: data.temporal_pitch_octave_offset() as i32,
-                                base_frequency: data.base_pitch() as f32,
-                                frequency: data.base_pitch() as f32,
-                                amk_tuning: 3,
-                                amk_subtuning: 0,
-                                color: random_slint_color(),
-                                use_color: false
+                            .map(|(source, data)| {
+                                let (pitch_type, (amk_tuning, amk_subtuning)) = match (data.instrument_tuning(), data.custom_tuning()) {
+                                    (Some(instrument_tuning), _) => (PitchType::AddMusicK, instrument_tuning),
+                                    (None, Some(_)) => (PitchType::Frequency, (3, 0)),
+                                    (None, None) => (PitchType::Automatic, (3, 0))
+                                };
+
+                                SampleConfig {
+                                    name: "".into(),
+                                    source: *source as i32,
+                                    pitch_type,
+                                    auto_octave_offset: data.temporal_pitch_octave_offset() as i32,
+                                    base_frequency: data.base_pitch() as f32,
+                                    frequency: data.custom_tuning().unwrap_or(data.base_pitch()) as f32,
+                                    amk_tuning: amk_tuning as i32,
+                                    amk_subtuning: amk_subtuning as i32,
+                                    color: random_slint_color(),
+                                    use_color: false,
+                                    percussion: data.percussion()
+                                }
                             })
                             .collect();
 
@@ -682,51 +853,67 @@ pub fn run() {
         })
     };
 
+    let tracks: Arc<Mutex<Vec<TrackInfo>>> = Arc::new(Mutex::new(Vec::new()));
+
     {
         let main_window_weak = main_window.as_weak();
         let options = options.clone();
         let spt_tx = spt_tx.clone();
         let localization_adapter = localization_adapter.clone();
+        let tracks = tracks.clone();
         main_window.on_browse_for_module(move || {
-            match browse_for_module_dialog(localization_adapter.clone()) {
-                Some(path) => {
-                    let metadata_lines = match get_spc_metadata(&path) {
-                        Ok((_duration, metadata_lines)) => metadata_lines,
-                        Err(e) => {
-                            let message = localization_adapter
-                                .lock()
-                                .unwrap()
-                                .get("error-message-spc-file-invalid", Some(&fluent_args!(error: e.to_string())), true);
-                            display_error_dialog(&message);
-                            return options.lock().unwrap().input_path.clone().into();
-                        }
-                    };
+            let current_path = options.lock().unwrap().input_path.clone();
+            let current_path = split_track_selector(&current_path).0.to_string();
 
-                    options.lock().unwrap().input_path = path.clone();
-                    main_window_weak.unwrap().set_metadata_lines(metadata_lines);
+            let path = match browse_for_module_dialog(localization_adapter.clone()) {
+                Some(path) => path,
+                None => return current_path.into()
+            };
 
-                    let script700_path = search_for_script700_file(path.clone())
-                        .and_then(|script700_path| {
-                            confirm_load_script700_dialog(localization_adapter.clone(), &script700_path)
-                                .then_some(script700_path)
-                        })
-                        .unwrap_or_default();
-                    main_window_weak.unwrap().set_script700_path(script700_path.file_name().unwrap_or_default().to_str().unwrap().into());
+            let new_tracks = match list_tracks(&path) {
+                Ok(new_tracks) => new_tracks.unwrap_or_default(),
+                Err(e) => {
+                    let message = localization_adapter
+                        .lock()
+                        .unwrap()
+                        .get("error-message-spc-file-invalid", Some(&fluent_args!(error: e.to_string())), true);
+                    display_error_dialog(&message);
+                    return current_path.into();
+                }
+            };
+            let module_path = match new_tracks.first() {
+                Some(track) => track.path.clone(),
+                None => path.clone()
+            };
 
-                    let script700_path = script700_path.to_str().unwrap().to_string();
-                    options.lock().unwrap().script700_path = script700_path.clone();
+            if !load_module(&main_window_weak.unwrap(), &options, &spt_tx, &localization_adapter, module_path) {
+                return current_path.into();
+            }
 
-                    main_window_weak.unwrap().invoke_reformat_duration();
+            let track_labels: Vec<String> = new_tracks.iter()
+                .enumerate()
+                .map(|(i, track)| format!("{}. {}", i + 1, track.title))
+                .collect();
+            main_window_weak.unwrap().set_tracks(slint_string_arr(track_labels));
+            main_window_weak.unwrap().set_track_index(0);
+            *tracks.lock().unwrap() = new_tracks;
 
-                    main_window_weak.unwrap().invoke_reset_sample_configs();
-                    main_window_weak.unwrap().set_sample_configs(slint::ModelRc::new(slint::VecModel::from(vec![])));
-                    spt_tx.send(SampleProcessingThreadRequest::CancelProcessing).unwrap();
-                    spt_tx.send(SampleProcessingThreadRequest::StartProcessing(path.clone(), script700_path.clone())).unwrap();
+            path.into()
+        });
+    }
 
-                    path.into()
-                },
-                None => options.lock().unwrap().input_path.clone().into()
-            }
+    {
+        let main_window_weak = main_window.as_weak();
+        let options = options.clone();
+        let spt_tx = spt_tx.clone();
+        let localization_adapter = localization_adapter.clone();
+        let tracks = tracks.clone();
+        main_window.on_select_track(move |track_index| {
+            let module_path = match tracks.lock().unwrap().get(track_index as usize) {
+                Some(track) => track.path.clone(),
+                None => return
+            };
+            load_module(&main_window_weak.unwrap(), &options, &spt_tx, &localization_adapter, module_path);
         });
     }
 
@@ -752,6 +939,28 @@ pub fn run() {
         });
     }
 
+    {
+        let options = options.clone();
+        let localization_adapter = localization_adapter.clone();
+        main_window.on_browse_for_midi_export(move || {
+            match browse_for_midi_export_dialog(localization_adapter.clone()) {
+                Some(path) => {
+                    options.lock().unwrap().midi_path = Some(path.clone());
+
+                    path.into()
+                },
+                None => options.lock().unwrap().midi_path.clone().unwrap_or("".to_string()).into()
+            }
+        });
+    }
+
+    {
+        let options = options.clone();
+        main_window.on_midi_export_cleared(move || {
+            options.lock().unwrap().midi_path = None;
+        });
+    }
+
     {
         let options = options.clone();
         main_window.on_get_duration(move |stop_condition_type, stop_condition_num| {
@@ -764,9 +973,12 @@ pub fn run() {
                     Duration::from_secs(stop_condition_num as _)
                 },
                 StopConditionType::SpcDuration => {
-                    match get_spc_metadata(options.lock().unwrap().input_path.clone()) {
-                        Ok((Some(duration), _metadata_lines)) => duration,
-                        _ => return ERROR_DURATION
+                    let play_time = load_spc(options.lock().unwrap().input_path.clone())
+                        .ok()
+                        .and_then(|spc_file| spc_file.metadata().play_time(None));
+                    match play_time {
+                        Some((duration, _fadeout_time)) => duration,
+                        None => return ERROR_DURATION
                     }
                 }
             };
@@ -776,6 +988,7 @@ pub fn run() {
 
     {
         let main_window_weak = main_window.as_weak();
+        let options = options.clone();
         let localization_adapter = localization_adapter.clone();
         main_window.on_import_tunings(move || {
             let tuning_data_path = match browse_for_tuning_data(localization_adapter.clone()) {
@@ -787,20 +1000,35 @@ pub fn run() {
                 .iter()
                 .collect();
 
-            if tuning_data_path.ends_with(".json") {
-                let session_json = match fs::read_to_string(tuning_data_path) {
-                    Ok(json) => json,
-                    Err(e) => {
-                        let message = localization_adapter
-                            .lock()
-                            .unwrap()
-                            .get("error-message-tuning-read-error", Some(&fluent_args!(error: e.to_string())), true);
-                        display_error_dialog(&message);
-                        return;
-                    }
-                };
+            let is_json = tuning_data_path.ends_with(".json");
+            if !is_json && !tuning_data_path.ends_with(".toml") {
+                let message = localization_adapter
+                    .lock()
+                    .unwrap()
+                    .get("error-message-tuning-unrecognized-format", None, true);
+                display_error_dialog(&message);
+                return;
+            }
 
-                let samples = match tuning::super_midi_pak_session::SuperMidiPakSession::from_json(&session_json).and_then(|session| session.samples()) {
+            let tuning_data = match fs::read_to_string(tuning_data_path) {
+                Ok(data) => data,
+                Err(e) => {
+                    let message = localization_adapter
+                        .lock()
+                        .unwrap()
+                        .get("error-message-tuning-read-error", Some(&fluent_args!(error: e.to_string())), true);
+                    display_error_dialog(&message);
+                    return;
+                }
+            };
+
+            // Tuning files can also be JSON, so only JSON that isn't a Super MIDI Pak session is read as one
+            let session = is_json
+                .then(|| tuning::super_midi_pak_session::SuperMidiPakSession::from_json(&tuning_data).ok())
+                .flatten();
+
+            if let Some(session) = session {
+                let samples = match session.samples() {
                     Ok(samples) => samples,
                     Err(e) => {
                         let message = localization_adapter
@@ -822,15 +1050,66 @@ pub fn run() {
                     }
                 }
             } else {
+                let tuning_file = if is_json {
+                    TuningFile::from_json(&tuning_data)
+                } else {
+                    TuningFile::from_toml(&tuning_data)
+                };
+                let tuning_file = match tuning_file {
+                    Ok(tuning_file) => tuning_file,
+                    Err(e) => {
+                        let message = localization_adapter
+                            .lock()
+                            .unwrap()
+                            .get("error-message-tuning-parse-error", Some(&fluent_args!(error: format!("{:#}", e))), true);
+                        display_error_dialog(&message);
+                        return;
+                    }
+                };
+
+                let options = options.lock().unwrap();
+                for config in sample_configs.iter_mut() {
+                    let source = config.source as u8;
+                    let entry = options.sample_tunings.get(&source)
+                        .and_then(|sample_data| tuning_file.find(source, sample_data.sample()));
+                    if let Some(entry) = entry {
+                        apply_tuning_entry(config, entry);
+                    }
+                }
+            }
+
+            main_window_weak.unwrap().set_sample_configs(slint::ModelRc::new(slint::VecModel::from(sample_configs)));
+        });
+    }
+
+    {
+        let main_window_weak = main_window.as_weak();
+        let options = options.clone();
+        let localization_adapter = localization_adapter.clone();
+        main_window.on_export_tunings(move || {
+            let tuning_file_path = match browse_for_tuning_export_dialog(localization_adapter.clone()) {
+                Some(path) => path,
+                None => return
+            };
+
+            let options = options.lock().unwrap();
+            let mut samples: Vec<SampleTuningEntry> = main_window_weak.unwrap().get_sample_configs()
+                .iter()
+                .map(|config| {
+                    let sample = options.sample_tunings.get(&(config.source as u8)).map(|data| data.sample());
+                    sample_config_tuning_entry(&config, sample)
+                })
+                .collect();
+            // Keep the order stable so the file diffs cleanly
+            samples.sort_by_key(|entry| entry.source);
+
+            if let Err(e) = TuningFile::new(samples).save(tuning_file_path) {
                 let message = localization_adapter
                     .lock()
                     .unwrap()
-                    .get("error-message-tuning-unrecognized-data-format", None, true);
+                    .get("error-message-tuning-write-error", Some(&fluent_args!(error: e.to_string())), true);
                 display_error_dialog(&message);
-                return;
             }
-
-            main_window_weak.unwrap().set_sample_configs(slint::ModelRc::new(slint::VecModel::from(sample_configs)));
         });
     }
 
@@ -886,8 +1165,14 @@ pub fn run() {
                     None => return
                 };
 
-                let brr_data = sample_data.sample().to_bytes();
-                if let Err(e) = fs::write(output_path, brr_data) {
+                let data = if output_path.to_ascii_lowercase().ends_with(".wav") {
+                    let pitch = sample_config_custom_tuning(&sample_config)
+                        .unwrap_or(sample_data.base_pitch() * (sample_config.auto_octave_offset as f64).exp2());
+                    sample_to_wav(sample_data.sample(), pitch)
+                } else {
+                    sample_data.sample().to_bytes()
+                };
+                if let Err(e) = fs::write(output_path, data) {
                     let message = localization_adapter
                         .lock()
                         .unwrap()
@@ -898,6 +1183,29 @@ pub fn run() {
         });
     }
 
+    {
+        let options = options.clone();
+        let localization_adapter = localization_adapter.clone();
+        main_window.on_dump_all_samples(move || {
+            let input_path = options.lock().unwrap().input_path.clone();
+            if input_path.is_empty() {
+                return;
+            }
+            let output_dir = match browse_for_dump_all_dialog() {
+                Some(path) => path,
+                None => return
+            };
+
+            if let Err(e) = dump_all_samples(input_path, output_dir) {
+                let message = localization_adapter
+                    .lock()
+                    .unwrap()
+                    .get("error-message-tuning-sample-dump-all-error", Some(&fluent_args!(error: e.to_string())), true);
+                display_error_dialog(&message);
+            }
+        });
+    }
+
     {
         let main_window_weak = main_window.as_weak();
         let options = options.clone();
@@ -926,7 +1234,7 @@ pub fn run() {
             options.lock().unwrap().stop_condition = match main_window_weak.unwrap().get_stop_condition_type() {
                 StopConditionType::Frames => StopCondition::Frames(stop_condition_num),
                 StopConditionType::Time => StopCondition::Frames(stop_condition_num * 60),
-                StopConditionType::SpcDuration => StopCondition::SpcDuration
+                StopConditionType::SpcDuration => StopCondition::SpcDuration(None)
             };
 
             options.lock().unwrap().per_sample_colors.clear();
@@ -939,17 +1247,15 @@ pub fn run() {
                 .collect();
             for config in sample_configs {
                 let source = config.source as u8;
-                let custom_tuning = match config.pitch_type {
-                    PitchType::Frequency => Some(config.frequency as f64),
-                    PitchType::AddMusicK => Some(32000.0 / (16.0 * ((config.amk_tuning as f64) + (config.amk_subtuning as f64 / 256.0)))),
-                    PitchType::Automatic => None
-                };
+                let custom_tuning = sample_config_custom_tuning(&config);
 
                 {
                     let mut options_guard = options.lock().unwrap();
                     let sample_tuning = options_guard.sample_tunings.get_mut(&source).unwrap();
                     sample_tuning.set_custom_tuning(custom_tuning);
                     sample_tuning.set_temporal_pitch_octave_offset(config.auto_octave_offset as f64);
+                    sample_tuning.set_percussion(config.percussion);
+                    sample_tuning.update_cache();
                 }
 
                 if config.use_color {
@@ -23,6 +23,11 @@ version-dialog-button-close = Close
 spc-file-browser-label = Input SPC:
 # Placeholder text shown when the current SPC file does not have metadata.
 spc-metadata-placeholder = <no metadata>
+# Metadata line showing the N-SPC driver version detected in the SPC.
+# $driver (String) - Driver name and the addresses of its data tables.
+spc-metadata-nspc-driver = Driver: {$driver}
+# Label for the ComboBox that selects a song when the input is an SPC2 or ZIP file.
+track-selector-label = Song:
 
 ## Configuration section
 # Label for channel color pickers.
@@ -33,12 +38,22 @@ config-button-import-tooltip = Import
 config-button-export-tooltip = Export
 # Button that resets the current config to the default.
 config-button-reset-tooltip = Reset
+# Label for the per-channel mute and solo toggles.
+config-label-channel-mix = Channel mix:
+# Toggle that mutes a channel in the rendered audio. $channel is the channel number (1-8).
+channel-mute-button = M{ $channel }
+# Toggle that makes only soloed channels audible in the rendered audio. $channel is the channel number (1-8).
+channel-solo-button = S{ $channel }
 
 ## Tuning section
 # Label for the tuning section.
 tuning-section-header = Sample tuning
 # Button that imports tuning data from music production data files.
 tuning-button-import = Import tunings
+# Button that saves the current sample tunings to a tuning file.
+tuning-button-export = Export tunings
+# Button that exports every sample in the SPC's sample directory to a folder.
+tuning-button-dump-all = Dump all samples
 
 # Pitch type: automatic pitch detection
 tuning-pitch-type-automatic = Automatic
@@ -78,12 +93,16 @@ tuning-sample-config-addmusick-tuning-placeholder = Tuning
 tuning-sample-config-addmusick-subtuning-placeholder = Sub.
 # Label for the color picker that selects a specific color for the currently selected sample.
 tuning-sample-config-per-sample-color-label = Per-sample color:
+# Checkbox that marks a sample as percussion, which is drawn at a fixed pitch in the piano roll and saved in exported tuning files.
+tuning-sample-config-percussion-label = Percussion
 
 ## Render options section
 # Label for the FileBrowser that selects a background image/video for the render.
 background-file-browser-label = Background:
 # Label for the checkbox that enables background dimming.
 dim-background-checkbox-label = Dim
+# Label for the FileBrowser that selects where to export a MIDI file of the render's notes.
+midi-export-file-browser-label = Export MIDI:
 
 # Label for the settings that determine the output video duration/play time.
 render-duration-label = Render duration:
@@ -119,6 +138,8 @@ resampling-type-sinc = Sinc
 
 # Label for the checkbox that enables Blargg's SNES_DSP audio filter.
 blargg-dsp-filter-checkbox-label = Use Blargg's DSP filter
+# Label for the checkbox that applies the muted voices and preamp level stored in the SPC file's tags.
+xid6-settings-checkbox-label = Use muted voices and preamp from tags
 
 # Button that starts rendering the video.
 render-button-start = Render!
@@ -206,9 +227,15 @@ error-message-tuning-read-error = Failed to read tuning data: {$error}
 error-message-tuning-parse-error = Invalid tuning data: {$error}
 # Error displayed when the format of a tuning data file could not be determined.
 error-message-tuning-unrecognized-format = Unrecognized tuning data format.
+# Error displayed when a tuning file could not be written.
+# $error (String) - Error message from operating system.
+error-message-tuning-write-error = Failed to write tuning file: {$error}
 # Error displayed when a BRR sample could not be dumped to a file.
 # $error (String) - Error message from operating system.
 error-message-tuning-sample-write-error = Failed to dump sample: {$error}
+# Error message shown when dumping every sample fails.
+# $error (String) - Error message from operating system.
+error-message-tuning-sample-dump-all-error = Failed to dump samples: {$error}
 
 ## File dialog filter labels
 # Label for aggregate of all supported file types for an operation
@@ -217,14 +244,24 @@ file-dialog-filter-all = All supported formats
 file-dialog-filter-config = TOML configuration file
 # Label for SPC700 program dumps (.spc)
 file-dialog-filter-spc = SPC file
+# Label for SPC2 archives containing several SPC dumps (.sp2)
+file-dialog-filter-spc2 = SPC2 file
+# Label for ZIP archives of SPC files (.zip)
+file-dialog-filter-zip = ZIP archive
 # Label for BRR sample files (.brr)
 file-dialog-filter-brr = BRR sample
+# Label for WAV sample files (.wav)
+file-dialog-filter-wav = WAV sample
+# Label for Standard MIDI files (.mid)
+file-dialog-filter-midi = MIDI file
 # Label for video background files (.mp4, .mkv, .mov, etc.)
 file-dialog-filter-video-background = Video background formats
 # Label for image background files (.jpg, .png, .webp, etc.)
 file-dialog-filter-image-background = Image background formats
 # Label for Super MIDI Pak session JSON files (.json)
 file-dialog-filter-super-midi-pak-session = Super MIDI Pak session
+# Label for SPCPresenter tuning files (.toml, .json)
+file-dialog-filter-tuning-file = Tuning file
 # Label for MPEG-4 video files (.mp4)
 file-dialog-filter-output-mpeg4 = MPEG-4 video
 # Label for Matroska video files (.mkv)
@@ -8,14 +8,21 @@ version-dialog-button-close = Close
 
 spc-file-browser-label = Input SPC:
 spc-metadata-placeholder = <no metadata>
+spc-metadata-nspc-driver = Driver: {$driver}
+track-selector-label = Song:
 
 config-label-base-channel-colors = Base channel colors:
 config-button-import-tooltip = Import
 config-button-export-tooltip = Export
 config-button-reset-tooltip = Reset
+config-label-channel-mix = Channel mix:
+channel-mute-button = M{ $channel }
+channel-solo-button = S{ $channel }
 
 tuning-section-header = Sample tuning
 tuning-button-import = Import tunings
+tuning-button-export = Export tunings
+tuning-button-dump-all = Dump all samples
 
 tuning-pitch-type-automatic = Automatic
 tuning-pitch-type-frequency = Frequency
@@ -36,9 +43,11 @@ tuning-sample-config-unit-suffix-hertz = Hz
 tuning-sample-config-addmusick-tuning-placeholder = Tuning
 tuning-sample-config-addmusick-subtuning-placeholder = Sub.
 tuning-sample-config-per-sample-color-label = Per-sample color:
+tuning-sample-config-percussion-label = Percussion
 
 background-file-browser-label = Background:
 dim-background-checkbox-label = Dim
+midi-export-file-browser-label = Export MIDI:
 
 render-duration-label = Render duration:
 render-duration-type-seconds = seconds
@@ -59,6 +68,7 @@ resampling-type-cubic = Cubic spline
 resampling-type-sinc = Sinc
 
 blargg-dsp-filter-checkbox-label = Use Blargg's DSP filter
+xid6-settings-checkbox-label = Use muted voices and preamp from tags
 
 render-button-start = Render!
 render-button-cancel = Cancel
@@ -94,15 +104,22 @@ error-message-config-serialize-error = Failed to serialize configuration: {$erro
 error-message-tuning-read-error = Failed to read tuning data: {$error}
 error-message-tuning-parse-error = Invalid tuning data: {$error}
 error-message-tuning-unrecognized-format = Unrecognized tuning data format.
+error-message-tuning-write-error = Failed to write tuning file: {$error}
 error-message-tuning-sample-write-error = Failed to dump sample: {$error}
+error-message-tuning-sample-dump-all-error = Failed to dump samples: {$error}
 
 file-dialog-filter-all = All supported formats
 file-dialog-filter-config = TOML configuration file
 file-dialog-filter-spc = SPC file
+file-dialog-filter-spc2 = SPC2 file
+file-dialog-filter-zip = ZIP archive
 file-dialog-filter-brr = BRR sample
+file-dialog-filter-wav = WAV sample
+file-dialog-filter-midi = MIDI file
 file-dialog-filter-video-background = Video background formats
 file-dialog-filter-image-background = Image background formats
 file-dialog-filter-super-midi-pak-session = Super MIDI Pak session
+file-dialog-filter-tuning-file = Tuning file
 file-dialog-filter-output-mpeg4 = MPEG-4 video
 file-dialog-filter-output-matroska = Matroska video
 file-dialog-filter-output-quicktime = QuickTime video
@@ -8,14 +8,21 @@ version-dialog-button-close = Cerrar
 
 spc-file-browser-label = Archivo SPC:
 spc-metadata-placeholder = <ningún metadatos>
+spc-metadata-nspc-driver = Controlador: {$driver}
+track-selector-label = Canción:
 
 config-label-base-channel-colors = Colores de canales:
 config-button-import-tooltip = Importar
 config-button-export-tooltip = Exportar
 config-button-reset-tooltip = Reiniciar
+config-label-channel-mix = Mezcla de canales:
+channel-mute-button = M{ $channel }
+channel-solo-button = S{ $channel }
 
 tuning-section-header = Afinación de samples
 tuning-button-import = Importar afinaciones
+tuning-button-export = Exportar afinaciones
+tuning-button-dump-all = Copiar todos los samples
 
 tuning-pitch-type-automatic = Automático
 tuning-pitch-type-frequency = Frequencia
@@ -36,9 +43,11 @@ tuning-sample-config-unit-suffix-hertz = Hz
 tuning-sample-config-addmusick-tuning-placeholder = Afin.
 tuning-sample-config-addmusick-subtuning-placeholder = Sub.
 tuning-sample-config-per-sample-color-label = Colorear este sample:
+tuning-sample-config-percussion-label = Percusión
 
 background-file-browser-label = Fondo:
 dim-background-checkbox-label = Atenuar
+midi-export-file-browser-label = Exportar MIDI:
 
 render-duration-label = Duración de video:
 render-duration-type-seconds = segundos
@@ -59,6 +68,7 @@ resampling-type-cubic = Spline cúbico
 resampling-type-sinc = Sinc
 
 blargg-dsp-filter-checkbox-label = Usar el filtro DSP de Blargg
+xid6-settings-checkbox-label = Usar las voces silenciadas y el preamplificador de las etiquetas
 
 render-button-start = ¡Renderizar!
 render-button-cancel = Cancelar
@@ -94,15 +104,22 @@ error-message-config-serialize-error = Error al serializar la configuración: {$
 error-message-tuning-read-error = Error al leer los datos de afinación: {$error}
 error-message-tuning-parse-error = Datos de afinación inválidos: {$error}
 error-message-tuning-unrecognized-format = Datos de afinación tienen un formato desconocido.
+error-message-tuning-write-error = Error al escribir el archivo de afinaciones: {$error}
 error-message-tuning-sample-write-error = Error al copiar el sample: {$error}
+error-message-tuning-sample-dump-all-error = Error al copiar los samples: {$error}
 
 file-dialog-filter-all = Todos formatos compatibles
 file-dialog-filter-config = Archivo de configuración TOML
 file-dialog-filter-spc = Archivo SPC
+file-dialog-filter-spc2 = Archivo SPC2
+file-dialog-filter-zip = Archivo ZIP
 file-dialog-filter-brr = Sample BRR
+file-dialog-filter-wav = Sample WAV
+file-dialog-filter-midi = Archivo MIDI
 file-dialog-filter-video-background = Formatos de vídeo de fondo
 file-dialog-filter-image-background = Formatos de imagen de fondo
 file-dialog-filter-super-midi-pak-session = Sesión de Super MIDI Pak
+file-dialog-filter-tuning-file = Archivo de afinaciones
 file-dialog-filter-output-mpeg4 = Vídeo MPEG-4
 file-dialog-filter-output-matroska = Vídeo Matroska
 file-dialog-filter-output-quicktime = Vídeo QuickTime
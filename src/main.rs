@@ -8,6 +8,9 @@ mod gui;
 mod config;
 mod sample_processing;
 
+#[cfg(test)]
+mod tests;
+
 use std::env;
 use build_time::build_time_utc;
 
@@ -0,0 +1,157 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use snes_apu_spcp::{ApuChannelState, ApuMasterState, ApuStateReceiver};
+use crate::emulator::Emulator;
+use crate::renderer::render_options::FRAME_RATE;
+
+const SAMPLE_RATE: u64 = 32000;
+const MIN_LOOP_LENGTH: u64 = 5 * SAMPLE_RATE;
+const MIN_CONFIRM_LENGTH: u64 = 30 * SAMPLE_RATE;
+const MAX_ANALYSIS_FRAMES: u64 = 15 * 60 * FRAME_RATE as u64;
+const ANALYSIS_INTERVAL_FRAMES: u64 = 10 * FRAME_RATE as u64;
+const TIMING_TOLERANCE: u64 = 64;
+
+#[derive(Copy, Clone, Debug)]
+pub struct LoopInfo {
+    pub start_frame: u64,
+    pub length_frames: u64
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+struct KeyOnEvent {
+    channel: usize,
+    source: u8,
+    pitch: u16,
+    volume: (i8, i8)
+}
+
+struct KeyOnRecorder {
+    sample_index: u64,
+    events: Vec<(u64, KeyOnEvent)>,
+    event_times: HashMap<KeyOnEvent, Vec<u64>>
+}
+
+impl KeyOnRecorder {
+    pub fn new() -> Self {
+        Self {
+            sample_index: 0,
+            events: Vec::new(),
+            event_times: HashMap::new()
+        }
+    }
+
+    fn has_event_near(&self, event: &KeyOnEvent, time: u64) -> bool {
+        let times = match self.event_times.get(event) {
+            Some(times) => times,
+            None => return false
+        };
+
+        let lower = time.saturating_sub(TIMING_TOLERANCE);
+        let i = times.partition_point(|&t| t < lower);
+        times.get(i).is_some_and(|&t| t <= time + TIMING_TOLERANCE)
+    }
+
+    fn verify_period(&self, period: u64) -> Option<u64> {
+        let end = self.sample_index;
+
+        // Walk backwards until an event no longer repeats one period earlier
+        let mut first_repeat = None;
+        for (time, event) in self.events.iter().rev() {
+            if *time < period || !self.has_event_near(event, time - period) {
+                break;
+            }
+            first_repeat = Some(*time);
+        }
+        let first_repeat = first_repeat?;
+
+        if end - first_repeat < period.max(MIN_CONFIRM_LENGTH) {
+            return None;
+        }
+
+        // Every event of the first pass must also show up in the second pass
+        let loop_start = first_repeat - period;
+        let all_repeat = self.events.iter()
+            .filter(|(time, _)| *time >= loop_start && *time + period + TIMING_TOLERANCE <= end)
+            .all(|(time, event)| self.has_event_near(event, time + period));
+
+        all_repeat.then_some(loop_start)
+    }
+
+    pub fn find_loop(&self) -> Option<(u64, u64)> {
+        let (last_time, last_event) = self.events.last()?;
+
+        let mut candidates: Vec<u64> = self.event_times.get(last_event)?
+            .iter()
+            .filter(|&&t| t + MIN_LOOP_LENGTH <= *last_time)
+            .map(|&t| last_time - t)
+            .collect();
+        candidates.sort_unstable();
+
+        candidates.into_iter()
+            .find_map(|period| self.verify_period(period).map(|start| (start, period)))
+    }
+}
+
+impl ApuStateReceiver for KeyOnRecorder {
+    fn receive_channel(&mut self, channel: usize, state: ApuChannelState) {
+        if !state.key_on {
+            return;
+        }
+
+        let event = KeyOnEvent {
+            channel,
+            source: state.source,
+            pitch: state.pitch,
+            volume: state.volume
+        };
+        self.events.push((self.sample_index, event));
+        self.event_times.entry(event).or_default().push(self.sample_index);
+    }
+
+    fn receive_master(&mut self, _state: ApuMasterState) {
+        self.sample_index += 1;
+    }
+}
+
+pub struct LoopDetector {
+    recorder: Arc<Mutex<KeyOnRecorder>>,
+    current_frame: u64
+}
+
+impl LoopDetector {
+    pub fn new() -> Self {
+        Self {
+            recorder: Arc::new(Mutex::new(KeyOnRecorder::new())),
+            current_frame: 0
+        }
+    }
+
+    pub fn detect(&mut self, emulator: &mut Emulator) -> Result<Option<LoopInfo>> {
+        let initial_state = emulator.save_state();
+        emulator.set_state_receiver(Some(self.recorder.clone()));
+
+        let result = self.run(emulator);
+
+        emulator.set_state_receiver(None);
+        emulator.load_state(&initial_state)?;
+
+        Ok(result)
+    }
+
+    fn run(&mut self, emulator: &mut Emulator) -> Option<LoopInfo> {
+        while self.current_frame < MAX_ANALYSIS_FRAMES {
+            emulator.skip(ANALYSIS_INTERVAL_FRAMES as usize);
+            self.current_frame += ANALYSIS_INTERVAL_FRAMES;
+
+            if let Some((start, length)) = self.recorder.lock().unwrap().find_loop() {
+                return Some(LoopInfo {
+                    start_frame: start * FRAME_RATE as u64 / SAMPLE_RATE,
+                    length_frames: length * FRAME_RATE as u64 / SAMPLE_RATE
+                });
+            }
+        }
+
+        None
+    }
+}
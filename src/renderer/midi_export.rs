@@ -0,0 +1,221 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use snes_apu_spcp::{ApuChannelState, ApuMasterState, ApuStateReceiver};
+use crate::sample_processing::SampleData;
+use crate::visualizer::C_0;
+
+const DSP_SAMPLE_RATE: u64 = 32000;
+const FRAME_RATE: u64 = 60;
+// One tick per frame: 60 ticks per quarter note at 60 BPM
+const TICKS_PER_QUARTER: u16 = 60;
+const MICROSECONDS_PER_QUARTER: u32 = 1_000_000;
+// General MIDI default pitch bend range, in semitones
+const PITCH_BEND_RANGE: f64 = 2.0;
+const PITCH_BEND_CENTER: u16 = 0x2000;
+
+fn midi_note(frequency: f64) -> Option<f64> {
+    (frequency > 0.0).then(|| 12.0 * (frequency / C_0).log2() + 12.0)
+}
+
+fn pitch_bend_value(semitones: f64) -> u16 {
+    let offset = (semitones / PITCH_BEND_RANGE * PITCH_BEND_CENTER as f64).round() as i32;
+    (PITCH_BEND_CENTER as i32 + offset).clamp(0, 0x3fff) as u16
+}
+
+fn velocity(level: f64) -> u8 {
+    (level * 127.0).round().clamp(1.0, 127.0) as u8
+}
+
+#[derive(Default)]
+struct VoiceTrack {
+    events: Vec<(u64, Vec<u8>)>,
+    note: Option<u8>,
+    program: Option<u8>,
+    pitch_bend: u16,
+
+    // Accumulated over the current frame
+    last_state: Option<ApuChannelState>,
+    key_on_pending: bool,
+    key_off: bool,
+    peak_level: f64
+}
+
+impl VoiceTrack {
+    fn push(&mut self, tick: u64, event: &[u8]) {
+        self.events.push((tick, event.to_vec()));
+    }
+
+    fn note_off(&mut self, tick: u64, channel: u8) {
+        if let Some(note) = self.note.take() {
+            self.push(tick, &[0x80 | channel, note, 0]);
+        }
+    }
+
+    fn set_pitch_bend(&mut self, tick: u64, channel: u8, value: u16) {
+        if value != self.pitch_bend {
+            self.pitch_bend = value;
+            self.push(tick, &[0xe0 | channel, (value & 0x7f) as u8, (value >> 7) as u8]);
+        }
+    }
+
+    fn note_on(&mut self, tick: u64, channel: u8, source: u8, note: f64, level: f64) {
+        self.note_off(tick, channel);
+
+        let program = source & 0x7f;
+        if self.program != Some(program) {
+            self.program = Some(program);
+            self.push(tick, &[0xc0 | channel, program]);
+        }
+
+        let key = note.round().clamp(0.0, 127.0);
+        self.set_pitch_bend(tick, channel, pitch_bend_value(note - key));
+        self.push(tick, &[0x90 | channel, key as u8, velocity(level)]);
+        self.note = Some(key as u8);
+    }
+
+    fn end_frame(&mut self, tick: u64, channel: u8, sample_data: &HashMap<u8, SampleData>) {
+        let Some(state) = self.last_state.take() else {
+            return;
+        };
+
+        let note = match state.noise_clock {
+            Some(t) => midi_note(C_0 * (t as f64 / 12.0).exp2()),
+            None => sample_data.get(&state.source)
+                .and_then(|data| midi_note(data.pitch_at(state.sample_block_index) * state.pitch as f64 / 0x1000 as f64))
+        };
+
+        if self.key_off {
+            self.note_off(tick, channel);
+        }
+
+        // Wait for the attack to be audible so the velocity reflects it
+        if self.key_on_pending && self.peak_level > 0.0 {
+            self.key_on_pending = false;
+            if let Some(note) = note {
+                self.note_on(tick, channel, state.source, note, self.peak_level);
+            }
+        } else if let (Some(key), Some(note)) = (self.note, note) {
+            if self.peak_level == 0.0 {
+                self.note_off(tick, channel);
+            } else if (note - key as f64).abs() > PITCH_BEND_RANGE {
+                self.note_on(tick, channel, state.source, note, self.peak_level);
+            } else {
+                self.set_pitch_bend(tick, channel, pitch_bend_value(note - key as f64));
+            }
+        }
+
+        self.key_off = false;
+        self.peak_level = 0.0;
+    }
+}
+
+/// Records the notes played by each voice as a Standard MIDI File, with one track per voice
+/// and one tick per video frame.
+pub struct MidiRecorder {
+    sample_data: HashMap<u8, SampleData>,
+    voices: Vec<VoiceTrack>,
+    sample_index: u64
+}
+
+impl MidiRecorder {
+    pub fn new(channels: usize, sample_data: HashMap<u8, SampleData>) -> Self {
+        Self {
+            sample_data,
+            voices: (0..channels).map(|_| VoiceTrack { pitch_bend: PITCH_BEND_CENTER, ..Default::default() }).collect(),
+            sample_index: 0
+        }
+    }
+
+    fn current_tick(&self) -> u64 {
+        self.sample_index * FRAME_RATE / DSP_SAMPLE_RATE
+    }
+
+    pub fn save<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let tick = self.current_tick();
+        for (channel, voice) in self.voices.iter_mut().enumerate() {
+            voice.note_off(tick, channel as u8);
+        }
+
+        let tempo = MICROSECONDS_PER_QUARTER.to_be_bytes();
+        let mut tracks = vec![write_track(&[
+            (0, vec![0xff, 0x51, 0x03, tempo[1], tempo[2], tempo[3]])
+        ])];
+        for (channel, voice) in self.voices.iter().enumerate() {
+            let name = format!("Voice {}", channel + 1);
+            let mut events = vec![(0, [&[0xff, 0x03, name.len() as u8], name.as_bytes()].concat())];
+            events.extend(voice.events.iter().cloned());
+            tracks.push(write_track(&events));
+        }
+
+        let mut data = Vec::new();
+        data.extend_from_slice(b"MThd");
+        data.extend_from_slice(&6u32.to_be_bytes());
+        data.extend_from_slice(&1u16.to_be_bytes());
+        data.extend_from_slice(&(tracks.len() as u16).to_be_bytes());
+        data.extend_from_slice(&TICKS_PER_QUARTER.to_be_bytes());
+        for track in tracks {
+            data.extend_from_slice(b"MTrk");
+            data.extend_from_slice(&(track.len() as u32).to_be_bytes());
+            data.extend(track);
+        }
+
+        fs::write(path, data)?;
+        Ok(())
+    }
+}
+
+impl ApuStateReceiver for MidiRecorder {
+    fn receive_channel(&mut self, channel: usize, state: ApuChannelState) {
+        let voice = &mut self.voices[channel];
+        if state.muted {
+            voice.key_on_pending = false;
+            voice.key_off = true;
+        } else {
+            let mean_volume = ((state.volume.0 as f64).abs() + (state.volume.1 as f64).abs()) / 256.0;
+            let level = mean_volume * (state.envelope_level as f64 / 2047.0);
+            voice.peak_level = voice.peak_level.max(level);
+            voice.key_on_pending |= state.key_on;
+            voice.key_off |= state.key_off;
+        }
+        voice.last_state = Some(state);
+    }
+
+    fn receive_master(&mut self, _state: ApuMasterState) {
+        let tick = self.current_tick();
+        self.sample_index += 1;
+        if self.current_tick() == tick {
+            return;
+        }
+
+        for (channel, voice) in self.voices.iter_mut().enumerate() {
+            voice.end_frame(tick, channel as u8, &self.sample_data);
+        }
+    }
+}
+
+fn write_varint(data: &mut Vec<u8>, value: u64) {
+    let mut bytes = vec![(value & 0x7f) as u8];
+    let mut value = value >> 7;
+    while value != 0 {
+        bytes.push(0x80 | (value & 0x7f) as u8);
+        value >>= 7;
+    }
+    data.extend(bytes.iter().rev());
+}
+
+fn write_track(events: &[(u64, Vec<u8>)]) -> Vec<u8> {
+    let mut data = Vec::new();
+    let mut last_tick = 0;
+    for (tick, event) in events {
+        write_varint(&mut data, tick - last_tick);
+        data.extend_from_slice(event);
+        last_tick = *tick;
+    }
+
+    // End of track
+    write_varint(&mut data, 0);
+    data.extend_from_slice(&[0xff, 0x2f, 0x00]);
+    data
+}
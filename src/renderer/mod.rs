@@ -1,36 +1,162 @@
 pub mod render_options;
+mod loop_detector;
+mod midi_export;
 
-use anyhow::{Result};
+use anyhow::{Result, Context, bail};
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 use ringbuf::{HeapRb, Rb};
 use ringbuf::ring_buffer::RbBase;
 use render_options::RendererOptions;
-use crate::emulator::Emulator;
-use crate::renderer::render_options::StopCondition;
+use snes_apu_spcp::{ApuChannelState, ApuMasterState, ApuStateReceiver};
+use crate::emulator::{Emulator, STEM_COUNT};
+use crate::renderer::loop_detector::LoopDetector;
+use crate::renderer::midi_export::MidiRecorder;
+use crate::renderer::render_options::{StartCondition, StopCondition, FRAME_RATE};
 use crate::video_builder;
 use crate::video_builder::VideoBuilder;
+use crate::video_builder::audio_builder::AudioBuilder;
 use crate::visualizer::Visualizer;
 
+const FRAME_DELAY: u64 = 6;
+const MAX_FIRST_NOTE_FRAMES: u64 = 5 * 60 * 60;
+const FIRST_NOTE_SNAPSHOT_INTERVAL: u64 = 60;
+
+#[derive(Default)]
+struct FirstNoteDetector(bool);
+
+impl ApuStateReceiver for FirstNoteDetector {
+    fn receive_channel(&mut self, _channel: usize, state: ApuChannelState) {
+        if state.key_on && !state.muted && (state.volume.0 != 0 || state.volume.1 != 0) {
+            self.0 = true;
+        }
+    }
+}
+
+// Forwards APU state to the visualizer and, when exporting MIDI, the MIDI recorder
+struct RenderStateReceiver {
+    viz: Arc<Mutex<Visualizer>>,
+    midi_recorder: Option<Arc<Mutex<MidiRecorder>>>
+}
+
+impl ApuStateReceiver for RenderStateReceiver {
+    fn receive_channel(&mut self, channel: usize, state: ApuChannelState) {
+        if let Some(midi_recorder) = &self.midi_recorder {
+            midi_recorder.lock().unwrap().receive_channel(channel, state);
+        }
+        self.viz.lock().unwrap().receive_channel(channel, state);
+    }
+
+    fn receive_master(&mut self, state: ApuMasterState) {
+        if let Some(midi_recorder) = &self.midi_recorder {
+            midi_recorder.lock().unwrap().receive_master(state);
+        }
+        self.viz.lock().unwrap().receive_master(state);
+    }
+}
+
+fn stem_output_path(output_path: &str, stem: usize, format: &str) -> PathBuf {
+    let output_path = Path::new(output_path);
+    let stem_name = if stem == STEM_COUNT - 1 {
+        "echo".to_string()
+    } else {
+        format!("voice{}", stem + 1)
+    };
+    let file_name = format!("{}_{}.{}", output_path.file_stem().unwrap().to_string_lossy(), stem_name, format);
+
+    output_path.with_file_name(file_name)
+}
+
+fn skip_to_first_note(emulator: &mut Emulator) -> Result<u64> {
+    let detector = Arc::new(Mutex::new(FirstNoteDetector::default()));
+    emulator.set_state_receiver(Some(detector.clone()));
+
+    let mut snapshot_frame = 0;
+    let mut snapshot = emulator.save_state();
+    let mut skipped_frames = 0;
+    loop {
+        if skipped_frames >= MAX_FIRST_NOTE_FRAMES {
+            bail!("No audible notes found in the first {} seconds", MAX_FIRST_NOTE_FRAMES / 60);
+        }
+
+        emulator.skip(1);
+        if detector.lock().unwrap().0 {
+            break;
+        }
+        skipped_frames += 1;
+
+        if skipped_frames % FIRST_NOTE_SNAPSHOT_INTERVAL == 0 {
+            snapshot_frame = skipped_frames;
+            snapshot = emulator.save_state();
+        }
+    }
+
+    // Rewind to the start of the frame containing the key-on so the attack is kept
+    emulator.set_state_receiver(None);
+    emulator.load_state(&snapshot)?;
+    emulator.fast_forward((skipped_frames - snapshot_frame) as usize);
+
+    Ok(skipped_frames)
+}
+
+/// Advances the emulator to where a render with this start condition begins, returning the
+/// number of frames skipped.
+pub fn skip_to_start(emulator: &mut Emulator, start_condition: StartCondition) -> Result<u64> {
+    match start_condition {
+        StartCondition::Frames(frames) => {
+            emulator.fast_forward(frames as usize);
+            Ok(frames)
+        },
+        StartCondition::FirstNote => skip_to_first_note(emulator)
+    }
+}
+
+/// Advances the emulator to the start of the song's first loop, returning the number of frames skipped.
+pub fn skip_to_loop_start(emulator: &mut Emulator) -> Result<u64> {
+    let loop_info = LoopDetector::new().detect(emulator)?
+        .context("Could not detect a loop in this song")?;
+
+    emulator.fast_forward(loop_info.start_frame as usize);
+    Ok(loop_info.start_frame)
+}
+
 pub struct Renderer {
     options: RendererOptions,
     emulator: Emulator,
     viz: Arc<Mutex<Visualizer>>,
     vb: VideoBuilder,
+    stems: Vec<AudioBuilder>,
+    midi_recorder: Option<Arc<Mutex<MidiRecorder>>>,
 
     cur_frame: u64,
+    start_offset: u64,
     encode_start: Instant,
     frame_timestamp: f64,
     frame_times: HeapRb<f64>,
     loop_count: u64,
+    loop_start: Option<u64>,
     loop_duration: Option<u64>,
+    spc_duration: Option<u64>,
+    fadeout_length: u64,
     fadeout_timer: Option<u64>,
-    expected_duration: Option<usize>
+    expected_duration: Option<usize>,
+    heard_audio: bool,
+    silent_samples: u64
 }
 
 impl Renderer {
     pub fn new(options: RendererOptions) -> Result<Self> {
         let emulator = Emulator::from_spc(options.input_path.clone(), options.video_options.sample_rate as u32)?;
+        Self::with_emulator(options, emulator)
+    }
+
+    pub fn from_spc_bytes(options: RendererOptions, data: &[u8]) -> Result<Self> {
+        let emulator = Emulator::from_spc_bytes(data, options.video_options.sample_rate as u32)?;
+        Self::with_emulator(options, emulator)
+    }
+
+    fn with_emulator(options: RendererOptions, emulator: Emulator) -> Result<Self> {
         let viz = Arc::new(Mutex::new(Visualizer::new(
             8,
             options.video_options.resolution_in.0,
@@ -51,19 +177,38 @@ impl Renderer {
 
         let vb = VideoBuilder::new(video_options)?;
 
+        let mut stems = Vec::new();
+        if let Some(stems_format) = &options.stems_format {
+            for stem in 0..STEM_COUNT {
+                let stem_path = stem_output_path(&options.video_options.output_path, stem, stems_format);
+                stems.push(AudioBuilder::new(stem_path, options.video_options.sample_rate)?);
+            }
+        }
+
+        let midi_recorder = options.midi_path.as_ref()
+            .map(|_| Arc::new(Mutex::new(MidiRecorder::new(8, options.sample_tunings.clone()))));
+
         Ok(Self {
             options: options.clone(),
             emulator,
             viz,
             vb,
+            stems,
+            midi_recorder,
             cur_frame: 0,
+            start_offset: 0,
             encode_start: Instant::now(),
             frame_timestamp: 0.0,
             frame_times: HeapRb::new(600),
             loop_count: 0,
+            loop_start: None,
             loop_duration: None,
+            spc_duration: None,
+            fadeout_length: options.fadeout_length,
             fadeout_timer: None,
-            expected_duration: None
+            expected_duration: None,
+            heard_audio: false,
+            silent_samples: 0
         })
     }
 
@@ -73,11 +218,48 @@ impl Renderer {
         if !self.options.script700_path.is_empty() {
             self.emulator.load_script700(&self.options.script700_path)?;
         }
+        if let Some(port_timeline_path) = &self.options.port_timeline_path {
+            self.emulator.load_port_timeline(port_timeline_path)?;
+        }
 
-        self.emulator.set_state_receiver(Some(self.viz.clone()));
         self.emulator.set_resampling_mode(self.options.config.emulator.resampling_mode);
         self.emulator.set_filter_enabled(self.options.config.emulator.filter_enabled);
-        self.emulator.set_frame_delay(6);
+        self.emulator.set_xid6_settings_enabled(self.options.config.emulator.apply_xid6_settings);
+        let (mute_mask, solo_mask) = self.options.config.piano_roll.settings.voice_masks();
+        self.emulator.set_voice_mask(mute_mask, solo_mask);
+
+        match self.options.stop_condition {
+            StopCondition::Loops(_) => self.detect_loop()?,
+            StopCondition::SpcDuration(loop_count) => {
+                let (duration_frames, _) = self.emulator.get_spc_duration(loop_count)
+                    .context("SPC file does not specify a song length, try a time-based stop condition instead")?;
+                self.spc_duration = Some(duration_frames);
+
+                // An xid6 end length means the song ends on its own and should not be faded,
+                // unless a fadeout length was asked for explicitly
+                if self.emulator.has_spc_end_length() && !self.options.fadeout_length_explicit {
+                    self.fadeout_length = 0;
+                }
+            },
+            _ => ()
+        }
+
+        self.start_offset = skip_to_start(&mut self.emulator, self.options.start_condition)?;
+        self.adjust_loop_start();
+
+        if let Some(trace_path) = &self.options.trace_path {
+            self.emulator.set_trace_file(trace_path)?;
+        }
+        if self.options.register_log_path.is_some() {
+            self.emulator.start_register_log();
+        }
+
+        self.emulator.set_state_receiver(Some(Arc::new(Mutex::new(RenderStateReceiver {
+            viz: self.viz.clone(),
+            midi_recorder: self.midi_recorder.clone()
+        }))));
+        self.emulator.set_frame_delay(FRAME_DELAY as usize);
+        self.emulator.set_stems_enabled(!self.stems.is_empty())?;
 
         if !self.options.per_sample_colors.is_empty() {
             self.viz.lock().unwrap().settings_manager_mut().put_per_sample_colors(self.options.per_sample_colors.clone());
@@ -88,6 +270,9 @@ impl Renderer {
         self.vb.set_roi_region(0, 0, roi_w, roi_h);
 
         self.vb.start_encoding()?;
+        for stem in self.stems.iter_mut() {
+            stem.start_encoding()?;
+        }
         self.encode_start = Instant::now();
 
         Ok(())
@@ -103,16 +288,21 @@ impl Renderer {
         }
 
         if let Some(audio) = self.emulator.get_audio_samples(Some(self.vb.audio_frame_size())) {
-            let adjusted_audio = match self.fadeout_timer {
-                Some(t) => {
-                    let volume_divisor = (self.options.fadeout_length as f64 / t as f64) as i16;
-                    audio.iter().map(|s| s / volume_divisor).collect()
-                },
-                None => audio
-            };
+            if let StopCondition::Silence { threshold_db, .. } = self.options.stop_condition {
+                self.update_silence(&audio, threshold_db);
+            }
+
+            let adjusted_audio = self.apply_fadeout(audio);
             self.vb.push_audio_data(video_builder::as_u8_slice(&adjusted_audio))?;
         }
 
+        for stem in 0..self.stems.len() {
+            if let Some(audio) = self.emulator.get_stem_samples(stem, None) {
+                let adjusted_audio = self.apply_fadeout(audio);
+                self.stems[stem].push_audio_data(&adjusted_audio)?;
+            }
+        }
+
         self.vb.step_encoding()?;
 
         let elapsed_secs = self.elapsed().as_secs_f64();
@@ -121,6 +311,7 @@ impl Renderer {
 
         self.frame_times.push_overwrite(frame_time);
 
+        self.loop_count = self.next_loop_count();
         self.expected_duration = self.next_expected_duration();
         self.fadeout_timer = self.next_fadeout_timer();
 
@@ -136,10 +327,43 @@ impl Renderer {
 
     pub fn finish_encoding(&mut self) -> Result<()> {
         self.vb.finish_encoding()?;
+        for stem in self.stems.iter_mut() {
+            stem.finish_encoding()?;
+        }
+        if let Some(register_log_path) = &self.options.register_log_path {
+            self.emulator.save_register_log(register_log_path)?;
+        }
+        if let (Some(midi_path), Some(midi_recorder)) = (&self.options.midi_path, &self.midi_recorder) {
+            midi_recorder.lock().unwrap().save(midi_path)?;
+        }
 
         Ok(())
     }
 
+    fn apply_fadeout(&self, audio: Vec<i16>) -> Vec<i16> {
+        match self.fadeout_timer {
+            Some(t) => {
+                let volume_divisor = (self.fadeout_length as f64 / t as f64) as i16;
+                audio.iter().map(|s| s / volume_divisor).collect()
+            },
+            None => audio
+        }
+    }
+
+    fn update_silence(&mut self, audio: &[i16], threshold_db: f64) {
+        let threshold = (i16::MAX as f64 * 10f64.powf(threshold_db / 20.0)) as i32;
+
+        // Count consecutive stereo samples under the threshold, ignoring any lead-in before the first sound
+        for sample in audio.chunks_exact(2) {
+            if sample.iter().any(|&s| (s as i32).abs() > threshold) {
+                self.heard_audio = true;
+                self.silent_samples = 0;
+            } else if self.heard_audio {
+                self.silent_samples += 1;
+            }
+        }
+    }
+
     pub fn current_frame(&self) -> u64 {
         self.cur_frame
     }
@@ -148,21 +372,53 @@ impl Renderer {
         self.encode_start.elapsed()
     }
 
+    fn detect_loop(&mut self) -> Result<()> {
+        match LoopDetector::new().detect(&mut self.emulator)? {
+            Some(loop_info) => {
+                self.loop_start = Some(loop_info.start_frame + FRAME_DELAY);
+                self.loop_duration = Some(loop_info.length_frames.max(1));
+                Ok(())
+            },
+            None => bail!("Could not detect a loop in this song, try a time-based stop condition instead")
+        }
+    }
+
+    fn adjust_loop_start(&mut self) {
+        // Loop passes are counted from the first loop point at or after the start offset
+        if let (Some(start), Some(duration)) = (self.loop_start, self.loop_duration) {
+            let song_start = start - FRAME_DELAY;
+            let skipped_loops = self.start_offset.saturating_sub(song_start).div_ceil(duration);
+            self.loop_start = Some(song_start + skipped_loops * duration - self.start_offset + FRAME_DELAY);
+        }
+    }
+
+    fn next_loop_count(&self) -> u64 {
+        match (self.loop_start, self.loop_duration) {
+            (Some(start), Some(duration)) if self.cur_frame >= start => (self.cur_frame - start) / duration,
+            _ => 0
+        }
+    }
+
     fn next_expected_duration(&self) -> Option<usize> {
         if self.expected_duration.is_some() {
             return self.expected_duration;
         }
 
         match self.options.stop_condition {
-            StopCondition::Frames(stop_frames) => Some((stop_frames + self.options.fadeout_length) as usize),
+            StopCondition::Frames(stop_frames) => Some((stop_frames + self.fadeout_length) as usize),
             StopCondition::Loops(stop_loop_count) => {
-                match self.loop_duration {
-                    Some(d) => Some(self.options.fadeout_length as usize + d as usize * stop_loop_count),
-                    None => None
+                match (self.loop_start, self.loop_duration) {
+                    (Some(s), Some(d)) => Some(self.fadeout_length as usize + s as usize + d as usize * stop_loop_count),
+                    _ => None
                 }
             },
-            StopCondition::SpcDuration => {
-                Some((self.emulator.get_spc_metadata()?.duration_frames + self.options.fadeout_length) as usize)
+            StopCondition::SpcDuration(_) => {
+                let duration_frames = self.spc_duration?.saturating_sub(self.start_offset);
+                Some((duration_frames + self.fadeout_length) as usize)
+            },
+            StopCondition::Silence { .. } => {
+                // Not known ahead of time, settles once the silence has been detected
+                self.fadeout_timer.map(|t| (self.cur_frame + t) as usize)
             }
         }
     }
@@ -173,32 +429,36 @@ impl Renderer {
             Some(t) => Some(t - 1),
             None => {
                 // if self.last_position.end {
-                //     return Some(self.options.fadeout_length);
+                //     return Some(self.fadeout_length);
                 // }
 
                 match self.options.stop_condition {
                     StopCondition::Loops(stop_loop_count) => {
                         if self.loop_count >= stop_loop_count as u64 {
-                            Some(self.options.fadeout_length)
+                            Some(self.fadeout_length)
                         } else {
                             None
                         }
                     },
                     StopCondition::Frames(stop_frames) => {
                         if self.current_frame() >= stop_frames {
-                            Some(self.options.fadeout_length)
+                            Some(self.fadeout_length)
+                        } else {
+                            None
+                        }
+                    },
+                    StopCondition::SpcDuration(_) => {
+                        let duration = self.spc_duration?;
+                        if self.current_frame() + self.start_offset >= duration {
+                            Some(self.fadeout_length)
                         } else {
                             None
                         }
                     },
-                    StopCondition::SpcDuration => {
-                        let duration = match self.emulator.get_spc_metadata() {
-                            Some(metadata) => metadata.duration_frames,
-                            None => panic!("SPC file does not have duration tag")
-                        };
-
-                        if self.current_frame() >= duration {
-                            Some(self.options.fadeout_length)
+                    StopCondition::Silence { hold_frames, .. } => {
+                        let hold_samples = hold_frames * self.options.video_options.sample_rate as u64 / FRAME_RATE as u64;
+                        if self.heard_audio && self.silent_samples >= hold_samples {
+                            Some(self.fadeout_length)
                         } else {
                             None
                         }
@@ -28,7 +28,8 @@ macro_rules! extra_str_traits {
 pub enum StopCondition {
     Frames(u64),
     Loops(usize),
-    SpcDuration
+    SpcDuration(Option<u32>),
+    Silence { threshold_db: f64, hold_frames: u64 }
 }
 
 impl FromStr for StopCondition {
@@ -36,14 +37,32 @@ impl FromStr for StopCondition {
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let parts: Vec<_> = s.split(':').collect();
+        if parts[0] == "silence" {
+            if parts.len() != 3 {
+                return Err("Silence stop condition format invalid, try 'silence:-60:120' (threshold in dB, hold time in frames).".to_string());
+            }
+
+            let threshold_db = f64::from_str(parts[1]).map_err(|e| e.to_string())?;
+            if threshold_db > 0.0 {
+                return Err("Silence threshold must not be above 0 dB".to_string());
+            }
+            let hold_frames = u64::from_str(parts[2]).map_err(|e| e.to_string())?;
+            return Ok(StopCondition::Silence { threshold_db, hold_frames });
+        }
+
+        if parts.len() == 3 && parts[0] == "time" && parts[1] == "spc" {
+            let loop_count = u32::from_str(parts[2]).map_err(|e| e.to_string())?;
+            return Ok(StopCondition::SpcDuration(Some(loop_count)));
+        }
+
         if parts.len() != 2 {
-            return Err("Stop condition format invalid, try one of 'time:30', 'time:spc', or 'frames:1800'.".to_string());
+            return Err("Stop condition format invalid, try one of 'time:30', 'time:spc', 'time:spc:3', 'frames:1800', 'loops:2', or 'silence:-60:120'.".to_string());
         }
 
         match parts[0] {
             "time" => {
                 if parts[1] == "spc" {
-                    Ok(StopCondition::SpcDuration)
+                    Ok(StopCondition::SpcDuration(None))
                 } else {
                     let time = u64::from_str(parts[1]).map_err( | e | e.to_string()) ?;
                     Ok(StopCondition::Frames(time * FRAME_RATE as u64))
@@ -57,21 +76,63 @@ impl FromStr for StopCondition {
                 let loops = usize::from_str(parts[1]).map_err(|e| e.to_string())?;
                 Ok(StopCondition::Loops(loops))
             },
-            _ => Err(format!("Unknown condition type {}. Valid types are 'time', 'frames', and 'loops'", parts[0]))
+            _ => Err(format!("Unknown condition type {}. Valid types are 'time', 'frames', 'loops', and 'silence'", parts[0]))
         }
     }
 }
 
 extra_str_traits!(StopCondition);
 
+#[derive(Copy, Clone)]
+pub enum StartCondition {
+    Frames(u64),
+    FirstNote
+}
+
+impl FromStr for StartCondition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "first-note" {
+            return Ok(StartCondition::FirstNote);
+        }
+
+        let parts: Vec<_> = s.split(':').collect();
+        if parts.len() != 2 {
+            return Err("Start condition format invalid, try one of 'time:30', 'frames:1800', or 'first-note'.".to_string());
+        }
+
+        match parts[0] {
+            "time" => {
+                let time = u64::from_str(parts[1]).map_err(|e| e.to_string())?;
+                Ok(StartCondition::Frames(time * FRAME_RATE as u64))
+            },
+            "frames" => {
+                let frames = u64::from_str(parts[1]).map_err(|e| e.to_string())?;
+                Ok(StartCondition::Frames(frames))
+            },
+            _ => Err(format!("Unknown condition type {}. Valid types are 'time', 'frames', and 'first-note'", parts[0]))
+        }
+    }
+}
+
+extra_str_traits!(StartCondition);
+
 #[derive(Clone)]
 pub struct RendererOptions {
     pub input_path: String,
     pub script700_path: String,
     pub video_options: VideoOptions,
 
+    pub start_condition: StartCondition,
     pub stop_condition: StopCondition,
     pub fadeout_length: u64,
+    pub fadeout_length_explicit: bool,
+    pub stems_format: Option<String>,
+    pub trace_path: Option<String>,
+    pub register_log_path: Option<String>,
+    pub port_timeline_path: Option<String>,
+    pub midi_path: Option<String>,
 
     pub config: Config,
     pub sample_tunings: HashMap<u8, SampleData>,
@@ -103,8 +164,15 @@ impl Default for RendererOptions {
                 sample_format_out: "fltp".to_string(),
                 sample_rate: 44_100,
             },
+            start_condition: StartCondition::Frames(0),
             stop_condition: StopCondition::Frames(300 * FRAME_RATE as u64),
             fadeout_length: 180,
+            fadeout_length_explicit: false,
+            stems_format: None,
+            trace_path: None,
+            register_log_path: None,
+            port_timeline_path: None,
+            midi_path: None,
             config: Config::default(),
             sample_tunings: HashMap::new(),
             per_sample_colors: HashMap::new()
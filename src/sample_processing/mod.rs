@@ -5,7 +5,13 @@ mod loudness;
 mod filter;
 mod simd_sum;
 mod sample_processing;
+mod sample_export;
+mod sample_import;
+mod sample_cache;
 
 pub use yin::*;
 pub use loudness::*;
 pub use sample_processing::*;
+pub use sample_export::*;
+pub use sample_import::*;
+pub use sample_cache::brr_hash;
@@ -0,0 +1,73 @@
+use anyhow::Result;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use serde::{Serialize, Deserialize};
+use crate::emulator::BrrSample;
+
+const CACHE_DIR_NAME: &str = "spc-presenter-rs";
+// Bump when the analysis changes in a way the parameters in the key don't capture
+const CACHE_VERSION: u64 = 1;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// Analysis results for one sample, along with the tuning the user last chose for it.
+#[derive(Serialize, Deserialize)]
+pub(super) struct CachedAnalysis {
+    pub base_pitch: f64,
+    pub temporal_pitch: Vec<f64>,
+    pub loudness: Vec<f64>,
+    pub custom_pitch: Option<f64>,
+    pub temporal_pitch_octave_offset: f64
+}
+
+fn cache_dir() -> Option<PathBuf> {
+    let base_dir = if cfg!(windows) {
+        env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        env::var_os("HOME").map(|home| PathBuf::from(home).join("Library").join("Caches"))
+    } else {
+        env::var_os("XDG_CACHE_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+    };
+
+    base_dir.map(|dir| dir.join(CACHE_DIR_NAME).join("samples"))
+}
+
+fn entry_path(key: u64) -> Option<PathBuf> {
+    cache_dir().map(|dir| dir.join(format!("{:016x}.json", key)))
+}
+
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, &byte| (hash ^ byte as u64).wrapping_mul(FNV_PRIME))
+}
+
+/// FNV-1a over each length-prefixed part, so keys stay stable across builds and platforms.
+pub(super) fn cache_key(parts: &[&[u8]]) -> u64 {
+    parts.iter().fold(fnv1a(FNV_OFFSET_BASIS, &CACHE_VERSION.to_le_bytes()), |hash, part| {
+        fnv1a(fnv1a(hash, &(part.len() as u64).to_le_bytes()), part)
+    })
+}
+
+/// FNV-1a of the sample's BRR data, for recognizing the same sample in other songs.
+pub fn brr_hash(sample: &BrrSample) -> u64 {
+    fnv1a(FNV_OFFSET_BASIS, &sample.to_bytes())
+}
+
+pub(super) fn load(key: u64) -> Option<CachedAnalysis> {
+    let json = fs::read_to_string(entry_path(key)?).ok()?;
+    serde_json::from_str(&json).ok()
+}
+
+pub(super) fn store(key: u64, analysis: &CachedAnalysis) -> Result<()> {
+    let Some(path) = entry_path(key) else {
+        return Ok(());
+    };
+
+    fs::create_dir_all(path.parent().unwrap())?;
+    fs::write(path, serde_json::to_string(analysis)?)?;
+    Ok(())
+}
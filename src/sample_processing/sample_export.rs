@@ -0,0 +1,106 @@
+use anyhow::Result;
+use std::fs;
+use std::path::Path;
+use crate::emulator::{BrrSample, Emulator};
+use crate::tuning::instrument_table;
+use super::SampleData;
+
+const SAMPLE_RATE: u32 = 32000;
+
+fn push_chunk(data: &mut Vec<u8>, id: &[u8; 4], body: &[u8]) {
+    data.extend_from_slice(id);
+    data.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    data.extend_from_slice(body);
+    if !body.len().is_multiple_of(2) {
+        data.push(0);
+    }
+}
+
+fn push_u32s(data: &mut Vec<u8>, values: &[u32]) {
+    for value in values {
+        data.extend_from_slice(&value.to_le_bytes());
+    }
+}
+
+// MIDI note of the sample's pitch, and the fraction of a semitone above it scaled to 32 bits
+fn midi_unity_note(pitch: f64) -> (u32, u32) {
+    if pitch <= 0.0 {
+        return (60, 0);
+    }
+
+    let note = (69.0 + 12.0 * (pitch / 440.0).log2()).clamp(0.0, 127.0);
+    let scaled_note = (note * 2f64.powi(32)).round() as u64;
+    ((scaled_note >> 32) as u32, scaled_note as u32)
+}
+
+/// Encodes a sample as a 16-bit mono WAV file at 32kHz. A `smpl` chunk holds the loop points
+/// and the sample's pitch as the MIDI unity note, so samplers can play it back in tune.
+pub fn sample_to_wav(sample: &BrrSample, pitch: f64) -> Vec<u8> {
+    let sample_count = (sample.start_block_count() + sample.loop_block_count()) * 16;
+    let pcm: Vec<u8> = sample.clone()
+        .into_iter()
+        .take(sample_count)
+        .flat_map(|s| s.to_le_bytes())
+        .collect();
+
+    let mut fmt = Vec::new();
+    fmt.extend_from_slice(&1u16.to_le_bytes());
+    fmt.extend_from_slice(&1u16.to_le_bytes());
+    push_u32s(&mut fmt, &[SAMPLE_RATE, SAMPLE_RATE * 2]);
+    fmt.extend_from_slice(&2u16.to_le_bytes());
+    fmt.extend_from_slice(&16u16.to_le_bytes());
+
+    let (unity_note, pitch_fraction) = midi_unity_note(pitch);
+    let loop_start = sample.loop_offset().map(|offset| (offset / 9 * 16) as u32);
+    let mut smpl = Vec::new();
+    push_u32s(&mut smpl, &[
+        0, 0,
+        1_000_000_000 / SAMPLE_RATE,
+        unity_note,
+        pitch_fraction,
+        0, 0,
+        loop_start.is_some() as u32,
+        0
+    ]);
+    if let Some(loop_start) = loop_start {
+        // Forward loop up to and including the last sample
+        push_u32s(&mut smpl, &[0, 0, loop_start, sample_count as u32 - 1, 0, 0]);
+    }
+
+    let mut body = b"WAVE".to_vec();
+    push_chunk(&mut body, b"fmt ", &fmt);
+    push_chunk(&mut body, b"smpl", &smpl);
+    push_chunk(&mut body, b"data", &pcm);
+
+    let mut result = Vec::new();
+    push_chunk(&mut result, b"RIFF", &body);
+    result
+}
+
+/// Analyzes every valid sample in the SPC's source directory and writes each one to
+/// `output_dir` as `sample_XX.brr` and `sample_XX.wav`. Returns the number of samples written.
+pub fn dump_all_samples<P: AsRef<Path>, Q: AsRef<Path>>(spc_path: P, output_dir: Q) -> Result<usize> {
+    let mut emulator = Emulator::from_spc(spc_path, 44_100)?;
+    emulator.init();
+
+    let instrument_tunings = instrument_table::read_instrument_tunings(emulator.spc_file())
+        .map(|(_driver, instrument_tunings)| instrument_tunings)
+        .unwrap_or_default();
+
+    fs::create_dir_all(&output_dir)?;
+
+    let samples = emulator.dump_all_samples();
+    for (source, sample) in samples.iter() {
+        println!("Processing sample ${:x}...", source);
+        let mut sample_data = SampleData::new_cached(sample.clone())?;
+        if let Some(&(tuning, subtuning)) = instrument_tunings.get(source) {
+            sample_data.set_instrument_tuning(tuning, subtuning);
+        }
+
+        let path = output_dir.as_ref().join(format!("sample_{:02x}", source));
+        fs::write(path.with_extension("brr"), sample.to_bytes())?;
+        fs::write(path.with_extension("wav"), sample_to_wav(sample, sample_data.tuned_pitch()))?;
+    }
+
+    Ok(samples.len())
+}
@@ -0,0 +1,113 @@
+use anyhow::{Context, Result, bail};
+use std::fs;
+use std::path::Path;
+
+const WAVE_FORMAT_PCM: u16 = 1;
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 3;
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xfffe;
+
+pub struct WavSample {
+    pub samples: Vec<i16>,
+    pub sample_rate: u32,
+    pub loop_start: Option<usize>
+}
+
+struct WavFormat {
+    format_tag: u16,
+    channels: usize,
+    sample_rate: u32,
+    bits_per_sample: u16
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(data.get(offset..(offset + 2))?.try_into().unwrap()))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(offset..(offset + 4))?.try_into().unwrap()))
+}
+
+fn parse_format(fmt: &[u8]) -> Result<WavFormat> {
+    let mut format_tag = read_u16(fmt, 0).context("Truncated fmt chunk")?;
+    if format_tag == WAVE_FORMAT_EXTENSIBLE {
+        // The sub-format GUID starts with the actual format tag
+        format_tag = read_u16(fmt, 24).context("Truncated fmt chunk")?;
+    }
+
+    Ok(WavFormat {
+        format_tag,
+        channels: read_u16(fmt, 2).context("Truncated fmt chunk")? as usize,
+        sample_rate: read_u32(fmt, 4).context("Truncated fmt chunk")?,
+        bits_per_sample: read_u16(fmt, 14).context("Truncated fmt chunk")?
+    })
+}
+
+fn decode_frame(format: &WavFormat, frame: &[u8]) -> i16 {
+    let bytes_per_sample = format.bits_per_sample as usize / 8;
+    let sum: f64 = frame.chunks_exact(bytes_per_sample)
+        .map(|s| match (format.format_tag, format.bits_per_sample) {
+            (WAVE_FORMAT_PCM, 8) => (s[0] as f64 - 128.0) * 256.0,
+            (WAVE_FORMAT_PCM, 16) => i16::from_le_bytes([s[0], s[1]]) as f64,
+            (WAVE_FORMAT_PCM, 24) => (i32::from_le_bytes([0, s[0], s[1], s[2]]) >> 16) as f64,
+            (WAVE_FORMAT_PCM, _) => (i32::from_le_bytes([s[0], s[1], s[2], s[3]]) >> 16) as f64,
+            _ => f32::from_le_bytes([s[0], s[1], s[2], s[3]]) as f64 * 32768.0
+        })
+        .sum();
+
+    // Mix down to mono
+    (sum / format.channels as f64).round().clamp(i16::MIN as f64, i16::MAX as f64) as i16
+}
+
+/// Reads a PCM or floating-point WAV file, mixed down to 16-bit mono. The loop start is taken
+/// from the first loop in the `smpl` chunk, and the sample is cut off after that loop's end.
+pub fn read_wav<P: AsRef<Path>>(path: P) -> Result<WavSample> {
+    let data = fs::read(path)?;
+    if data.get(0..4) != Some(b"RIFF") || data.get(8..12) != Some(b"WAVE") {
+        bail!("Not a WAV file");
+    }
+
+    let mut format = None;
+    let mut pcm = None;
+    let mut sample_loop = None;
+
+    let mut ptr = 12;
+    while let (Some(id), Some(len)) = (data.get(ptr..(ptr + 4)), read_u32(&data, ptr + 4)) {
+        let body = data.get((ptr + 8)..(ptr + 8 + len as usize)).context("Truncated WAV chunk")?;
+        match id {
+            b"fmt " => format = Some(parse_format(body)?),
+            b"data" => pcm = Some(body),
+            b"smpl" if read_u32(body, 28).unwrap_or(0) > 0 => {
+                sample_loop = read_u32(body, 44).zip(read_u32(body, 48));
+            }
+            _ => ()
+        }
+        ptr += 8 + len as usize + (len as usize % 2);
+    }
+
+    let format = format.context("WAV file has no fmt chunk")?;
+    let pcm = pcm.context("WAV file has no data chunk")?;
+    let supported = match format.format_tag {
+        WAVE_FORMAT_PCM => matches!(format.bits_per_sample, 8 | 16 | 24 | 32),
+        WAVE_FORMAT_IEEE_FLOAT => format.bits_per_sample == 32,
+        _ => false
+    };
+    if !supported || format.channels == 0 {
+        bail!("Unsupported WAV format (format {}, {} bits, {} channels)", format.format_tag, format.bits_per_sample, format.channels);
+    }
+
+    let frame_size = format.channels * format.bits_per_sample as usize / 8;
+    let mut samples: Vec<i16> = pcm.chunks_exact(frame_size)
+        .map(|frame| decode_frame(&format, frame))
+        .collect();
+
+    let loop_start = sample_loop.map(|(start, end)| {
+        samples.truncate(end as usize + 1);
+        start as usize
+    });
+
+    Ok(WavSample {
+        samples,
+        sample_rate: format.sample_rate,
+        loop_start
+    })
+}
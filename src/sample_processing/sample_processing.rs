@@ -1,12 +1,16 @@
 use std::sync::{Arc, Mutex};
 use std::collections::{HashMap, VecDeque};
 use std::path::Path;
+use std::io::{Cursor, Read, Seek};
 use std::thread;
 use std::time::Duration;
 use anyhow::{Result, anyhow};
 use snes_apu_spcp::{ApuChannelState, ApuMasterState, ApuStateReceiver};
-use crate::emulator::{Emulator, BrrSample};
-use super::{sample_loudness, util, Yin};
+use crate::emulator::{Emulator, BrrSample, PortTimeline};
+use crate::tuning;
+use crate::tuning::instrument_table::InstrumentTunings;
+use super::{sample_cache, sample_loudness, util, Yin};
+use super::sample_cache::CachedAnalysis;
 
 const F_MIN: f64 = 62.5;
 const F_MAX: f64 = 4000.0;
@@ -14,6 +18,8 @@ const SAMPLE_RATE: f64 = 32000.0;
 const BASE_PITCH_TROUGH_THRESHOLD: f64 = 0.2;
 const FRAME_LENGTH: usize = 2048;
 const HOP_LENGTH: usize = 256;
+// Cached analyses of looped samples repeat the loop until it has played for at least this many samples
+const CACHED_LOOP_LENGTH: usize = 64000;
 
 #[derive(Clone)]
 pub struct SampleData {
@@ -22,7 +28,11 @@ pub struct SampleData {
     temporal_pitch: Vec<f64>,
     temporal_pitch_octave_offset: f64,
     custom_pitch: Option<f64>,
-    loudness: Vec<f64>
+    instrument_tuning: Option<(u8, u8)>,
+    loudness: Vec<f64>,
+    analysis_blocks: usize,
+    percussion: bool,
+    cache_key: Option<u64>
 }
 
 impl Default for SampleData {
@@ -33,7 +43,11 @@ impl Default for SampleData {
             temporal_pitch: vec![],
             temporal_pitch_octave_offset: 0.0,
             custom_pitch: None,
-            loudness: vec![]
+            instrument_tuning: None,
+            loudness: vec![],
+            analysis_blocks: 0,
+            percussion: false,
+            cache_key: None
         }
     }
 }
@@ -112,10 +126,75 @@ impl SampleData {
             temporal_pitch,
             temporal_pitch_octave_offset: 0.0,
             custom_pitch: None,
-            loudness
+            instrument_tuning: None,
+            loudness,
+            analysis_blocks: sample_count.div_ceil(16),
+            percussion: false,
+            cache_key: None
         })
     }
 
+    /// Like `new`, but reuses the analysis (and the user's tuning) cached for identical BRR data.
+    /// The analysis length only depends on the sample: all of it without a loop, or the start
+    /// followed by enough repetitions of the loop to cover `CACHED_LOOP_LENGTH`.
+    pub fn new_cached(sample: BrrSample) -> Result<Self> {
+        let sample_count = match sample.loop_block_count() * 16 {
+            0 => sample.start_block_count() * 16,
+            loop_length => sample.start_block_count() * 16 + CACHED_LOOP_LENGTH.div_ceil(loop_length) * loop_length
+        };
+        let cache_key = sample_cache::cache_key(&[
+            &sample.to_bytes(),
+            &(CACHED_LOOP_LENGTH as u64).to_le_bytes(),
+            &F_MIN.to_le_bytes(),
+            &F_MAX.to_le_bytes(),
+            &BASE_PITCH_TROUGH_THRESHOLD.to_le_bytes(),
+            &(FRAME_LENGTH as u64).to_le_bytes(),
+            &(HOP_LENGTH as u64).to_le_bytes()
+        ]);
+
+        if let Some(cached) = sample_cache::load(cache_key) {
+            println!("Using cached analysis {:016x}: f_0={} Hz", cache_key, cached.base_pitch);
+            return Ok(Self {
+                sample,
+                base_pitch: cached.base_pitch,
+                temporal_pitch: cached.temporal_pitch,
+                temporal_pitch_octave_offset: cached.temporal_pitch_octave_offset,
+                custom_pitch: cached.custom_pitch,
+                instrument_tuning: None,
+                loudness: cached.loudness,
+                analysis_blocks: sample_count / 16,
+                percussion: false,
+                cache_key: Some(cache_key)
+            });
+        }
+
+        let mut result = Self::new(sample, sample_count)?;
+        result.cache_key = Some(cache_key);
+        result.update_cache();
+        Ok(result)
+    }
+
+    /// Saves the analysis and the current user tuning to the cache, if this sample came from it.
+    /// Tunings read from an instrument table belong to the song, so they aren't saved.
+    pub fn update_cache(&self) {
+        let Some(cache_key) = self.cache_key else {
+            return;
+        };
+
+        let instrument_pitch = self.instrument_tuning
+            .map(|(tuning, subtuning)| tuning::amk_tuning_frequency(tuning, subtuning));
+        let analysis = CachedAnalysis {
+            base_pitch: self.base_pitch,
+            temporal_pitch: self.temporal_pitch.clone(),
+            loudness: self.loudness.clone(),
+            custom_pitch: self.custom_pitch.filter(|&pitch| Some(pitch) != instrument_pitch),
+            temporal_pitch_octave_offset: self.temporal_pitch_octave_offset
+        };
+        if let Err(e) = sample_cache::store(cache_key, &analysis) {
+            println!("WARNING: Failed to cache sample analysis: {}", e);
+        }
+    }
+
     pub fn sample(&self) -> &BrrSample {
         &self.sample
     }
@@ -124,31 +203,73 @@ impl SampleData {
         self.base_pitch
     }
 
+    /// The sample's fundamental frequency, taking custom tunings and the octave offset into account.
+    pub fn tuned_pitch(&self) -> f64 {
+        self.custom_pitch.unwrap_or(self.base_pitch * self.temporal_pitch_octave_offset.exp2())
+    }
+
     pub fn temporal_pitch_octave_offset(&self) -> f64 {
         self.temporal_pitch_octave_offset
     }
 
+    // Past the end of the analysis a looped sample keeps repeating its loop, so look up the last
+    // whole repetition that was analyzed
+    fn analysis_block_index(&self, sample_block_index: usize) -> usize {
+        let (start_blocks, loop_blocks) = (self.sample.start_block_count(), self.sample.loop_block_count());
+        if loop_blocks == 0 || sample_block_index < self.analysis_blocks {
+            return sample_block_index;
+        }
+
+        let analysis_end = start_blocks + self.analysis_blocks.saturating_sub(start_blocks) / loop_blocks * loop_blocks;
+        if analysis_end == start_blocks {
+            return sample_block_index;
+        }
+        analysis_end - loop_blocks + (sample_block_index - analysis_end) % loop_blocks
+    }
+
     pub fn pitch_at(&self, sample_block_index: usize) -> f64 {
         if let Some(pitch) = self.custom_pitch {
             return pitch;
         }
 
-        let x = sample_block_index as f64 * 16.0 / HOP_LENGTH as f64;
+        let x = self.analysis_block_index(sample_block_index) as f64 * 16.0 / HOP_LENGTH as f64;
         util::linear_interpolate(&self.temporal_pitch, x, self.base_pitch) * self.temporal_pitch_octave_offset.exp2()
     }
 
     pub fn loudness_at(&self, sample_block_index: usize) -> f64 {
-        let x = sample_block_index as f64 * 16.0 / HOP_LENGTH as f64;
+        let x = self.analysis_block_index(sample_block_index) as f64 * 16.0 / HOP_LENGTH as f64;
         util::linear_interpolate(&self.loudness, x, 0.0)
     }
 
+    pub fn custom_tuning(&self) -> Option<f64> {
+        self.custom_pitch
+    }
+
     pub fn set_custom_tuning(&mut self, custom_pitch: Option<f64>) {
         self.custom_pitch = custom_pitch;
     }
 
+    /// The tuning multiplier and submultiplier read from the sound driver's instrument table, if any.
+    pub fn instrument_tuning(&self) -> Option<(u8, u8)> {
+        self.instrument_tuning
+    }
+
+    pub fn set_instrument_tuning(&mut self, tuning: u8, subtuning: u8) {
+        self.instrument_tuning = Some((tuning, subtuning));
+        self.custom_pitch = Some(tuning::amk_tuning_frequency(tuning, subtuning));
+    }
+
     pub fn set_temporal_pitch_octave_offset(&mut self, offset: f64) {
         self.temporal_pitch_octave_offset = offset;
     }
+
+    pub fn percussion(&self) -> bool {
+        self.percussion
+    }
+
+    pub fn set_percussion(&mut self, percussion: bool) {
+        self.percussion = percussion;
+    }
 }
 
 pub enum SampleProcessorProgress {
@@ -195,19 +316,40 @@ pub struct SampleProcessor {
     sample_data: HashMap<u8, SampleData>,
     sample_detector: Arc<Mutex<SampleDetector>>,
     detected_sources: HashMap<u8, usize>,
-    processing_queue: VecDeque<(u8, BrrSample)>
+    processing_queue: VecDeque<(u8, BrrSample)>,
+    instrument_tunings: InstrumentTunings,
+    use_cache: bool
 }
 
 impl SampleProcessor {
     pub fn from_spc<P: AsRef<Path>>(spc_path: P) -> Result<Self> {
-        let mut emulator = Emulator::from_spc(spc_path, 44_100)?;
+        Self::from_emulator(Emulator::from_spc(spc_path, 44_100)?)
+    }
+
+    pub fn from_spc_reader<R: Read + Seek>(reader: R) -> Result<Self> {
+        Self::from_emulator(Emulator::from_spc_reader(reader, 44_100)?)
+    }
+
+    pub fn from_spc_bytes(data: &[u8]) -> Result<Self> {
+        Self::from_spc_reader(Cursor::new(data))
+    }
+
+    fn from_emulator(mut emulator: Emulator) -> Result<Self> {
         emulator.init();
 
-        let total_frames = match emulator.get_spc_metadata() {
-            Some(spc_metadata) => spc_metadata.duration_frames as usize,
+        let total_frames = match emulator.get_spc_duration(None) {
+            Some((duration_frames, _fadeout_frames)) => duration_frames as usize,
             None => 300 * 60
         };
 
+        let instrument_tunings = match tuning::instrument_table::read_instrument_tunings(emulator.spc_file()) {
+            Some((driver, instrument_tunings)) => {
+                println!("Detected {}, read tunings for {} sources", driver, instrument_tunings.len());
+                instrument_tunings
+            },
+            None => InstrumentTunings::new()
+        };
+
         let sample_detector = Arc::new(Mutex::new(SampleDetector::new()));
         emulator.set_filter_enabled(false);
         emulator.set_state_receiver(Some(sample_detector.clone()));
@@ -220,7 +362,9 @@ impl SampleProcessor {
             sample_data: HashMap::new(),
             sample_detector,
             detected_sources: HashMap::new(),
-            processing_queue: VecDeque::new()
+            processing_queue: VecDeque::new(),
+            instrument_tunings,
+            use_cache: true
         })
     }
 
@@ -228,6 +372,14 @@ impl SampleProcessor {
         self.emulator.load_script700(script700_path)
     }
 
+    pub fn set_port_timeline(&mut self, port_timeline: PortTimeline) {
+        self.emulator.set_port_timeline(port_timeline);
+    }
+
+    pub fn set_cache_enabled(&mut self, use_cache: bool) {
+        self.use_cache = use_cache;
+    }
+
     pub fn set_frame_count(&mut self, frame_count: usize) {
         self.total_frames = frame_count;
     }
@@ -278,9 +430,18 @@ impl SampleProcessor {
         } else {
             // Process detected samples
             if let Some((source, sample)) = self.processing_queue.pop_front() {
-                let sample_count = (self.detected_sources.get(&source).cloned().unwrap_or(60000) + 2000) * 16;
-                println!("Processing sample ${:x} for {} samples...", source, sample_count);
-                let sample_data = SampleData::new(sample, sample_count)?;
+                let mut sample_data = if self.use_cache {
+                    println!("Processing sample ${:x}...", source);
+                    SampleData::new_cached(sample)?
+                } else {
+                    let sample_count = (self.detected_sources.get(&source).cloned().unwrap_or(60000) + 2000) * 16;
+                    println!("Processing sample ${:x} for {} samples...", source, sample_count);
+                    SampleData::new(sample, sample_count)?
+                };
+                if let Some(&(tuning, subtuning)) = self.instrument_tunings.get(&source) {
+                    println!("Using instrument table tuning ${:02x}.{:02x} for sample ${:x}", tuning, subtuning, source);
+                    sample_data.set_instrument_tuning(tuning, subtuning);
+                }
                 self.sample_data.insert(source, sample_data);
                 self.current_sample += 1;
             }
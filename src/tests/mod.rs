@@ -0,0 +1,152 @@
+use std::fs;
+use std::path::PathBuf;
+use spc_spcp::spc::{Spc, IPL_ROM_LEN, RAM_LEN, REG_LEN};
+use crate::emulator::{PortTimeline, source_directory_range};
+use crate::tuning::instrument_table::read_instrument_tunings;
+
+fn write_temp_file(name: &str, contents: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("spc-presenter-{}-{}", std::process::id(), name));
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+fn blank_spc() -> Spc {
+    Spc {
+        version_minor: 30,
+        pc: 0x0200,
+        a: 0,
+        x: 0,
+        y: 0,
+        psw: 0x02,
+        sp: 0xef,
+        id666_tag: None,
+        extended_id666: None,
+        ram: [0; RAM_LEN],
+        regs: [0; REG_LEN],
+        ipl_rom: [0; IPL_ROM_LEN]
+    }
+}
+
+// An N-SPC song with four sources in a directory at $0200 and its instrument table at $3d00
+fn nspc_spc(instruments: &[[u8; 6]]) -> Spc {
+    let mut spc = blank_spc();
+    spc.regs[0x5d] = 0x02;
+    for source in 0..4 {
+        let address = (0x0210 + source * 9) as u16;
+        spc.ram[(0x0200 + source * 4)..(0x0204 + source * 4)].copy_from_slice(&[address.to_le_bytes(), address.to_le_bytes()].concat());
+        spc.ram[address as usize] = 0x03;
+    }
+
+    spc.ram[0x0800..0x080c].copy_from_slice(&[0x8d, 0x06, 0xcf, 0xda, 0x10, 0x60, 0x98, 0x00, 0x10, 0x98, 0x3d, 0x11]);
+    spc.ram[0x0a00..0x0a1b].copy_from_slice(&[1, 1, 2, 3, 0, 1, 2, 1, 2, 1, 1, 3, 0, 1, 2, 3, 1, 3, 3, 0, 1, 3, 0, 3, 3, 3, 1]);
+    for (i, instrument) in instruments.iter().enumerate() {
+        spc.ram[(0x3d00 + i * 6)..(0x3d06 + i * 6)].copy_from_slice(instrument);
+    }
+    spc
+}
+
+#[test]
+fn source_directory_ends_at_brr_data() {
+    assert_eq!(source_directory_range(&nspc_spc(&[])), 0x0200..0x0210);
+
+    // An entry pointing back into the directory ends it
+    let mut spc = nspc_spc(&[]);
+    spc.ram[0x0208..0x020c].copy_from_slice(&[0x04, 0x02, 0x04, 0x02]);
+    assert_eq!(source_directory_range(&spc), 0x0200..0x0208);
+}
+
+#[test]
+fn instrument_tunings_stop_at_first_invalid_entry() {
+    let instruments = [
+        [0x00, 0x8f, 0xe0, 0x00, 0x03, 0x00],
+        [0x01, 0x00, 0x00, 0x7f, 0x04, 0x80],
+        // AddMusicK noise instrument
+        [0x80, 0x8f, 0xe0, 0x00, 0x00, 0x00],
+        // Sources used with different tunings are left out
+        [0x02, 0x8f, 0xe0, 0x00, 0x05, 0x00],
+        [0x02, 0x8f, 0xe0, 0x00, 0x06, 0x00],
+        [0x03, 0x8f, 0xe0, 0x00, 0x02, 0x00],
+        // Past the end of the table
+        [0x09, 0x8f, 0xe0, 0x00, 0x03, 0x00],
+        [0x01, 0x8f, 0xe0, 0x00, 0x07, 0x00]
+    ];
+    let (_, tunings) = read_instrument_tunings(&nspc_spc(&instruments)).unwrap();
+    let mut tunings: Vec<_> = tunings.into_iter().collect();
+    tunings.sort();
+    assert_eq!(tunings, vec![(0x00, (0x03, 0x00)), (0x01, (0x04, 0x80)), (0x03, (0x02, 0x00))]);
+
+    // A silent envelope or a tuning multiplier of 0 also ends the table
+    for invalid in [[0x03, 0x00, 0x00, 0x00, 0x02, 0x00], [0x03, 0x8f, 0xe0, 0x00, 0x00, 0x80]] {
+        let (_, tunings) = read_instrument_tunings(&nspc_spc(&[instruments[0], invalid, instruments[1]])).unwrap();
+        assert_eq!(tunings.into_iter().collect::<Vec<_>>(), vec![(0x00, (0x03, 0x00))]);
+    }
+}
+
+#[test]
+fn port_timeline_load() {
+    let toml_path = write_temp_file("timeline.toml", r#"
+        [[events]]
+        frame = 120
+        port0 = 0x02
+
+        [[events]]
+        frame = 60
+        port0 = 0x12
+        port1 = 0x01
+
+        [[events]]
+        frame = 60
+        port2 = 0x34
+    "#);
+    let json_path = write_temp_file("timeline.json", r#"
+        { "events": [{ "frame": 30, "port3": 255 }] }
+    "#);
+    let invalid_path = write_temp_file("invalid.toml", r#"
+        [[events]]
+        frame = 1
+        port4 = 0
+    "#);
+
+    let timeline = PortTimeline::load(&toml_path);
+    let json_timeline = PortTimeline::load(&json_path);
+    let invalid_timeline = PortTimeline::load(&invalid_path);
+    for path in [toml_path, json_path, invalid_path] {
+        fs::remove_file(path).unwrap();
+    }
+
+    // Sorted by frame, with events on the same frame in file order
+    let timeline = timeline.unwrap();
+    let events: Vec<_> = timeline.events.iter()
+        .map(|event| (event.frame, event.writes().collect::<Vec<_>>()))
+        .collect();
+    assert_eq!(events, vec![
+        (60, vec![(0, 0x12), (1, 0x01)]),
+        (60, vec![(2, 0x34)]),
+        (120, vec![(0, 0x02)])
+    ]);
+
+    let json_timeline = json_timeline.unwrap();
+    assert_eq!(json_timeline.events.len(), 1);
+    assert_eq!(json_timeline.events[0].writes().collect::<Vec<_>>(), vec![(3, 0xff)]);
+
+    assert!(invalid_timeline.is_err());
+}
+
+#[test]
+fn port_timeline_position_at() {
+    let timeline: PortTimeline = toml::from_str(r#"
+        [[events]]
+        frame = 10
+        [[events]]
+        frame = 10
+        [[events]]
+        frame = 20
+    "#).unwrap();
+
+    assert_eq!(timeline.position_at(0), 0);
+    assert_eq!(timeline.position_at(10), 0);
+    assert_eq!(timeline.position_at(11), 2);
+    assert_eq!(timeline.position_at(20), 2);
+    assert_eq!(timeline.position_at(21), 3);
+    assert_eq!(PortTimeline::default().position_at(0), 0);
+}
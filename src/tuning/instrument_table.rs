@@ -0,0 +1,56 @@
+use std::collections::HashMap;
+use spc_spcp::nspc::NspcDriver;
+use spc_spcp::spc::Spc;
+use crate::emulator::source_directory_range;
+
+// srcn, ADSR1, ADSR2, GAIN, tuning multiplier, tuning submultiplier
+const INSTRUMENT_LEN: usize = 6;
+// The table has no terminator, so read until the first entry that can't be an instrument, up to
+// this many entries
+const MAX_INSTRUMENTS: usize = 64;
+// AddMusicK uses sources with the high bit set for noise instruments
+const NOISE_SOURCE_FLAG: u8 = 0x80;
+const ADSR_ENABLE_FLAG: u8 = 0x80;
+
+// Real instruments use a source within the directory, a tuning multiplier of at least 1, and an
+// envelope that isn't silenced by a direct GAIN of 0
+fn is_plausible_instrument(instrument: &[u8], source_count: usize) -> bool {
+    let (source, adsr1, gain, tuning) = (instrument[0], instrument[1], instrument[3], instrument[4]);
+    if (source & NOISE_SOURCE_FLAG) != 0 {
+        return true;
+    }
+    (source as usize) < source_count && tuning != 0 && ((adsr1 & ADSR_ENABLE_FLAG) != 0 || gain != 0)
+}
+
+/// Tuning multiplier and submultiplier for each source.
+pub type InstrumentTunings = HashMap<u8, (u8, u8)>;
+
+/// Reads the tuning multiplier and submultiplier for each source from the instrument table of
+/// an N-SPC-family driver. Sources used by several instruments with different tunings are left
+/// out, since there is no single tuning to use for them.
+pub fn read_instrument_tunings(spc: &Spc) -> Option<(NspcDriver, InstrumentTunings)> {
+    let driver = spc.nspc_driver()?;
+    let instrument_table = driver.instrument_table? as usize;
+    let source_count = source_directory_range(spc).len() / 4;
+
+    let mut tunings: HashMap<u8, Option<(u8, u8)>> = HashMap::new();
+    for instrument in spc.ram[instrument_table..].chunks_exact(INSTRUMENT_LEN).take(MAX_INSTRUMENTS) {
+        if !is_plausible_instrument(instrument, source_count) {
+            break;
+        }
+
+        let (source, tuning) = (instrument[0], (instrument[4], instrument[5]));
+        if (source & NOISE_SOURCE_FLAG) != 0 {
+            continue;
+        }
+
+        tunings.entry(source)
+            .and_modify(|existing| if *existing != Some(tuning) { *existing = None; })
+            .or_insert(Some(tuning));
+    }
+
+    let tunings = tunings.into_iter()
+        .filter_map(|(source, tuning)| Some((source, tuning?)))
+        .collect();
+    Some((driver, tunings))
+}
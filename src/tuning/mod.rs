@@ -1 +1,8 @@
-pub mod super_midi_pak_session;
\ No newline at end of file
+pub mod super_midi_pak_session;
+pub mod instrument_table;
+pub mod tuning_file;
+
+/// Base frequency of a sample tuned with an N-SPC/AddMusicK tuning multiplier and submultiplier.
+pub fn amk_tuning_frequency(tuning: u8, subtuning: u8) -> f64 {
+    32000.0 / (16.0 * (tuning as f64 + subtuning as f64 / 256.0))
+}
@@ -0,0 +1,166 @@
+use anyhow::{Context, Result, bail};
+use std::fs;
+use std::path::Path;
+use serde::{Serialize, Deserialize};
+use csscolorparser::Color as CssColor;
+use tiny_skia::Color;
+use crate::emulator::BrrSample;
+use crate::sample_processing::brr_hash;
+
+pub const TUNING_FILE_VERSION: u32 = 1;
+
+/// Tuning for one sample. Entries with a BRR hash match that sample wherever it is in the
+/// source directory; entries without one match by source index.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct SampleTuningEntry {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub brr_hash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Fundamental frequency in Hz at pitch $1000
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency: Option<f64>,
+    /// AddMusicK tuning multiplier and submultiplier
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub amk: Option<(u8, u8)>,
+    /// Octaves to shift the detected pitch by, for samples without a frequency or AMK tuning
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub octave_offset: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub percussion: bool
+}
+
+impl SampleTuningEntry {
+    fn validate(&self) -> Result<()> {
+        if self.source.is_none() && self.brr_hash.is_none() {
+            bail!("needs a source index or a BRR hash");
+        }
+        if self.frequency.is_some() && self.amk.is_some() {
+            bail!("has both a frequency and an AddMusicK tuning");
+        }
+        if let Some(hash) = &self.brr_hash {
+            u64::from_str_radix(hash, 16).with_context(|| format!("invalid BRR hash '{}'", hash))?;
+        }
+        if let Some(color) = &self.color {
+            color.parse::<CssColor>().with_context(|| format!("invalid color '{}'", color))?;
+        }
+        Ok(())
+    }
+
+    fn brr_hash_value(&self) -> Option<u64> {
+        u64::from_str_radix(self.brr_hash.as_ref()?, 16).ok()
+    }
+
+    /// The sample's frequency from either the frequency or the AddMusicK tuning.
+    pub fn pitch(&self) -> Option<f64> {
+        self.frequency
+            .or_else(|| self.amk.map(|(tuning, subtuning)| super::amk_tuning_frequency(tuning, subtuning)))
+    }
+
+    pub fn color(&self) -> Option<Color> {
+        let color = self.color.as_ref()?.parse::<CssColor>().ok()?;
+        Color::from_rgba(color.r as f32, color.g as f32, color.b as f32, color.a as f32)
+    }
+}
+
+#[derive(Deserialize)]
+struct VersionHeader {
+    version: u32
+}
+
+/// A shareable set of sample tunings, stored as TOML or as JSON.
+///
+/// ```toml
+/// version = 1
+///
+/// [[samples]]
+/// source = 0x05
+/// brr_hash = "9e3779b97f4a7c15"
+/// name = "Strings"
+/// amk = [0x03, 0x32]
+/// color = "#ff9800"
+/// ```
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct TuningFile {
+    pub version: u32,
+    #[serde(default)]
+    pub samples: Vec<SampleTuningEntry>
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("json"))
+}
+
+impl TuningFile {
+    pub fn new(samples: Vec<SampleTuningEntry>) -> Self {
+        Self {
+            version: TUNING_FILE_VERSION,
+            samples
+        }
+    }
+
+    pub fn from_toml(data: &str) -> Result<Self> {
+        let header: VersionHeader = toml::from_str(data)?;
+        Self::check_version(header.version)?;
+        Self::validated(toml::from_str(data)?)
+    }
+
+    pub fn from_json(data: &str) -> Result<Self> {
+        let header: VersionHeader = serde_json::from_str(data)?;
+        Self::check_version(header.version)?;
+        Self::validated(serde_json::from_str(data)?)
+    }
+
+    /// Loads a tuning file from TOML, or from JSON if the extension is `.json`.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let data = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read tuning file '{}'", path.display()))?;
+
+        if is_json(path) {
+            Self::from_json(&data)
+        } else {
+            Self::from_toml(&data)
+        }
+    }
+
+    /// Saves the tuning file as TOML, or as JSON if the extension is `.json`.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let data = if is_json(path.as_ref()) {
+            serde_json::to_string_pretty(self)?
+        } else {
+            toml::to_string(self)?
+        };
+        fs::write(path, data)?;
+        Ok(())
+    }
+
+    fn check_version(version: u32) -> Result<()> {
+        if version == 0 || version > TUNING_FILE_VERSION {
+            bail!("Unsupported tuning file version {} (expected {} or lower)", version, TUNING_FILE_VERSION);
+        }
+        Ok(())
+    }
+
+    fn validated(self) -> Result<Self> {
+        for (i, entry) in self.samples.iter().enumerate() {
+            entry.validate().with_context(|| format!("Invalid tuning file entry {}", i + 1))?;
+        }
+        Ok(self)
+    }
+
+    /// The entry for a sample, preferring one that matches its BRR data over one that matches
+    /// its source index.
+    pub fn find(&self, source: u8, sample: &BrrSample) -> Option<&SampleTuningEntry> {
+        let hash = brr_hash(sample);
+        self.samples.iter()
+            .find(|entry| entry.brr_hash_value() == Some(hash))
+            .or_else(|| self.samples.iter().find(|entry| entry.brr_hash.is_none() && entry.source == Some(source)))
+    }
+}
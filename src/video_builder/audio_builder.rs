@@ -0,0 +1,130 @@
+use anyhow::{Result, Context};
+use std::collections::VecDeque;
+use std::path::Path;
+use ffmpeg_next::{format, encoder, frame, ChannelLayout, Packet, Rational};
+use ffmpeg_next::format::sample::Type;
+use super::as_u8_slice;
+use super::vb_unwrap::VideoBuilderUnwrap;
+use super::ffmpeg_hacks::{ffmpeg_copy_codec_params, ffmpeg_copy_context_params, ffmpeg_create_context, ffmpeg_get_audio_context_frame_size};
+
+const SAMPLE_FORMAT: format::Sample = format::Sample::I16(Type::Packed);
+
+pub struct AudioBuilder {
+    out_ctx: format::context::Output,
+    encoder: encoder::Audio,
+    stream_idx: usize,
+    time_base: Rational,
+    sample_rate: i32,
+    frame_size: usize,
+    sample_buffer: VecDeque<i16>,
+    pts: i64
+}
+
+impl AudioBuilder {
+    pub fn new<P: AsRef<Path>>(output_path: P, sample_rate: i32) -> Result<Self> {
+        let mut out_ctx = format::output(&output_path).vb_unwrap()?;
+
+        let codec_name = match output_path.as_ref().extension().and_then(|e| e.to_str()) {
+            Some("flac") => "flac",
+            _ => "pcm_s16le"
+        };
+        let codec = encoder::find_by_name(codec_name)
+            .with_context(|| format!("Unknown codec {}", codec_name))?;
+        let time_base = Rational::new(1, sample_rate);
+
+        let (encoder, stream_idx) = {
+            let mut stream = out_ctx.add_stream(codec).vb_unwrap()?;
+            let mut context = ffmpeg_create_context(codec, stream.parameters())?
+                .encoder()
+                .audio()
+                .vb_unwrap()?;
+
+            context.set_rate(sample_rate);
+            context.set_format(SAMPLE_FORMAT);
+            context.set_channels(2);
+            context.set_channel_layout(ChannelLayout::STEREO);
+            context.set_time_base(time_base);
+
+            ffmpeg_copy_codec_params(&mut stream, &context, &codec)?;
+
+            stream.set_time_base(time_base);
+
+            let encoder = context.open_as(codec).vb_unwrap()?;
+            ffmpeg_copy_context_params(&mut stream, encoder.as_ref())?;
+
+            (encoder, stream.index())
+        };
+
+        let frame_size = ffmpeg_get_audio_context_frame_size(encoder.as_ref(), 1024);
+
+        Ok(Self {
+            out_ctx,
+            encoder,
+            stream_idx,
+            time_base,
+            sample_rate,
+            frame_size,
+            sample_buffer: VecDeque::new(),
+            pts: 0
+        })
+    }
+
+    pub fn start_encoding(&mut self) -> Result<()> {
+        self.out_ctx.write_header().vb_unwrap()
+    }
+
+    pub fn push_audio_data(&mut self, audio: &[i16]) -> Result<()> {
+        self.sample_buffer.extend(audio.iter());
+
+        while self.sample_buffer.len() >= self.frame_size * 2 {
+            let samples: Vec<i16> = self.sample_buffer.drain(0..(self.frame_size * 2)).collect();
+            self.send_frame(&samples)?;
+        }
+
+        Ok(())
+    }
+
+    fn send_frame(&mut self, samples: &[i16]) -> Result<()> {
+        let sample_count = samples.len() / 2;
+        let data = as_u8_slice(samples);
+
+        let mut frame = frame::Audio::new(SAMPLE_FORMAT, sample_count, ChannelLayout::STEREO);
+        frame.set_rate(self.sample_rate as _);
+        frame.data_mut(0)[..data.len()].copy_from_slice(data);
+        frame.set_pts(Some(self.pts));
+        self.encoder.send_frame(&frame).vb_unwrap()?;
+
+        self.pts += sample_count as i64;
+
+        self.mux_packets()
+    }
+
+    fn mux_packets(&mut self) -> Result<()> {
+        let mut packet = Packet::empty();
+        while self.encoder.receive_packet(&mut packet).is_ok() {
+            let out_time_base = self.out_ctx.stream(self.stream_idx)
+                .unwrap()
+                .time_base();
+
+            packet.rescale_ts(self.time_base, out_time_base);
+            packet.set_stream(self.stream_idx);
+            packet.write_interleaved(&mut self.out_ctx).vb_unwrap()?;
+        }
+
+        Ok(())
+    }
+
+    pub fn finish_encoding(&mut self) -> Result<()> {
+        if !self.sample_buffer.is_empty() {
+            let samples: Vec<i16> = self.sample_buffer.drain(..).collect();
+            self.send_frame(&samples)?;
+        }
+
+        self.encoder.send_eof().vb_unwrap()?;
+        self.mux_packets()?;
+
+        self.out_ctx.write_trailer().vb_unwrap()?;
+
+        Ok(())
+    }
+}
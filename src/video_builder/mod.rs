@@ -1,4 +1,5 @@
 pub mod video_options;
+pub mod audio_builder;
 mod vb_unwrap;
 mod ffmpeg_hacks;
 mod encoding;
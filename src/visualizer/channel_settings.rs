@@ -5,11 +5,11 @@ use serde::{Serialize, Serializer, Deserialize, Deserializer};
 use super::ChannelState;
 
 #[derive(Clone)]
-pub struct ChannelSettings(String, String, bool, Vec<Color>);
+pub struct ChannelSettings(String, String, bool, Vec<Color>, bool, bool);
 
 impl ChannelSettings {
     pub fn new(chip: &str, name: &str, colors: &[Color]) -> Self {
-        Self(chip.to_string(), name.to_string(), false, colors.to_vec())
+        Self(chip.to_string(), name.to_string(), false, colors.to_vec(), false, false)
     }
 
     pub fn chip(&self) -> String {
@@ -24,6 +24,14 @@ impl ChannelSettings {
         self.2
     }
 
+    pub fn muted(&self) -> bool {
+        self.4
+    }
+
+    pub fn solo(&self) -> bool {
+        self.5
+    }
+
     pub fn color(&self, state: &ChannelState) -> Option<Color> {
         let color_index = match self.3.len() {
             0 => state.timbre,
@@ -56,6 +64,14 @@ impl ChannelSettings {
         self.2 = hidden;
     }
 
+    pub fn set_muted(&mut self, muted: bool) {
+        self.4 = muted;
+    }
+
+    pub fn set_solo(&mut self, solo: bool) {
+        self.5 = solo;
+    }
+
     pub fn set_colors(&mut self, colors: &[Color]) {
         self.3 = colors.to_vec();
     }
@@ -91,6 +107,12 @@ impl ChannelSettingsManager {
             .find(|settings| settings.chip().as_str() == chip && settings.name().as_str() == channel)
     }
 
+    pub fn voice_masks(&self) -> (u8, u8) {
+        self.0.iter().enumerate().fold((0, 0), |(mute_mask, solo_mask), (channel, settings)| {
+            (mute_mask | ((settings.muted() as u8) << channel), solo_mask | ((settings.solo() as u8) << channel))
+        })
+    }
+
     pub fn put_per_sample_colors(&mut self, sample_colors: HashMap<u8, Color>) {
         for settings in self.0.iter_mut() {
             let base_color = settings.colors().first().cloned().unwrap_or(Color::from_rgba8(0xFF, 0xA0, 0xA0, 0xFF));
@@ -140,6 +162,8 @@ impl Default for ChannelSettingsManager {
 #[serde(default)]
 struct PianoRollChannelConfig {
     pub hidden: bool,
+    pub muted: bool,
+    pub solo: bool,
     #[serde(flatten)]
     pub colors: BTreeMap<String, CssColor>
 }
@@ -150,6 +174,8 @@ impl Serialize for ChannelSettingsManager {
         for channel_settings in self.0.iter() {
             let config = PianoRollChannelConfig {
                 hidden: channel_settings.hidden(),
+                muted: channel_settings.muted(),
+                solo: channel_settings.solo(),
                 colors: BTreeMap::from_iter(
                     channel_settings.colors()
                         .iter()
@@ -199,6 +225,8 @@ impl<'de> Deserialize<'de> for ChannelSettingsManager {
                     }
                     settings.set_colors(&colors);
                     settings.set_hidden(channel_settings.hidden);
+                    settings.set_muted(channel_settings.muted);
+                    settings.set_solo(channel_settings.solo);
                 }
             }
         }
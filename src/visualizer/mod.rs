@@ -152,6 +152,8 @@ impl ApuStateReceiver for Visualizer {
 
         let frequency = match state.noise_clock {
             Some(t) => C_0 * (t as f64 / 12.0).exp2(),
+            // Percussion stays at the sample's own pitch, so each drum keeps to one row
+            None if sample_data.percussion() => source_pitch,
             None => source_pitch * state.pitch as f64 / 0x1000 as f64
         }.max(f64::EPSILON);
 